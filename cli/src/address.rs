@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, io, path::Path};
+use videohub_proto::protocol::{HubInfo, Label};
+use videohub_server_api_def::defs::Numbering;
+
+/// How close to a label a name has to be for the label to be suggested, from 0 to 1
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Side {
+    #[clap(alias = "in")]
+    Input,
+    #[clap(alias = "out")]
+    Output,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Input => "input",
+            Side::Output => "output",
+        }
+    }
+}
+
+/// Names given to the ports, in addition to their labels. The ports are numbered as `--numbering`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Aliases {
+    pub inputs: BTreeMap<String, usize>,
+    pub outputs: BTreeMap<String, usize>,
+}
+
+impl Aliases {
+    /// Read a YAML file of aliases, there are none when the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("Invalid aliases file {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn get(&self, side: Side) -> &BTreeMap<String, usize> {
+        match side {
+            Side::Input => &self.inputs,
+            Side::Output => &self.outputs,
+        }
+    }
+}
+
+/// Finds the ports of a videohub from their number, label, label glob or alias.
+///
+/// The numbers and the aliases are given in the numbering of the users, the ports found are
+/// numbered as the protocol does.
+pub struct Ports<'a> {
+    hub_info: &'a HubInfo,
+    aliases: &'a Aliases,
+    numbering: Numbering,
+}
+
+impl<'a> Ports<'a> {
+    pub fn new(hub_info: &'a HubInfo, aliases: &'a Aliases, numbering: Numbering) -> Self {
+        Self {
+            hub_info,
+            aliases,
+            numbering,
+        }
+    }
+
+    /// The port of this name, an error when several ports match it.
+    pub fn one(&self, side: Side, name: &str) -> Result<usize> {
+        match self.all(side, name)?.as_slice() {
+            [port] => Ok(*port),
+            ports => Err(anyhow!(
+                "'{name}' matches several {}s: {}. Use the number of one of them",
+                side.name(),
+                self.describe(side, ports)
+            )),
+        }
+    }
+
+    /// The ports of this name, a glob like `Studio * PGM` can match several of them.
+    pub fn all(&self, side: Side, name: &str) -> Result<Vec<usize>> {
+        let labels = self.labels(side);
+        if let Ok(number) = name.parse::<usize>() {
+            return self.check(side, number).map(|port| vec![port]);
+        }
+        if let Some(number) = self.aliases.get(side).get(name) {
+            return self.check(side, *number).map(|port| vec![port]);
+        }
+
+        // The exact label first, then ignoring the case
+        let find = |matches: &dyn Fn(&str) -> bool| -> Vec<usize> {
+            labels
+                .iter()
+                .filter(|Label(_, label)| matches(label))
+                .map(|Label(port, _)| *port)
+                .collect()
+        };
+        let mut ports = find(&|label| label == name);
+        if ports.is_empty() {
+            ports = find(&|label| label.eq_ignore_ascii_case(name));
+        }
+        if ports.is_empty() && name.contains(['*', '?', '[']) {
+            let pattern = Pattern::new(name).with_context(|| format!("Invalid glob '{name}'"))?;
+            let options = MatchOptions {
+                case_sensitive: false,
+                ..MatchOptions::new()
+            };
+            ports = find(&|label| pattern.matches_with(label, options));
+        }
+        if ports.is_empty() {
+            return Err(anyhow!(
+                "No {} named '{name}'{}",
+                side.name(),
+                self.suggestions(side, name)
+            ));
+        }
+        Ok(ports)
+    }
+
+    fn labels(&self, side: Side) -> &[Label] {
+        match side {
+            Side::Input => &self.hub_info.input_labels,
+            Side::Output => &self.hub_info.output_labels,
+        }
+    }
+
+    // The port of a number given by a user
+    fn check(&self, side: Side, number: usize) -> Result<usize> {
+        let nb_ports = self.labels(side).len();
+        let port = crate::from_user(self.numbering, number)?;
+        if port >= nb_ports {
+            return Err(anyhow!(
+                "Unknown {side} port {number}, the videohub has {nb_ports} {side}s numbered from {first}",
+                side = side.name(),
+                first = self.numbering.first()
+            ));
+        }
+        Ok(port)
+    }
+
+    // The ports with their labels: 1 'CAM 2', 4 'CAM 2'
+    fn describe(&self, side: Side, ports: &[usize]) -> String {
+        let labels = self.labels(side);
+        ports
+            .iter()
+            .map(|port| {
+                let label = labels
+                    .iter()
+                    .find(|Label(id, _)| id == port)
+                    .map(|Label(_, label)| label.as_str())
+                    .unwrap_or_default();
+                format!("{} '{label}'", self.numbering.to_user(*port))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // The labels and aliases which look like the name
+    fn suggestions(&self, side: Side, name: &str) -> String {
+        let name = name.to_lowercase();
+        let labels = self
+            .labels(side)
+            .iter()
+            .map(|Label(port, label)| (label.as_str(), self.numbering.to_user(*port)));
+        let aliases = self
+            .aliases
+            .get(side)
+            .iter()
+            .map(|(alias, port)| (alias.as_str(), *port));
+        let mut candidates: Vec<(f64, &str, usize)> = labels
+            .chain(aliases)
+            .map(|(candidate, port)| {
+                let score = strsim::jaro_winkler(&name, &candidate.to_lowercase());
+                (score, candidate, port)
+            })
+            .filter(|(score, _, _)| *score >= SUGGESTION_THRESHOLD)
+            .collect();
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let suggestions: Vec<String> = candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate, port)| format!("'{candidate}' ({port})"))
+            .collect();
+        if suggestions.is_empty() {
+            String::new()
+        } else {
+            format!(". Did you mean {}?", suggestions.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hub_info() -> HubInfo {
+        let labels = |names: &[&str]| {
+            names
+                .iter()
+                .enumerate()
+                .map(|(port, name)| Label(port, name.to_string()))
+                .collect()
+        };
+        let mut hub_info = HubInfo::default();
+        hub_info.input_labels = labels(&["CAM 1", "CAM 2", "Graphics", "CAM 2"]);
+        hub_info.output_labels = labels(&["Studio A PGM", "Studio B PGM", "Monitor"]);
+        hub_info
+    }
+
+    #[test]
+    fn test_ports() {
+        let hub_info = hub_info();
+        let aliases = Aliases {
+            inputs: BTreeMap::from([("gfx".to_string(), 3)]),
+            outputs: BTreeMap::new(),
+        };
+        let ports = Ports::new(&hub_info, &aliases, Numbering::OneBased);
+
+        assert_eq!(ports.one(Side::Input, "1").unwrap(), 0);
+        assert_eq!(ports.one(Side::Input, "gfx").unwrap(), 2);
+        assert_eq!(ports.one(Side::Input, "cam 1").unwrap(), 0);
+        assert_eq!(ports.all(Side::Output, "studio * pgm").unwrap(), [0, 1]);
+        assert!(ports.one(Side::Input, "0").is_err());
+        assert!(ports.one(Side::Output, "4").is_err());
+        assert!(ports.all(Side::Output, "Studio [A").is_err());
+    }
+
+    #[test]
+    fn test_ports_ambiguous() {
+        let hub_info = hub_info();
+        let aliases = Aliases::default();
+        let ports = Ports::new(&hub_info, &aliases, Numbering::ZeroBased);
+
+        assert_eq!(ports.all(Side::Input, "CAM 2").unwrap(), [1, 3]);
+        let err = ports.one(Side::Input, "CAM 2").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "'CAM 2' matches several inputs: 1 'CAM 2', 3 'CAM 2'. Use the number of one of them"
+        );
+        assert!(ports.one(Side::Output, "Studio*").is_err());
+    }
+
+    #[test]
+    fn test_ports_suggestions() {
+        let hub_info = hub_info();
+        let aliases = Aliases::default();
+        let ports = Ports::new(&hub_info, &aliases, Numbering::OneBased);
+
+        let err = ports
+            .one(Side::Output, "Studio A PG")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("No output named 'Studio A PG'. Did you mean 'Studio A PGM' (1)"));
+        let err = ports
+            .one(Side::Input, "Teleprompter")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No input named 'Teleprompter'");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::format::{print_differences, Format};
+use anyhow::{anyhow, Context, Result};
+use futures::executor::block_on;
+use log::info;
+use std::{fs, path::Path};
+use videohub_proto::Hub;
+use videohub_server_api_def::defs::{Backup, Numbering};
+
+/// The ports of the backup are numbered as `numbering`, which is saved with them.
+pub fn backup(
+    videohub: &Hub,
+    path: Option<&Path>,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    let hub_info = block_on(videohub.read())?;
+    let backup = Backup::from(hub_info).numbered(numbering);
+    match path {
+        Some(path) => {
+            let content = if is_yaml(path) {
+                serde_yaml::to_string(&backup)?
+            } else {
+                serde_json::to_string_pretty(&backup)?
+            };
+            info!("Saving the backup to {}", path.display());
+            fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+        }
+        None if format == Format::Yaml => {
+            print!("{}", serde_yaml::to_string(&backup)?);
+            Ok(())
+        }
+        None => {
+            println!("{}", serde_json::to_string_pretty(&backup)?);
+            Ok(())
+        }
+    }
+}
+
+/// Restore a backup whatever the numbering of its ports.
+pub fn restore(
+    videohub: &Hub,
+    path: &Path,
+    dry_run: bool,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let backup: Backup = if is_yaml(path) {
+        serde_yaml::from_str(&content)?
+    } else {
+        serde_json::from_str(&content)?
+    };
+    backup.check_version().map_err(|message| anyhow!(message))?;
+    let backup = backup.into_protocol().map_err(|message| anyhow!(message))?;
+
+    let hub_info = block_on(videohub.read())?;
+    print_differences(format, &numbering.to_user(backup.differences(&hub_info)))?;
+    let blocks = backup.blocks(&hub_info);
+    crate::locks::check_routes(&hub_info, &blocks, numbering)?;
+    if !dry_run {
+        info!("Restoring the backup {}", path.display());
+        for block in blocks {
+            block_on(videohub.write(block))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml")
+}
@@ -0,0 +1,180 @@
+use futures::executor::block_on;
+use std::time::{Duration, Instant};
+use videohub_proto::{
+    hub,
+    protocol::{DevicePresent, HubInfo},
+    Hub,
+};
+
+/// The state of a check, its exit code as a Nagios plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl State {
+    fn label(self) -> &'static str {
+        match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// How long the videohub may take to send its state
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub warning: Duration,
+    pub critical: Duration,
+    /// Given up after it, the check is critical
+    pub timeout: Duration,
+}
+
+/// Read the state of the videohub and print a Nagios plugin line: how long it took and the
+/// problems it reports. The returned state is the exit code.
+pub fn run(videohub: &Hub, thresholds: Thresholds) -> State {
+    let started_at = Instant::now();
+    let (state, message, latency) = match block_on(videohub.read_timeout(thresholds.timeout)) {
+        Ok(hub_info) => {
+            let latency = started_at.elapsed();
+            let (state, message) = assess(&hub_info, latency, thresholds);
+            (state, message, Some(latency))
+        }
+        Err(hub::Error::Timeout) => (
+            State::Critical,
+            format!(
+                "The videohub did not answer within {:.1} s",
+                thresholds.timeout.as_secs_f64()
+            ),
+            None,
+        ),
+        Err(err @ hub::Error::ConnectionError(_)) => (
+            State::Critical,
+            format!("{:#}", anyhow::Error::from(err)),
+            None,
+        ),
+        Err(err) => (
+            State::Unknown,
+            format!("{:#}", anyhow::Error::from(err)),
+            None,
+        ),
+    };
+
+    let mut line = format!("VIDEOHUB {} - {message}", state.label());
+    if let Some(latency) = latency {
+        // The performance data: 'label'=value[unit];warning;critical;min;max
+        line.push_str(&format!(
+            " | time={:.3}s;{:.3};{:.3};0;{:.3}",
+            latency.as_secs_f64(),
+            thresholds.warning.as_secs_f64(),
+            thresholds.critical.as_secs_f64(),
+            thresholds.timeout.as_secs_f64()
+        ));
+    }
+    println!("{line}");
+    state
+}
+
+// The worst state of the problems, with a summary of them
+fn assess(hub_info: &HubInfo, latency: Duration, thresholds: Thresholds) -> (State, String) {
+    let mut problems = vec![];
+    if latency > thresholds.critical {
+        problems.push((State::Critical, "slow answer".to_string()));
+    } else if latency > thresholds.warning {
+        problems.push((State::Warning, "slow answer".to_string()));
+    }
+    match hub_info.device_info.device_present {
+        DevicePresent::Present => {}
+        DevicePresent::NotPresent => {
+            problems.push((State::Critical, "the device is not present".to_string()))
+        }
+        DevicePresent::NeedUpdate => {
+            problems.push((State::Warning, "the device needs an update".to_string()))
+        }
+    }
+    for (name, status) in &hub_info.alarm_status {
+        if status != "OK" {
+            problems.push((State::Warning, format!("{name}: {status}")));
+        }
+    }
+
+    let state = problems
+        .iter()
+        .map(|(state, _)| *state)
+        .max()
+        .unwrap_or(State::Ok);
+    let mut message = format!(
+        "{} answered in {} ms",
+        hub_info.device_info.friendly_name,
+        latency.as_millis()
+    );
+    if !problems.is_empty() {
+        let problems: Vec<_> = problems.into_iter().map(|(_, problem)| problem).collect();
+        message.push_str(&format!(", {}", problems.join(", ")));
+    }
+    (state, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use videohub_proto::protocol::Alarms;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        warning: Duration::from_millis(500),
+        critical: Duration::from_secs(2),
+        timeout: Duration::from_secs(5),
+    };
+
+    fn hub_info(device_present: DevicePresent) -> HubInfo {
+        let mut hub_info = HubInfo::default();
+        hub_info.device_info.device_present = device_present;
+        hub_info.device_info.friendly_name = "Studio".to_string();
+        hub_info
+    }
+
+    #[test]
+    fn test_assess() {
+        let present = hub_info(DevicePresent::Present);
+        assert_eq!(
+            assess(&present, Duration::from_millis(20), THRESHOLDS),
+            (State::Ok, "Studio answered in 20 ms".to_string())
+        );
+        assert_eq!(
+            assess(&present, Duration::from_secs(1), THRESHOLDS),
+            (
+                State::Warning,
+                "Studio answered in 1000 ms, slow answer".to_string()
+            )
+        );
+        assert_eq!(
+            assess(&present, Duration::from_secs(3), THRESHOLDS).0,
+            State::Critical
+        );
+
+        let mut alarms = hub_info(DevicePresent::NeedUpdate);
+        alarms.alarm_status = Alarms::from([
+            ("Fan 1".to_string(), "OK".to_string()),
+            ("Fan 2".to_string(), "Failed".to_string()),
+        ]);
+        assert_eq!(
+            assess(&alarms, Duration::from_millis(20), THRESHOLDS),
+            (
+                State::Warning,
+                "Studio answered in 20 ms, the device needs an update, Fan 2: Failed".to_string()
+            )
+        );
+
+        // The worst problem gives the state
+        let absent = hub_info(DevicePresent::NotPresent);
+        assert_eq!(
+            assess(&absent, Duration::from_secs(1), THRESHOLDS).0,
+            State::Critical
+        );
+    }
+}
@@ -1,5 +1,7 @@
-use clap::Parser;
-use videohub_proto::protocol::{Label, Route};
+use crate::{address::Side, format::Format, view::View};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+use videohub_server_api_def::defs::Numbering;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -7,36 +9,340 @@ pub struct Cli {
     #[clap(
         short = 'i',
         long = "ip-address",
+        required_unless_present = "replay",
         help = "IPv4 Address of the videohub device. Ex: -i 10.0.0.1"
     )]
-    pub ip_address: String,
+    pub ip_address: Option<String>,
     #[clap(
         long,
-        help = "Change an output label. For example, to change the label of the port 1 to 'my new label' use: 1=\"my new label\" "
+        help = "Record all the bytes exchanged with the videohub, timestamped, to a capture file"
     )]
-    pub output_label: Option<Label>,
+    pub capture: Option<PathBuf>,
     #[clap(
         long,
-        help = "Change an input label. For example, to change the label of the port 2 to 'foo' use: 2=foo "
+        help = "Read the Videohub info from a capture file instead of connecting to a device"
     )]
-    pub input_label: Option<Label>,
+    pub replay: Option<PathBuf>,
     #[clap(
-        short = 'o',
-        long = "output-route",
-        help = "Route an Input to an Output: To route the input 15 to the output 40 write: 15=40 "
+        long,
+        requires = "replay",
+        help = "Serve the capture file to clients connecting to this local TCP port, as a videohub device would"
+    )]
+    pub replay_port: Option<u16>,
+    #[clap(
+        long,
+        value_enum,
+        global = true,
+        default_value = "table",
+        help = "How the data is printed, the structured formats use the field names of the web server API"
+    )]
+    pub format: Format,
+    #[clap(
+        long,
+        global = true,
+        default_value = "aliases.yaml",
+        help = "A YAML file naming ports, with `inputs` and `outputs` maps from a name to a port number"
+    )]
+    pub aliases: PathBuf,
+    #[clap(
+        long,
+        value_enum,
+        global = true,
+        env = "VIDEOHUB_NUMBERING",
+        default_value = "zero-based",
+        help = "How the ports are numbered, in what is printed and in the arguments. The videohub protocol numbers them from 0"
     )]
-    pub output_route: Option<Route>,
+    pub numbering: PortNumbering,
     #[clap(
-        short = 'd',
-        long = "display",
-        help = "Display Videohub info and current state",
-        action
+        long,
+        value_enum,
+        global = true,
+        default_value = "device-info",
+        help = "The built-in view displayed by info in the table format"
     )]
-    pub display: bool,
-    #[clap(short = 'u', long = "unlock", help = "Unlock a given port")]
-    pub unlock: Option<usize>,
-    #[clap(short = 'l', long = "lock", help = "Lock a given port")]
-    pub lock: Option<usize>,
+    pub view: View,
+    #[clap(
+        long,
+        global = true,
+        help = "A Tera template displayed by info in the table format, instead of a built-in view"
+    )]
+    pub template: Option<PathBuf>,
+    // `info` when not set
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Display the Videohub info and current state
+    Info,
+    /// List the input or output ports
+    #[clap(subcommand)]
+    Ports(PortsCommand),
+    /// Route inputs to outputs, all the routes are taken at once
+    Route {
+        #[clap(
+            required = true,
+            help = "OUTPUT=INPUT, or the outputs with --from. To route the input 15 to the output 40 write: 40=15. The ports are numbers, labels, label globs like 'Studio * PGM' or aliases"
+        )]
+        routes: Vec<String>,
+        #[clap(
+            long,
+            help = "The input routed to all the outputs. Ex: --from \"CAM 3\""
+        )]
+        from: Option<String>,
+        #[clap(
+            long,
+            help = "Review the routes before taking them, the default from a terminal when the videohub is in take mode"
+        )]
+        take: bool,
+    },
+    /// Change input or output labels
+    #[clap(subcommand)]
+    Label(LabelCommand),
+    /// Export or import the labels as a table of `port,label` rows, in CSV or TSV
+    #[clap(subcommand)]
+    Labels(LabelsCommand),
+    /// Lock outputs for this connection, unless another panel holds their lock
+    Lock {
+        #[clap(required = true, help = "Numbers, labels, label globs or aliases")]
+        outputs: Vec<String>,
+    },
+    /// Release the locks of this connection, the locks of an earlier run need --force
+    Unlock {
+        #[clap(required = true, help = "Numbers, labels, label globs or aliases")]
+        outputs: Vec<String>,
+        #[clap(long, help = "Also release the locks held by the other panels")]
+        force: bool,
+    },
+    /// Display the changes made on the videohub as they happen, as JSON lines with --format json
+    Watch {
+        #[clap(
+            long,
+            help = "Only the changes of these output ports, and the routes to them. Ex: --outputs 0-19,22"
+        )]
+        outputs: Option<PortList>,
+        #[clap(
+            long,
+            help = "Only the changes of these input ports, and the routes from them. Ex: --inputs 4-7"
+        )]
+        inputs: Option<PortList>,
+    },
+    /// Route from a full screen panel kept up to date with the videohub
+    Tui,
+    /// Run commands over a single connection, typed or from a script
+    Shell {
+        #[clap(
+            help = "A file of commands, one per line, - for the standard input. Typed at a prompt when not set"
+        )]
+        script: Option<PathBuf>,
+    },
+    /// Print the current state as the videohub sends it
+    Dump,
+    /// Check that the videohub answers in time and reports no problem, as a Nagios plugin. Exits
+    /// with 0 when OK, 1 on a warning, 2 when critical and 3 when unknown
+    Check {
+        #[clap(
+            short,
+            long,
+            default_value = "1",
+            help = "Seconds taken to send the state from which the check is a warning"
+        )]
+        warning: Seconds,
+        #[clap(
+            short,
+            long,
+            default_value = "3",
+            help = "Seconds taken to send the state from which the check is critical"
+        )]
+        critical: Seconds,
+        #[clap(
+            short,
+            long,
+            default_value = "10",
+            help = "Seconds after which the videohub is given up on"
+        )]
+        timeout: Seconds,
+    },
+    /// Save and recall sets of routes
+    #[clap(subcommand)]
+    Salvo(SalvoCommand),
+    /// Save the labels, routes, locks and configuration
+    Backup {
+        #[clap(
+            help = "The file to write, in YAML when its extension is .yaml or .yml, in JSON otherwise. The standard output when not set"
+        )]
+        file: Option<PathBuf>,
+    },
+    /// Restore a backup, only the values which differ are sent
+    Restore {
+        #[clap(help = "A JSON or YAML backup")]
+        file: PathBuf,
+        #[clap(long, help = "Only display what would be changed", action)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PortsCommand {
+    /// List the input ports with their labels
+    Input,
+    /// List the output ports with their labels, locks and routes
+    Output,
+}
+
+#[derive(Subcommand)]
+pub enum LabelCommand {
+    /// Change input labels
+    Input {
+        #[clap(
+            required = true,
+            help = "PORT=LABEL. To change the label of the port 2 to 'my label' write: 2=\"my label\". The port can also be given by its current label or an alias"
+        )]
+        labels: Vec<String>,
+    },
+    /// Change output labels
+    Output {
+        #[clap(
+            required = true,
+            help = "PORT=LABEL. To change the label of the port 2 to 'my label' write: 2=\"my label\". The port can also be given by its current label or an alias"
+        )]
+        labels: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LabelsCommand {
+    /// Save the labels of the inputs or outputs
+    Export {
+        #[clap(value_enum)]
+        side: Side,
+        #[clap(
+            help = "The file to write, in TSV when its extension is .tsv, in CSV otherwise. The standard output when not set"
+        )]
+        file: Option<PathBuf>,
+        #[clap(
+            long,
+            help = "Separate the columns with tabs, as spreadsheets do",
+            action
+        )]
+        tsv: bool,
+    },
+    /// Change the labels of the inputs or outputs, only the labels which differ are sent
+    Import {
+        #[clap(value_enum)]
+        side: Side,
+        #[clap(
+            help = "A CSV or TSV file, exported by a spreadsheet or the export command. The first row is skipped when it is a header"
+        )]
+        file: PathBuf,
+        #[clap(long, help = "Only display what would be changed", action)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SalvoCommand {
+    /// Save the current routes of some outputs, replacing the salvo of the same name
+    Save {
+        name: String,
+        #[clap(
+            long,
+            help = "The output ports to save, all of them when not set. Ex: --outputs 0-19,22"
+        )]
+        outputs: Option<PortList>,
+        #[clap(long, help = "Also save the output labels", action)]
+        labels: bool,
+        #[clap(long, help = "Also save the output locks", action)]
+        locks: bool,
+        #[clap(
+            long,
+            default_value = "salvos.json",
+            help = "The file keeping the salvos"
+        )]
+        file: PathBuf,
+    },
+    /// Apply a saved salvo, all its routes are taken at once
+    Recall {
+        name: String,
+        #[clap(
+            long,
+            default_value = "salvos.json",
+            help = "The file keeping the salvos"
+        )]
+        file: PathBuf,
+    },
+}
+
+/// How the ports are numbered for the users
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PortNumbering {
+    /// From 0, as the videohub protocol does
+    ZeroBased,
+    /// From 1, as the panels and patch bays do
+    OneBased,
+}
+
+impl From<PortNumbering> for Numbering {
+    fn from(rhs: PortNumbering) -> Self {
+        match rhs {
+            PortNumbering::ZeroBased => Numbering::ZeroBased,
+            PortNumbering::OneBased => Numbering::OneBased,
+        }
+    }
+}
+
+/// No videohub has more ports, the larger ranges are refused before they are allocated
+const MAX_PORTS: usize = 288;
+
+/// Ports given as a comma separated list of ports and ranges, like `0-19,22`
+#[derive(Debug, Clone)]
+pub struct PortList(pub Vec<usize>);
+
+impl FromStr for PortList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |port: &str| {
+            port.trim()
+                .parse::<usize>()
+                .map_err(|err| format!("Invalid port '{}': {err}", port.trim()))
+        };
+        let mut ports = vec![];
+        for item in s.split(',').map(str::trim) {
+            match item.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err(format!("The range {item} ends before it starts"));
+                    }
+                    if last > MAX_PORTS {
+                        return Err(format!(
+                            "The range {item} goes past {MAX_PORTS}, no videohub has more ports"
+                        ));
+                    }
+                    ports.extend(first..=last);
+                }
+                None => ports.push(parse(item)?),
+            }
+        }
+        Ok(Self(ports))
+    }
+}
+
+/// A duration given in seconds, like `0.5`
+#[derive(Debug, Clone, Copy)]
+pub struct Seconds(pub Duration);
+
+impl FromStr for Seconds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seconds: f64 = s.trim().parse().map_err(|err| format!("{err}"))?;
+        Duration::try_from_secs_f64(seconds)
+            .map(Self)
+            .map_err(|_| format!("Not a number of seconds: {s}"))
+    }
 }
 
 impl Cli {
@@ -44,3 +350,23 @@ impl Cli {
         Self::parse()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_port_list() {
+        let ports: PortList = "0-3, 7 ,10-10".parse().unwrap();
+        assert_eq!(ports.0, [0, 1, 2, 3, 7, 10]);
+        assert_eq!("288".parse::<PortList>().unwrap().0, [288]);
+        assert_eq!("280-288".parse::<PortList>().unwrap().0.len(), 9);
+
+        assert!("5-2".parse::<PortList>().is_err());
+        assert!("0-289".parse::<PortList>().is_err());
+        assert!("0-18446744073709551615".parse::<PortList>().is_err());
+        assert!("1,x".parse::<PortList>().is_err());
+        assert!("-3".parse::<PortList>().is_err());
+        assert!("".parse::<PortList>().is_err());
+    }
+}
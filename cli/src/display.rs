@@ -2,7 +2,8 @@ use itertools::izip;
 use serde_json::value::{from_value, to_value, Value};
 use std::collections::HashMap;
 use tera::{Error, Result};
-use videohub_proto::protocol::{Label, OutputLock, Route};
+use videohub_proto::protocol::{error::Position, Label, LockStatus, OutputLock, Route};
+use videohub_server_api_def::defs::{ChangeKind, Difference, InputPort, OutputPort};
 
 pub fn format_input_labels(args: &HashMap<String, Value>) -> Result<Value> {
     let labels = match args.get("input_labels") {
@@ -82,3 +83,106 @@ pub fn format_output_labels(args: &HashMap<String, Value>) -> Result<Value> {
         .collect::<Vec<String>>();
     Ok(to_value(formatted_output)?)
 }
+
+/// Tera filter padding a value to `width` characters, aligned to the `left` (by default), the
+/// `right` or the `center`. Nothing is padded as an empty string.
+pub fn pad(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    };
+    let width: usize = match args.get("width") {
+        Some(width) => from_value(width.clone())?,
+        None => return Err(Error::msg("The pad filter needs a width")),
+    };
+    let padded = match args.get("align").and_then(Value::as_str).unwrap_or("left") {
+        "left" => format!("{text:<width$}"),
+        "right" => format!("{text:>width$}"),
+        "center" => format!("{text:^width$}"),
+        align => return Err(Error::msg(format!("Unknown alignment '{align}'"))),
+    };
+    Ok(Value::String(padded))
+}
+
+/// Render the location of a protocol error with the offending line of input and a marker under the
+/// column where the parsing failed.
+pub fn format_error_position(position: &Position) -> String {
+    let line_number = position.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+    let block = match &position.block {
+        Some(block) => format!(" in block '{block}'"),
+        None => String::new(),
+    };
+    format!(
+        "{gutter}--> line {line}, column {column}{block}\n\
+         {gutter} |\n\
+         {line_number} | {excerpt}\n\
+         {gutter} | {marker:>column$}",
+        line = position.line,
+        column = position.column,
+        excerpt = position.excerpt,
+        marker = "^",
+    )
+}
+
+pub fn format_difference(difference: &Difference) -> String {
+    let target = match difference.kind {
+        ChangeKind::InputLabel => format!("input label {}", difference.port),
+        ChangeKind::OutputLabel => format!("output label {}", difference.port),
+        ChangeKind::OutputLock => format!("output lock {}", difference.port),
+        ChangeKind::Route => format!("route {}", difference.port),
+        ChangeKind::TakeMode => "take mode".to_string(),
+        ChangeKind::DevicePresent => "device present".to_string(),
+    };
+    format!(
+        "{target}: {current} -> {new}",
+        current = difference.current.as_deref().unwrap_or("?"),
+        new = difference.target
+    )
+}
+
+const TABLE_SEPARATOR: &str =
+    "------------------------------------------------------------------------------------------";
+
+pub fn format_input_ports(input_ports: &[InputPort]) -> String {
+    let mut lines = vec![
+        TABLE_SEPARATOR.to_string(),
+        format!("|{:^8}| {:<78}|", "Port #", "Port Name"),
+        TABLE_SEPARATOR.to_string(),
+    ];
+    for input_port in input_ports {
+        lines.push(format!("|{:^8}| {:<78}|", input_port.id, input_port.label));
+        lines.push(TABLE_SEPARATOR.to_string());
+    }
+    lines.join("\n")
+}
+
+pub fn format_output_ports(output_ports: &[OutputPort]) -> String {
+    let mut lines = vec![
+        TABLE_SEPARATOR.to_string(),
+        format!(
+            "|{:^8}| {:<50}| {:^13}| {:^11}|",
+            "Port #", "Port Name", "Lock State", "Input Port"
+        ),
+        TABLE_SEPARATOR.to_string(),
+    ];
+    for output_port in output_ports {
+        let lock_state = output_port
+            .lock_state
+            .clone()
+            .map(|lock_state| LockStatus::from(lock_state).to_string());
+        lines.push(format!(
+            "|{:^8}| {:<50}| {:^13}| {:^11}|",
+            output_port.id,
+            output_port.label.as_deref().unwrap_or(""),
+            lock_state.unwrap_or_default(),
+            output_port
+                .input_port
+                .map(|input_port| input_port.to_string())
+                .unwrap_or_default()
+        ));
+        lines.push(TABLE_SEPARATOR.to_string());
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,83 @@
+use crate::display::format_difference;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use videohub_proto::protocol::HubInfo;
+use videohub_server_api_def::defs::{
+    self, Configuration, DeviceInfo, Difference, InputPort, OutputPort,
+};
+
+/// How the data is printed
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Tables to read
+    Table,
+    Json,
+    Yaml,
+    /// One line per port, only for the lists
+    Csv,
+}
+
+/// Everything `info` prints, with the field names of the web server API
+#[derive(Serialize)]
+pub struct Info {
+    pub version: String,
+    pub device_info: DeviceInfo,
+    pub input_ports: Vec<InputPort>,
+    pub output_ports: Vec<OutputPort>,
+    pub configuration: Configuration,
+}
+
+impl From<HubInfo> for Info {
+    fn from(rhs: HubInfo) -> Self {
+        Self {
+            input_ports: defs::input_ports(&rhs),
+            output_ports: defs::output_ports(&rhs),
+            version: rhs.protocol_preamble.version,
+            device_info: DeviceInfo::from(rhs.device_info),
+            configuration: Configuration::from(rhs.configuration),
+        }
+    }
+}
+
+/// Print a value, `table` prints it for the table format.
+pub fn print<T, F>(format: Format, value: &T, table: F) -> Result<()>
+where
+    T: Serialize,
+    F: FnOnce(&T) -> Result<()>,
+{
+    match format {
+        Format::Table => table(value)?,
+        Format::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Format::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        Format::Csv => return Err(anyhow!("The CSV format is only available for the lists")),
+    }
+    Ok(())
+}
+
+/// Print a list, one line per item in CSV.
+pub fn print_list<T, F>(format: Format, items: &[T], table: F) -> Result<()>
+where
+    T: Serialize,
+    F: FnOnce(&[T]) -> Result<()>,
+{
+    if format != Format::Csv {
+        return print(format, &items, |items| table(items));
+    }
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for item in items {
+        writer.serialize(item)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Print the changes made, or which would be made, to a videohub.
+pub fn print_differences(format: Format, differences: &[Difference]) -> Result<()> {
+    print_list(format, differences, |differences| {
+        for difference in differences {
+            println!("{}", format_difference(difference));
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,103 @@
+use crate::{
+    address::Side,
+    cli::LabelsCommand,
+    format::{print_differences, Format},
+};
+use anyhow::{anyhow, Context, Result};
+use futures::executor::block_on;
+use log::info;
+use std::fs;
+use videohub_proto::{
+    protocol::{BlockType, HubInfo, Label},
+    Hub,
+};
+use videohub_server_api_def::defs::{self, Numbering, TableFormat};
+
+/// The ports of the tables are numbered as `numbering`.
+pub fn run(
+    videohub: &Hub,
+    command: LabelsCommand,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    match command {
+        LabelsCommand::Export { side, file, tsv } => {
+            let hub_info = block_on(videohub.read())?;
+            let labels = numbering.to_user(side_labels(&hub_info, side).to_vec());
+            let is_tsv = tsv
+                || file.as_deref().is_some_and(|path| {
+                    path.extension().is_some_and(|extension| extension == "tsv")
+                });
+            let table_format = if is_tsv {
+                TableFormat::Tsv
+            } else {
+                TableFormat::Csv
+            };
+            let table = defs::labels_table(&labels, table_format);
+            match file {
+                Some(path) => {
+                    info!("Saving the {} labels to {}", side.name(), path.display());
+                    fs::write(&path, table)
+                        .with_context(|| format!("Failed to write {}", path.display()))
+                }
+                None => {
+                    print!("{table}");
+                    Ok(())
+                }
+            }
+        }
+        LabelsCommand::Import {
+            side,
+            file,
+            dry_run,
+        } => {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let labels = defs::parse_labels_table(&content)
+                .map_err(|message| anyhow!(message))
+                .with_context(|| format!("Invalid labels file {}", file.display()))?;
+            let labels = crate::from_user(numbering, labels)?;
+            let hub_info = block_on(videohub.read())?;
+            check_ports(&hub_info, side, &labels, numbering)?;
+            let block = match side {
+                Side::Input => BlockType::InputLabels(labels),
+                Side::Output => BlockType::OutputLabels(labels),
+            };
+            let differences = defs::differences(&hub_info, &block);
+            print_differences(format, &numbering.to_user(differences))?;
+            match defs::minimal_block(&hub_info, &block) {
+                Some(block) if !dry_run => {
+                    info!("Importing the {} labels of {}", side.name(), file.display());
+                    block_on(videohub.write(block))?;
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+}
+
+fn side_labels(hub_info: &HubInfo, side: Side) -> &[Label] {
+    match side {
+        Side::Input => &hub_info.input_labels,
+        Side::Output => &hub_info.output_labels,
+    }
+}
+
+// The labels of ports the videohub does not have are not sent
+fn check_ports(
+    hub_info: &HubInfo,
+    side: Side,
+    labels: &[Label],
+    numbering: Numbering,
+) -> Result<()> {
+    let nb_ports = side_labels(hub_info, side).len();
+    match labels.iter().find(|Label(port, _)| *port >= nb_ports) {
+        Some(Label(port, _)) => Err(anyhow!(
+            "Unknown {side} port {}, the videohub has {nb_ports} {side}s",
+            numbering.to_user(*port),
+            side = side.name()
+        )),
+        None => Ok(()),
+    }
+}
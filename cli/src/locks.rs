@@ -0,0 +1,244 @@
+use crate::format::{print_differences, Format};
+use anyhow::{anyhow, Result};
+use futures::executor::block_on;
+use log::{info, warn};
+use std::time::{Duration, Instant};
+use videohub_proto::{
+    hub,
+    protocol::{BlockType, HubInfo, LockStatus, OutputLock},
+    Hub,
+};
+use videohub_server_api_def::defs::{self, Numbering};
+
+/// How long the videohub is waited for to report the new locks
+const CONFIRM_DELAY: Duration = Duration::from_secs(1);
+
+/// How the locks of outputs are changed
+#[derive(Debug, Clone, Copy)]
+pub enum LockChange {
+    /// Take the locks for this connection
+    Lock,
+    /// Release the locks of this connection, and those of the other panels with `force`
+    Unlock { force: bool },
+}
+
+/// The block taking the locks of the outputs for this connection.
+///
+/// Fails on the outputs locked by another panel, they have to be unlocked with `--force` first.
+pub fn lock_block(
+    hub_info: &HubInfo,
+    outputs: &[usize],
+    numbering: Numbering,
+) -> Result<BlockType> {
+    check_not_locked_by_other(hub_info, outputs, numbering, "unlock --force releases them")?;
+    Ok(block(outputs, |_| LockStatus::Owned))
+}
+
+/// The block releasing the locks of the outputs held by this connection.
+///
+/// The locks of the other panels are only released with `force`, the other outputs fail.
+pub fn unlock_block(
+    hub_info: &HubInfo,
+    outputs: &[usize],
+    force: bool,
+    numbering: Numbering,
+) -> Result<BlockType> {
+    if !force {
+        check_not_locked_by_other(hub_info, outputs, numbering, "--force unlocks them")?;
+    }
+    Ok(block(outputs, |output| {
+        if hub_info.is_locked_by_other(output) {
+            LockStatus::ForceUnlock
+        } else {
+            LockStatus::Unlocked
+        }
+    }))
+}
+
+/// Fails on the routes to the outputs locked by another panel, the videohub would ignore them.
+pub fn check_routes<'a>(
+    hub_info: &HubInfo,
+    blocks: impl IntoIterator<Item = &'a BlockType>,
+    numbering: Numbering,
+) -> Result<()> {
+    let outputs: Vec<_> = blocks
+        .into_iter()
+        .flat_map(|block| match block {
+            BlockType::VideoOutputRouting(routes) => routes.iter().map(|route| route.0).collect(),
+            _ => vec![],
+        })
+        .collect();
+    check_not_locked_by_other(
+        hub_info,
+        &outputs,
+        numbering,
+        "their routes can not be changed",
+    )
+}
+
+/// Lock or unlock outputs, and report the locks the videohub gave back to this connection.
+pub fn run(
+    videohub: &Hub,
+    outputs: &[usize],
+    change: LockChange,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    let mut session = block_on(videohub.connect())?;
+    let mut hub_info = block_on(session.read_prelude())?;
+    let (block, expected) = match change {
+        LockChange::Lock => (
+            lock_block(&hub_info, outputs, numbering)?,
+            LockStatus::Owned,
+        ),
+        LockChange::Unlock { force } => (
+            unlock_block(&hub_info, outputs, force, numbering).map_err(|err| {
+                anyhow!("{err} (the locks taken by an earlier run belong to its connection)")
+            })?,
+            LockStatus::Unlocked,
+        ),
+    };
+    let differences = numbering.to_user(defs::differences(&hub_info, &block));
+    print_differences(format, &differences)?;
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    info!("Changing the locks -- {block:?}");
+    block_on(session.write(block))?;
+    let done = |hub_info: &HubInfo| {
+        outputs
+            .iter()
+            .all(|output| hub_info.output_lock(*output) == Some(&expected))
+    };
+    let deadline = Instant::now() + CONFIRM_DELAY;
+    while !done(&hub_info) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match block_on(session.next_block_timeout(remaining)) {
+            Ok(Some(block)) => hub_info.apply(block),
+            Ok(None) => break,
+            Err(hub::Error::DeserializeError(err)) => {
+                warn!("Invalid block from the videohub: {err}")
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let refused: Vec<_> = outputs
+        .iter()
+        .filter(|output| hub_info.output_lock(**output) != Some(&expected))
+        .map(|output| numbering.to_user(*output).to_string())
+        .collect();
+    if refused.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "The videohub did not report the outputs {} as {}",
+            refused.join(", "),
+            match expected {
+                LockStatus::Owned => "owned by this connection",
+                _ => "unlocked",
+            }
+        ))
+    }
+}
+
+fn block(outputs: &[usize], lock_status: impl Fn(usize) -> LockStatus) -> BlockType {
+    BlockType::VideoOutputLocks(
+        outputs
+            .iter()
+            .map(|output| OutputLock(*output, lock_status(*output)))
+            .collect(),
+    )
+}
+
+fn check_not_locked_by_other(
+    hub_info: &HubInfo,
+    outputs: &[usize],
+    numbering: Numbering,
+    hint: &str,
+) -> Result<()> {
+    let mut locked: Vec<_> = outputs
+        .iter()
+        .filter(|output| hub_info.is_locked_by_other(**output))
+        .copied()
+        .collect();
+    if locked.is_empty() {
+        return Ok(());
+    }
+    locked.sort_unstable();
+    locked.dedup();
+    Err(anyhow!(
+        "Outputs locked by another panel: {}, {hint}",
+        locked
+            .iter()
+            .map(|output| numbering.to_user(*output).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use videohub_proto::protocol::Route;
+
+    fn hub_info() -> HubInfo {
+        let mut hub_info = HubInfo::default();
+        hub_info.video_output_locks = vec![
+            OutputLock(0, LockStatus::Owned),
+            OutputLock(1, LockStatus::Locked),
+            OutputLock(2, LockStatus::Unlocked),
+        ];
+        hub_info
+    }
+
+    fn locks(block: BlockType) -> Vec<OutputLock> {
+        match block {
+            BlockType::VideoOutputLocks(locks) => locks,
+            block => panic!("Not a block of locks: {block:?}"),
+        }
+    }
+
+    #[test]
+    fn test_unlock_block() {
+        let hub_info = hub_info();
+        let block = unlock_block(&hub_info, &[0, 2], false, Numbering::ZeroBased).unwrap();
+        assert_eq!(
+            locks(block),
+            [
+                OutputLock(0, LockStatus::Unlocked),
+                OutputLock(2, LockStatus::Unlocked),
+            ]
+        );
+        let err = unlock_block(&hub_info, &[0, 1], false, Numbering::OneBased).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Outputs locked by another panel: 2, --force unlocks them"
+        );
+        let block = unlock_block(&hub_info, &[0, 1], true, Numbering::ZeroBased).unwrap();
+        assert_eq!(
+            locks(block),
+            [
+                OutputLock(0, LockStatus::Unlocked),
+                OutputLock(1, LockStatus::ForceUnlock),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lock_block() {
+        let hub_info = hub_info();
+        let block = lock_block(&hub_info, &[0, 2], Numbering::ZeroBased).unwrap();
+        assert_eq!(
+            locks(block),
+            [
+                OutputLock(0, LockStatus::Owned),
+                OutputLock(2, LockStatus::Owned)
+            ]
+        );
+        assert!(lock_block(&hub_info, &[1], Numbering::ZeroBased).is_err());
+        let routes = BlockType::VideoOutputRouting(vec![Route(1, 0)]);
+        assert!(check_routes(&hub_info, [&routes], Numbering::ZeroBased).is_err());
+    }
+}
@@ -1,99 +1,318 @@
+mod address;
+mod backup;
+mod check;
 mod cli;
 mod display;
+mod format;
+mod labels;
+mod locks;
+mod salvo;
+mod shell;
+mod tui;
+mod view;
+mod watch;
 
-use anyhow::Result;
-use cli::Cli;
-use display::{format_input_labels, format_output_labels};
+use address::{Aliases, Ports, Side};
+use anyhow::{anyhow, Result};
+use cli::{Cli, Command, LabelCommand, PortsCommand};
+use display::{format_error_position, format_input_ports, format_output_ports};
+use format::{print, print_differences, print_list, Format, Info};
 use futures::executor::block_on;
+use locks::LockChange;
 use log::info;
-use std::{net::Ipv4Addr, str::FromStr};
-use tera::Tera;
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    net::{Ipv4Addr, SocketAddrV4},
+    path::PathBuf,
+    str::FromStr,
+};
 use videohub_proto::{
-    protocol::{BlockType, HubInfo, Label, LockStatus, OutputLock, Route},
+    capture::{self, CaptureWriter},
+    hub,
+    protocol::{ser, BlockType, HubInfo, Label, Route},
     Hub, DEFAULT_DEVICE_PORT,
 };
+use videohub_server_api_def::defs::{self, Numbering};
+use view::View;
+use watch::WatchFilter;
 
-fn main() -> Result<()> {
+fn main() {
     let args = Cli::get();
     simple_logger::SimpleLogger::new().env().init().unwrap();
 
-    let ipv4_addr = Ipv4Addr::from_str(&args.ip_address)?;
+    if let Err(err) = run(args) {
+        eprintln!("Error: {err:#}");
+        // Show where the data received from the videohub could not be understood
+        if let Some(position) = err
+            .downcast_ref::<hub::Error>()
+            .and_then(hub::Error::position)
+        {
+            eprintln!("{}", format_error_position(position));
+        }
+        std::process::exit(1);
+    }
+}
 
-    let videohub = Hub::new(ipv4_addr, DEFAULT_DEVICE_PORT);
+fn run(args: Cli) -> Result<()> {
+    let command = args.command.unwrap_or(Command::Info);
+    let format = args.format;
+    let aliases = Aliases::load(&args.aliases)?;
+    let numbering = Numbering::from(args.numbering);
+    let display = Display {
+        format,
+        view: args.view,
+        template: args.template,
+    };
 
-    if let Some(Label(id, text)) = args.input_label {
-        let block = BlockType::InputLabels(vec![Label(id, text.clone())]);
-        info!("Changing label of input port {} to {}", id, text);
-        block_on(videohub.write(block))?;
-    }
-    if let Some(Label(id, text)) = args.output_label {
-        let block = BlockType::OutputLabels(vec![Label(id, text.clone())]);
-        info!("Changing label of output port {} to {}", id, text);
-        block_on(videohub.write(block))?;
+    if let Some(path) = args.replay {
+        let records = capture::read_file(path)?;
+        if let Some(port) = args.replay_port {
+            block_on(capture::serve(
+                SocketAddrV4::new(Ipv4Addr::LOCALHOST, port),
+                records,
+            ))?;
+            return Ok(());
+        }
+        let hub_info = capture::replay(&records)?;
+        return match command {
+            Command::Info => info(&numbering.to_user(hub_info), &display),
+            Command::Dump => dump(&hub_info),
+            _ => Err(anyhow!(
+                "Only info and dump can be used with a capture file"
+            )),
+        };
     }
-    if let Some(Route(dst, src)) = args.output_route {
-        let block = BlockType::VideoOutputRouting(vec![Route(dst, src)]);
-        info!("Routing -- Input={} to Output={}", src, dst);
-        block_on(videohub.write(block))?;
+
+    // Without a capture file, clap makes sure the address is provided
+    let ipv4_addr = Ipv4Addr::from_str(&args.ip_address.unwrap_or_default())?;
+
+    let mut videohub = Hub::new(ipv4_addr, DEFAULT_DEVICE_PORT);
+    if let Some(path) = args.capture {
+        videohub = videohub.with_capture(CaptureWriter::create(path)?);
     }
-    if let Some(index) = args.unlock {
-        let block = BlockType::VideoOutputLocks(vec![OutputLock(index, LockStatus::ForceUnlock)]);
-        info!("Unlocking -- Output={index}");
-        block_on(videohub.write(block))?;
+
+    match command {
+        Command::Info => info(&numbering.to_user(block_on(videohub.read())?), &display)?,
+        Command::Ports(command) => ports(
+            &numbering.to_user(block_on(videohub.read())?),
+            command,
+            format,
+        )?,
+        Command::Route { routes, from, take } => {
+            let hub_info = block_on(videohub.read())?;
+            let ports = Ports::new(&hub_info, &aliases, numbering);
+            let routes = resolve_routes(&ports, &routes, from)?;
+            for Route(dst, src) in &routes {
+                info!("Routing -- Input={} to Output={}", src, dst);
+            }
+            let block = BlockType::VideoOutputRouting(routes);
+            locks::check_routes(&hub_info, [&block], numbering)?;
+            let take = take || (hub_info.configuration.take_mode && io::stdin().is_terminal());
+            if !take || confirm_take(&hub_info, &block, numbering, format)? {
+                block_on(videohub.write(block))?;
+            }
+        }
+        Command::Label(LabelCommand::Input { labels }) => {
+            let hub_info = block_on(videohub.read())?;
+            let ports = Ports::new(&hub_info, &aliases, numbering);
+            let labels = resolve_labels(&ports, Side::Input, &labels)?;
+            for Label(id, text) in &labels {
+                info!("Changing label of input port {} to {}", id, text);
+            }
+            block_on(videohub.write(BlockType::InputLabels(labels)))?;
+        }
+        Command::Label(LabelCommand::Output { labels }) => {
+            let hub_info = block_on(videohub.read())?;
+            let ports = Ports::new(&hub_info, &aliases, numbering);
+            let labels = resolve_labels(&ports, Side::Output, &labels)?;
+            for Label(id, text) in &labels {
+                info!("Changing label of output port {} to {}", id, text);
+            }
+            block_on(videohub.write(BlockType::OutputLabels(labels)))?;
+        }
+        Command::Labels(command) => labels::run(&videohub, command, numbering, format)?,
+        Command::Lock { outputs } => {
+            let hub_info = block_on(videohub.read())?;
+            let outputs = resolve_outputs(&Ports::new(&hub_info, &aliases, numbering), &outputs)?;
+            info!("Locking -- Outputs={outputs:?}");
+            locks::run(&videohub, &outputs, LockChange::Lock, numbering, format)?;
+        }
+        Command::Unlock { outputs, force } => {
+            let hub_info = block_on(videohub.read())?;
+            let outputs = resolve_outputs(&Ports::new(&hub_info, &aliases, numbering), &outputs)?;
+            info!("Unlocking -- Outputs={outputs:?} Force={force}");
+            locks::run(
+                &videohub,
+                &outputs,
+                LockChange::Unlock { force },
+                numbering,
+                format,
+            )?;
+        }
+        Command::Watch { outputs, inputs } => {
+            let filter = WatchFilter {
+                outputs: outputs
+                    .map(|outputs| from_user(numbering, outputs.0))
+                    .transpose()?,
+                inputs: inputs
+                    .map(|inputs| from_user(numbering, inputs.0))
+                    .transpose()?,
+            };
+            if filter.outputs.is_some() || filter.inputs.is_some() {
+                let device_info = block_on(videohub.read())?.device_info;
+                let outputs = filter.outputs.as_deref().unwrap_or_default();
+                check_ports(outputs, device_info.nb_video_outputs, "output", numbering)?;
+                let inputs = filter.inputs.as_deref().unwrap_or_default();
+                check_ports(inputs, device_info.nb_video_inputs, "input", numbering)?;
+            }
+            let address = ipv4_addr.to_string();
+            watch::watch(&videohub, &address, &filter, numbering, format)?
+        }
+        Command::Tui => tui::run(&videohub, numbering)?,
+        Command::Shell { script } => {
+            shell::run(&videohub, script.as_deref(), &aliases, numbering, format)?
+        }
+        Command::Dump => dump(&block_on(videohub.read())?)?,
+        Command::Check {
+            warning,
+            critical,
+            timeout,
+        } => {
+            let thresholds = check::Thresholds {
+                warning: warning.0,
+                critical: critical.0,
+                timeout: timeout.0,
+            };
+            std::process::exit(check::run(&videohub, thresholds) as i32);
+        }
+        Command::Salvo(command) => salvo::run(&videohub, command, numbering, format)?,
+        Command::Backup { file } => backup::backup(&videohub, file.as_deref(), numbering, format)?,
+        Command::Restore { file, dry_run } => {
+            backup::restore(&videohub, &file, dry_run, numbering, format)?
+        }
     }
-    if let Some(index) = args.lock {
-        let block = BlockType::VideoOutputLocks(vec![OutputLock(index, LockStatus::Locked)]);
-        info!("Locking -- Output={index}");
-        block_on(videohub.write(block))?;
+
+    Ok(())
+}
+
+// The routes of OUTPUT=INPUT pairs, or of outputs to a single input
+fn resolve_routes(ports: &Ports, routes: &[String], from: Option<String>) -> Result<Vec<Route>> {
+    let mut resolved = vec![];
+    for route in routes {
+        let (outputs, input) = match (&from, route.split_once('=')) {
+            (Some(input), _) => (route.as_str(), input.as_str()),
+            (None, Some(pair)) => pair,
+            (None, None) => {
+                return Err(anyhow!(
+                    "'{route}' is not OUTPUT=INPUT, the input can also be given with --from"
+                ))
+            }
+        };
+        let input = ports.one(Side::Input, input)?;
+        resolved.extend(
+            ports
+                .all(Side::Output, outputs)?
+                .into_iter()
+                .map(|output| Route(output, input)),
+        );
     }
-    if args.display {
-        let hub_info = block_on(videohub.read())?;
-        display_hub_info(&hub_info).unwrap();
+    Ok(resolved)
+}
+
+// The labels of PORT=LABEL pairs
+fn resolve_labels(ports: &Ports, side: Side, labels: &[String]) -> Result<Vec<Label>> {
+    labels
+        .iter()
+        .map(|label| {
+            let (port, text) = label
+                .split_once('=')
+                .ok_or_else(|| anyhow!("'{label}' is not PORT=LABEL"))?;
+            Ok(Label(ports.one(side, port)?, text.to_string()))
+        })
+        .collect()
+}
+
+fn resolve_outputs(ports: &Ports, outputs: &[String]) -> Result<Vec<usize>> {
+    let mut resolved = vec![];
+    for output in outputs {
+        resolved.extend(ports.all(Side::Output, output)?);
     }
+    Ok(resolved)
+}
 
-    Ok(())
+/// The ports of a value given by a user, numbered as the protocol does.
+fn from_user<T: defs::Renumber>(numbering: Numbering, value: T) -> Result<T> {
+    numbering
+        .from_user(value)
+        .map_err(|message| anyhow!(message))
 }
 
-fn display_hub_info(hub_info: &HubInfo) -> Result<()> {
-    let mut tera = Tera::new("**/templates/**/*.j2")?;
+/// Fails on the ports the videohub does not have, they are numbered as the protocol does.
+fn check_ports(ports: &[usize], nb_ports: usize, side: &str, numbering: Numbering) -> Result<()> {
+    match ports.iter().find(|port| **port >= nb_ports) {
+        Some(port) => Err(anyhow!(
+            "The videohub has no {side} port {}, it has {nb_ports} of them",
+            numbering.to_user(*port)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Print the routes which would change, and ask whether to take them.
+fn confirm_take(
+    hub_info: &HubInfo,
+    block: &BlockType,
+    numbering: Numbering,
+    format: Format,
+) -> Result<bool> {
+    let differences = numbering.to_user(defs::differences(hub_info, block));
+    print_differences(format, &differences)?;
+    if differences.is_empty() {
+        return Ok(false);
+    }
+    eprint!("Take {} route(s)? [y/N] ", differences.len());
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let taken = matches!(answer.trim(), "y" | "Y" | "yes");
+    if !taken {
+        eprintln!("Cancelled, nothing was sent");
+    }
+    Ok(taken)
+}
 
-    tera.register_function("format_input_labels", format_input_labels);
-    tera.register_function("format_output_labels", format_output_labels);
+/// How `info` is printed
+struct Display {
+    format: Format,
+    // In the table format
+    view: View,
+    template: Option<PathBuf>,
+}
 
-    // Find the relative template path name from the file name
-    let template_name = tera
-        .get_template_names()
-        .find(|name| name.contains("device_info.j2"))
-        .unwrap_or("");
+fn info(hub_info: &HubInfo, display: &Display) -> Result<()> {
+    print(display.format, &Info::from(hub_info.clone()), |_| {
+        let template = display.template.as_deref();
+        println!("{}", view::render(hub_info, display.view, template)?);
+        Ok(())
+    })
+}
 
-    let context = get_context(hub_info);
-    let result = tera.render(template_name, &context)?;
-    println!("{result}");
-    Ok(())
+fn ports(hub_info: &HubInfo, command: PortsCommand, format: Format) -> Result<()> {
+    match command {
+        PortsCommand::Input => print_list(format, &defs::input_ports(hub_info), |input_ports| {
+            println!("{}", format_input_ports(input_ports));
+            Ok(())
+        }),
+        PortsCommand::Output => print_list(format, &defs::output_ports(hub_info), |output_ports| {
+            println!("{}", format_output_ports(output_ports));
+            Ok(())
+        }),
+    }
 }
 
-fn get_context(hub_info: &HubInfo) -> tera::Context {
-    let mut context = tera::Context::new();
-
-    context.insert("version", &hub_info.protocol_preamble.version);
-    context.insert("device_present", &hub_info.device_info.device_present);
-    context.insert("model_name", &hub_info.device_info.model_name);
-    context.insert("friendly_name", &hub_info.device_info.friendly_name);
-    context.insert("unique_id", &hub_info.device_info.unique_id);
-    context.insert("nb_video_inputs", &hub_info.device_info.nb_video_inputs);
-    context.insert("nb_video_outputs", &hub_info.device_info.nb_video_outputs);
-    context.insert(
-        "nb_video_processing_units",
-        &hub_info.device_info.nb_video_processing_units,
-    );
-    context.insert(
-        "nb_video_monitoring_outputs",
-        &hub_info.device_info.nb_video_monitoring_outputs,
-    );
-    context.insert("nb_serial_ports", &hub_info.device_info.nb_serial_ports);
-    context.insert("input_labels", &hub_info.input_labels);
-    context.insert("output_labels", &hub_info.output_labels);
-    context.insert("video_output_routing", &hub_info.video_output_routing);
-    context.insert("video_output_locks", &hub_info.video_output_locks);
-    context
+fn dump(hub_info: &HubInfo) -> Result<()> {
+    for block in hub_info.blocks() {
+        print!("{}", ser::to_string(&block)?);
+    }
+    Ok(())
 }
@@ -0,0 +1,86 @@
+use crate::{
+    cli::SalvoCommand,
+    format::{print_differences, Format},
+};
+use anyhow::{anyhow, Context, Result};
+use futures::executor::block_on;
+use log::info;
+use std::{fs, io, path::Path};
+use videohub_proto::Hub;
+use videohub_server_api_def::defs::{self, Numbering, Salvo, SalvoCapture};
+
+/// The ports are given and printed in the numbering of the users, the salvos files keep the
+/// numbering of the protocol as the web server does.
+pub fn run(
+    videohub: &Hub,
+    command: SalvoCommand,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    match command {
+        SalvoCommand::Save {
+            name,
+            outputs,
+            labels,
+            locks,
+            file,
+        } => {
+            let capture = SalvoCapture {
+                name,
+                outputs: outputs
+                    .map(|outputs| crate::from_user(numbering, outputs.0))
+                    .transpose()?,
+                labels,
+                locks,
+            };
+            let hub_info = block_on(videohub.read())?;
+            crate::check_ports(
+                capture.outputs.as_deref().unwrap_or_default(),
+                hub_info.device_info.nb_video_outputs,
+                "output",
+                numbering,
+            )?;
+            let salvo = Salvo::capture(&hub_info, &capture);
+            let mut salvos = load(&file)?;
+            salvos.retain(|existing| existing.name != salvo.name);
+            info!("Saving the salvo '{}' to {}", salvo.name, file.display());
+            salvos.push(salvo);
+            salvos.sort_by(|a, b| a.name.cmp(&b.name));
+            save(&file, &salvos)
+        }
+        SalvoCommand::Recall { name, file } => {
+            let salvo = load(&file)?
+                .into_iter()
+                .find(|salvo| salvo.name == name)
+                .ok_or_else(|| anyhow!("Unknown salvo '{name}' in {}", file.display()))?;
+            let hub_info = block_on(videohub.read())?;
+            let blocks = salvo.blocks();
+            crate::locks::check_routes(&hub_info, &blocks, numbering)?;
+            let differences: Vec<_> = blocks
+                .iter()
+                .flat_map(|block| defs::differences(&hub_info, block))
+                .collect();
+            print_differences(format, &numbering.to_user(differences))?;
+            info!("Recalling the salvo '{name}'");
+            for block in blocks {
+                block_on(videohub.write(block))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Same format as the salvos of the web server
+fn load(path: &Path) -> Result<Vec<Salvo>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Invalid salvos file {}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn save(path: &Path, salvos: &[Salvo]) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(salvos)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,497 @@
+use crate::{
+    address::{Aliases, Ports, Side},
+    cli::PortsCommand,
+    format::{print_differences, Format},
+    locks,
+    view::View,
+};
+use anyhow::{anyhow, Context as _, Result};
+use clap::{ErrorKind, Parser, ValueEnum};
+use futures::executor::block_on;
+use log::LevelFilter;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    CompletionType, Config, Context, Editor, Helper,
+};
+use std::{
+    fs,
+    io::{self, IsTerminal, Read},
+    mem::{discriminant, Discriminant},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+use videohub_proto::{
+    hub,
+    protocol::{BlockType, HubInfo, Label, Route},
+    Hub, Session, SessionWriter,
+};
+use videohub_server_api_def::defs::{self, Numbering};
+
+const PROMPT: &str = "videohub> ";
+const HISTORY_FILE: &str = ".videohub_history";
+/// How long the videohub is waited for to report a change, before the next command
+const CONFIRM_DELAY: Duration = Duration::from_secs(1);
+
+/// The commands of the shell
+#[derive(Parser)]
+#[clap(no_binary_name = true)]
+enum ShellCommand {
+    /// Route an input to outputs. The ports are numbers, labels, label globs or aliases
+    Route { outputs: String, input: String },
+    /// Change the label of a port
+    Label {
+        #[clap(value_enum)]
+        side: Side,
+        port: String,
+        label: String,
+    },
+    /// Lock outputs for this connection, unless another panel holds their lock
+    Lock {
+        #[clap(required = true)]
+        outputs: Vec<String>,
+    },
+    /// Release the locks of this connection, and those of the other panels with --force
+    Unlock {
+        #[clap(required = true)]
+        outputs: Vec<String>,
+        #[clap(long)]
+        force: bool,
+    },
+    /// Display the videohub info or its ports
+    Show {
+        #[clap(value_enum)]
+        what: Show,
+    },
+    /// Leave the shell
+    #[clap(alias = "quit")]
+    Exit,
+}
+
+#[derive(Clone, ValueEnum)]
+enum Show {
+    Info,
+    Inputs,
+    #[clap(alias = "routes")]
+    Outputs,
+}
+
+// The words of the commands, completed before the ports
+const COMMANDS: &[&str] = &["route", "label", "lock", "unlock", "show", "help", "exit"];
+const SIDES: &[&str] = &["input", "output"];
+const SHOWN: &[&str] = &["info", "inputs", "outputs", "routes"];
+
+/// Run the commands typed, or read from a script, over a single connection to the videohub.
+///
+/// `-` reads the script from the standard input, which is also used when it is not a terminal.
+pub fn run(
+    videohub: &Hub,
+    script: Option<&Path>,
+    aliases: &Aliases,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    // Only the problems are logged between the commands, unless asked otherwise
+    if std::env::var_os("RUST_LOG").is_none() {
+        log::set_max_level(LevelFilter::Warn);
+    }
+
+    let mut shell = Shell {
+        videohub,
+        aliases,
+        numbering,
+        format,
+        mirror: Arc::new(Mirror::default()),
+        writer: None,
+    };
+    shell.connect()?;
+
+    match script {
+        Some(path) if path != Path::new("-") => {
+            let script = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            shell.run_script(&script)
+        }
+        None if io::stdin().is_terminal() => shell.run_interactive(),
+        _ => {
+            let mut script = String::new();
+            io::stdin().read_to_string(&mut script)?;
+            shell.run_script(&script)
+        }
+    }
+}
+
+struct Shell<'a> {
+    videohub: &'a Hub,
+    aliases: &'a Aliases,
+    numbering: Numbering,
+    format: Format,
+    mirror: Arc<Mirror>,
+    writer: Option<SessionWriter>,
+}
+
+impl Shell<'_> {
+    // Open the connection when it is not, the state is then kept up to date by a thread
+    fn connect(&mut self) -> Result<()> {
+        if self.mirror.hub_info().is_some() {
+            return Ok(());
+        }
+        let mut session = block_on(self.videohub.connect())?;
+        let prelude = block_on(session.read_prelude())?;
+        self.mirror.set(Some(prelude));
+        self.writer = Some(session.writer());
+        let mirror = self.mirror.clone();
+        thread::spawn(move || follow(session, &mirror));
+        Ok(())
+    }
+
+    fn run_interactive(&mut self) -> Result<()> {
+        // The ports are listed with their labels
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = Editor::<ShellHelper, DefaultHistory>::with_config(config)?;
+        editor.set_helper(Some(ShellHelper {
+            mirror: self.mirror.clone(),
+            numbering: self.numbering,
+        }));
+        let history = history_path();
+        if let Some(history) = &history {
+            // There is no history the first time
+            let _ = editor.load_history(history);
+        }
+        loop {
+            let line = match editor.readline(PROMPT) {
+                Ok(line) => line,
+                // Ctrl-C clears the line, Ctrl-D leaves
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err.into()),
+            };
+            if !line.trim().is_empty() {
+                editor.add_history_entry(line.as_str())?;
+            }
+            match self.execute(&line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => eprintln!("Error: {err:#}"),
+            }
+        }
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+        Ok(())
+    }
+
+    // Stop at the first command failing
+    fn run_script(&mut self, script: &str) -> Result<()> {
+        for (index, line) in script.lines().enumerate() {
+            let keep_going = self
+                .execute(line)
+                .with_context(|| format!("line {}: {}", index + 1, line.trim()))?;
+            if !keep_going {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the shell keeps going
+    fn execute(&mut self, line: &str) -> Result<bool> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(true);
+        }
+        let command = match ShellCommand::try_parse_from(split_words(line)?) {
+            Ok(command) => command,
+            Err(err) if matches!(err.kind(), ErrorKind::DisplayHelp) => {
+                print!("{err}");
+                return Ok(true);
+            }
+            Err(err) => return Err(anyhow!(err.to_string().trim_end().to_string())),
+        };
+        self.connect()?;
+        let hub_info = self.mirror.hub_info().ok_or_else(|| {
+            anyhow!("Lost the connection with the videohub, the command was not sent")
+        })?;
+
+        let ports = Ports::new(&hub_info, self.aliases, self.numbering);
+        let block = match command {
+            ShellCommand::Route { outputs, input } => {
+                let input = ports.one(Side::Input, &input)?;
+                let routes = ports
+                    .all(Side::Output, &outputs)?
+                    .into_iter()
+                    .map(|output| Route(output, input))
+                    .collect();
+                let block = BlockType::VideoOutputRouting(routes);
+                locks::check_routes(&hub_info, [&block], self.numbering)?;
+                block
+            }
+            ShellCommand::Label { side, port, label } => {
+                let label = vec![Label(ports.one(side, &port)?, label)];
+                match side {
+                    Side::Input => BlockType::InputLabels(label),
+                    Side::Output => BlockType::OutputLabels(label),
+                }
+            }
+            ShellCommand::Lock { outputs } => {
+                let outputs = crate::resolve_outputs(&ports, &outputs)?;
+                locks::lock_block(&hub_info, &outputs, self.numbering)?
+            }
+            ShellCommand::Unlock { outputs, force } => {
+                let outputs = crate::resolve_outputs(&ports, &outputs)?;
+                locks::unlock_block(&hub_info, &outputs, force, self.numbering)?
+            }
+            ShellCommand::Show { what } => {
+                let hub_info = self.numbering.to_user(hub_info);
+                let display = crate::Display {
+                    format: self.format,
+                    view: View::DeviceInfo,
+                    template: None,
+                };
+                match what {
+                    Show::Info => crate::info(&hub_info, &display)?,
+                    Show::Inputs => crate::ports(&hub_info, PortsCommand::Input, self.format)?,
+                    Show::Outputs => crate::ports(&hub_info, PortsCommand::Output, self.format)?,
+                }
+                return Ok(true);
+            }
+            ShellCommand::Exit => return Ok(false),
+        };
+
+        // What the command changes, the state is updated once the videohub sends it back
+        let differences = self.numbering.to_user(defs::differences(&hub_info, &block));
+        print_differences(self.format, &differences)?;
+        if differences.is_empty() {
+            return Ok(true);
+        }
+        let kind = discriminant(&block);
+        self.mirror.clear_received();
+        let writer = self.writer.as_mut().unwrap();
+        if let Err(err) = block_on(writer.write(block)) {
+            self.mirror.set(None);
+            return Err(err.into());
+        }
+        // For the next commands to see the change
+        if !self.mirror.wait_for(kind, CONFIRM_DELAY) {
+            log::warn!("The videohub did not report the change, the output may be locked");
+        }
+        Ok(true)
+    }
+}
+
+// Keep the state up to date until the connection is lost
+fn follow(mut session: Session, mirror: &Mirror) {
+    loop {
+        match block_on(session.next_block()) {
+            Ok(block) => mirror.apply(block),
+            Err(hub::Error::DeserializeError(err)) => {
+                log::warn!("Invalid block from the videohub: {err}");
+            }
+            Err(err) => {
+                log::warn!("Lost the connection with the videohub: {err}");
+                mirror.set(None);
+                return;
+            }
+        }
+    }
+}
+
+// The state of the videohub, kept up to date by the thread following the session
+#[derive(Default)]
+struct Mirror {
+    state: Mutex<MirrorState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct MirrorState {
+    // None while disconnected
+    hub_info: Option<HubInfo>,
+    // The kinds of blocks received since the last command was sent
+    received: Vec<Discriminant<BlockType>>,
+}
+
+impl Mirror {
+    fn hub_info(&self) -> Option<HubInfo> {
+        self.state.lock().unwrap().hub_info.clone()
+    }
+
+    fn set(&self, hub_info: Option<HubInfo>) {
+        self.state.lock().unwrap().hub_info = hub_info;
+        self.changed.notify_all();
+    }
+
+    fn apply(&self, block: BlockType) {
+        let mut state = self.state.lock().unwrap();
+        state.received.push(discriminant(&block));
+        if let Some(hub_info) = state.hub_info.as_mut() {
+            hub_info.apply(block);
+        }
+        self.changed.notify_all();
+    }
+
+    fn clear_received(&self) {
+        self.state.lock().unwrap().received.clear();
+    }
+
+    // Whether a block of this kind was received within the delay, or the connection was lost
+    fn wait_for(&self, kind: Discriminant<BlockType>, delay: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (_state, timeout) = self
+            .changed
+            .wait_timeout_while(state, delay, |state| {
+                state.hub_info.is_some() && !state.received.contains(&kind)
+            })
+            .unwrap();
+        !timeout.timed_out()
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+}
+
+// Split a line in words, the words within quotes are kept together
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Missing closing quote"));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// Completes the commands, and the ports from their number or label
+struct ShellHelper {
+    mirror: Arc<Mirror>,
+    numbering: Numbering,
+}
+
+impl ShellHelper {
+    // The labels of a side, with the numbering of the users
+    fn labels(&self, side: &str) -> Vec<Label> {
+        let state = self.mirror.state.lock().unwrap();
+        let labels = match (state.hub_info.as_ref(), side) {
+            (Some(hub_info), "input" | "in") => hub_info.input_labels.clone(),
+            (Some(hub_info), _) => hub_info.output_labels.clone(),
+            (None, _) => vec![],
+        };
+        self.numbering.to_user(labels)
+    }
+
+    fn ports(&self, side: &str, prefix: &str) -> Vec<Pair> {
+        let prefix = prefix.to_lowercase();
+        self.labels(side)
+            .iter()
+            .filter(|Label(id, label)| {
+                id.to_string().starts_with(&prefix) || label.to_lowercase().contains(&prefix)
+            })
+            .map(|Label(id, label)| Pair {
+                display: format!("{id} {label}"),
+                replacement: id.to_string(),
+            })
+            .collect()
+    }
+
+    // The current label of a port, to edit it
+    fn label(&self, side: &str, port: &str) -> Vec<Pair> {
+        self.labels(side)
+            .iter()
+            .filter(|Label(id, _)| id.to_string() == port)
+            .map(|Label(_, label)| Pair {
+                display: label.clone(),
+                replacement: format!("\"{label}\""),
+            })
+            .collect()
+    }
+}
+
+fn words(candidates: &[&str], prefix: &str) -> Vec<Pair> {
+    candidates
+        .iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .map(|candidate| Pair {
+            display: candidate.to_string(),
+            replacement: candidate.to_string(),
+        })
+        .collect()
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |index| index + 1);
+        let prefix = &line[start..pos];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+        let candidates = match previous.as_slice() {
+            [] => words(COMMANDS, prefix),
+            ["route"] | ["lock" | "unlock", ..] => self.ports("output", prefix),
+            ["route", _] => self.ports("input", prefix),
+            ["label"] => words(SIDES, prefix),
+            ["label", side] => self.ports(side, prefix),
+            ["label", side, port] => self.label(side, port),
+            ["show"] => words(SHOWN, prefix),
+            _ => vec![],
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("  route  5 \t12 ").unwrap(),
+            ["route", "5", "12"]
+        );
+        assert_eq!(
+            split_words(r#"label output 0="Studio A" 'CAM "3"'"#).unwrap(),
+            ["label", "output", "0=Studio A", "CAM \"3\""]
+        );
+        // An empty quoted word is kept
+        assert_eq!(split_words("route '' 1").unwrap(), ["route", "", "1"]);
+        assert!(split_words("").unwrap().is_empty());
+        assert!(split_words("route \"CAM 1").is_err());
+    }
+}
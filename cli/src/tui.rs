@@ -0,0 +1,508 @@
+use crate::watch::RECONNECT_DELAY;
+use anyhow::Result;
+use futures::executor::block_on;
+use log::LevelFilter;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Row, Table, TableState, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+use videohub_proto::{
+    hub,
+    protocol::{BlockType, HubInfo, Route},
+    Hub,
+};
+use videohub_server_api_def::defs::{self, InputPort, LockStatus, Numbering, OutputPort};
+
+/// How long to wait for a key before looking for the changes of the videohub
+const POLL_DELAY: Duration = Duration::from_millis(100);
+
+const HELP: &str = "↑↓ move  ←→/Tab switch panel  / search  n next match  Enter take  q quit";
+
+// Sent by the thread following the videohub
+enum Update {
+    State(HubInfo),
+    Block(BlockType),
+    Lost(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Panel {
+    Inputs,
+    Outputs,
+}
+
+struct App {
+    hub_info: Option<HubInfo>,
+    // The ports are shown in the numbering of the users
+    numbering: Numbering,
+    focus: Panel,
+    inputs: TableState,
+    outputs: TableState,
+    // The label searched, while it is typed
+    search: Option<String>,
+    last_search: String,
+    // The route waiting to be confirmed, (output, input)
+    take: Option<(usize, usize)>,
+    status: String,
+    // Number of rows visible in the panels, to move by pages
+    page_size: usize,
+    quit: bool,
+}
+
+/// Full screen routing panel, kept up to date with the changes made on the videohub.
+///
+/// The output and the input under the cursors are routed once the take is confirmed.
+pub fn run(videohub: &Hub, numbering: Numbering) -> Result<()> {
+    // The logs would be written over the screen
+    log::set_max_level(LevelFilter::Off);
+
+    let (sender, updates) = mpsc::channel();
+    let follower = videohub.clone();
+    thread::spawn(move || follow(&follower, &sender));
+
+    let mut terminal = ratatui::try_init()?;
+    let result = App::new(numbering).run(&mut terminal, videohub, &updates);
+    ratatui::restore();
+    result
+}
+
+// Send the state of the videohub and its changes until the application is closed
+fn follow(videohub: &Hub, updates: &Sender<Update>) {
+    loop {
+        let result = block_on(async {
+            let mut session = videohub.connect().await?;
+            let prelude = session.read_prelude().await?;
+            if updates.send(Update::State(prelude)).is_err() {
+                return Ok(());
+            }
+            loop {
+                match session.next_block().await {
+                    Ok(block) => {
+                        if updates.send(Update::Block(block)).is_err() {
+                            return Ok(());
+                        }
+                    }
+                    // Skipped, like the monitors of the web server do
+                    Err(hub::Error::DeserializeError(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        });
+        match result {
+            Ok(()) => return,
+            Err(err) => {
+                if updates.send(Update::Lost(err.to_string())).is_err() {
+                    return;
+                }
+            }
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+impl App {
+    fn new(numbering: Numbering) -> Self {
+        Self {
+            hub_info: None,
+            numbering,
+            focus: Panel::Outputs,
+            inputs: TableState::default(),
+            outputs: TableState::default(),
+            search: None,
+            last_search: String::new(),
+            take: None,
+            status: "Connecting to the videohub...".to_string(),
+            page_size: 1,
+            quit: false,
+        }
+    }
+
+    fn run(
+        mut self,
+        terminal: &mut DefaultTerminal,
+        videohub: &Hub,
+        updates: &Receiver<Update>,
+    ) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(POLL_DELAY)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.on_key(key, videohub);
+                    }
+                }
+            }
+            while let Ok(update) = updates.try_recv() {
+                self.on_update(update);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_update(&mut self, update: Update) {
+        match update {
+            Update::State(hub_info) => {
+                self.status = format!("Connected to {}", hub_info.device_info.friendly_name);
+                self.hub_info = Some(hub_info);
+                // The number of ports may have changed while disconnected
+                let nb_inputs = self.input_ports().len();
+                let nb_outputs = self.output_ports().len();
+                clamp(&mut self.inputs, nb_inputs);
+                clamp(&mut self.outputs, nb_outputs);
+            }
+            Update::Block(block) => {
+                if let Some(hub_info) = &mut self.hub_info {
+                    hub_info.apply(block);
+                }
+            }
+            Update::Lost(err) => {
+                self.status = format!("Lost the connection with the videohub: {err}");
+            }
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent, videohub: &Hub) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        if let Some(search) = &mut self.search {
+            match key.code {
+                KeyCode::Char(c) => {
+                    search.push(c);
+                    self.find(false);
+                }
+                KeyCode::Backspace => {
+                    search.pop();
+                }
+                KeyCode::Enter => self.last_search = self.search.take().unwrap_or_default(),
+                KeyCode::Esc => self.search = None,
+                _ => {}
+            }
+            return;
+        }
+        if let Some((output, input)) = self.take {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    self.take = None;
+                    self.route(videohub, output, input);
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.take = None;
+                    self.status = "Take cancelled".to_string();
+                }
+                _ => {}
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Panel::Inputs => Panel::Outputs,
+                    Panel::Outputs => Panel::Inputs,
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.focus = Panel::Inputs,
+            KeyCode::Right | KeyCode::Char('l') => self.focus = Panel::Outputs,
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-(self.page_size as isize)),
+            KeyCode::PageDown => self.move_by(self.page_size as isize),
+            KeyCode::Home | KeyCode::Char('g') => self.move_by(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.move_by(isize::MAX),
+            KeyCode::Char('/') => self.search = Some(String::new()),
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Enter | KeyCode::Char('t') => self.prepare_take(),
+            _ => {}
+        }
+    }
+
+    fn input_ports(&self) -> Vec<InputPort> {
+        self.hub_info
+            .as_ref()
+            .map(|hub_info| self.numbering.to_user(defs::input_ports(hub_info)))
+            .unwrap_or_default()
+    }
+
+    fn output_ports(&self) -> Vec<OutputPort> {
+        self.hub_info
+            .as_ref()
+            .map(|hub_info| self.numbering.to_user(defs::output_ports(hub_info)))
+            .unwrap_or_default()
+    }
+
+    // The labels of the focused panel with its cursor
+    fn focused(&mut self) -> (Vec<String>, &mut TableState) {
+        match self.focus {
+            Panel::Inputs => (
+                self.input_ports()
+                    .into_iter()
+                    .map(|input_port| input_port.label)
+                    .collect(),
+                &mut self.inputs,
+            ),
+            Panel::Outputs => (
+                self.output_ports()
+                    .into_iter()
+                    .map(|output_port| output_port.label.unwrap_or_default())
+                    .collect(),
+                &mut self.outputs,
+            ),
+        }
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        let (labels, state) = self.focused();
+        if labels.is_empty() {
+            return;
+        }
+        let selected = state.selected().unwrap_or(0) as isize;
+        let last = labels.len() as isize - 1;
+        state.select(Some(selected.saturating_add(offset).clamp(0, last) as usize));
+    }
+
+    // Move to the next label containing the search, from the cursor or after it
+    fn find(&mut self, next: bool) {
+        let search = self
+            .search
+            .clone()
+            .unwrap_or_else(|| self.last_search.clone())
+            .to_lowercase();
+        if search.is_empty() {
+            return;
+        }
+        let (labels, state) = self.focused();
+        let start = state.selected().unwrap_or(0) + usize::from(next);
+        let found = (0..labels.len())
+            .map(|offset| (start + offset) % labels.len())
+            .find(|index| labels[*index].to_lowercase().contains(&search));
+        match found {
+            Some(index) => state.select(Some(index)),
+            None => self.status = format!("No label matches '{search}'"),
+        }
+    }
+
+    fn prepare_take(&mut self) {
+        let output_ports = self.output_ports();
+        let input_ports = self.input_ports();
+        let output_port = self.outputs.selected().and_then(|i| output_ports.get(i));
+        let input_port = self.inputs.selected().and_then(|i| input_ports.get(i));
+        let (Some(output_port), Some(input_port)) = (output_port, input_port) else {
+            return;
+        };
+        if matches!(output_port.lock_state, Some(LockStatus::Locked)) {
+            self.status = format!("The output {} is locked by another panel", output_port.id);
+        } else if output_port.input_port == Some(input_port.id) {
+            self.status = format!(
+                "The input {} is already routed to the output {}",
+                input_port.id, output_port.id
+            );
+        } else {
+            self.take = Some((output_port.id, input_port.id));
+        }
+    }
+
+    fn route(&mut self, videohub: &Hub, output: usize, input: usize) {
+        let route = crate::from_user(self.numbering, Route(output, input));
+        let result = route.and_then(|route| {
+            let block = BlockType::VideoOutputRouting(vec![route]);
+            Ok(block_on(videohub.write(block))?)
+        });
+        self.status = match result {
+            Ok(_) => format!("Routed the input {input} to the output {output}"),
+            Err(err) => format!("Failed to route the input {input} to the output {output}: {err}"),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(2)]).areas(frame.area());
+        let [inputs_area, outputs_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+        // Without the borders and the header
+        self.page_size = usize::from(main.height.saturating_sub(3)).max(1);
+
+        let input_ports = self.input_ports();
+        let output_ports = self.output_ports();
+        let selected_input = self
+            .inputs
+            .selected()
+            .and_then(|i| input_ports.get(i))
+            .map(|input_port| input_port.id);
+        // The input routed to the output under the cursor
+        let routed_input = self
+            .outputs
+            .selected()
+            .and_then(|i| output_ports.get(i))
+            .and_then(|output_port| output_port.input_port);
+
+        let input_rows = input_ports.iter().map(|input_port| {
+            let style = if Some(input_port.id) == routed_input {
+                Style::new().fg(Color::Yellow)
+            } else {
+                Style::new()
+            };
+            Row::new([input_port.id.to_string(), input_port.label.clone()]).style(style)
+        });
+        let inputs = Table::new(input_rows, [Constraint::Length(4), Constraint::Min(8)])
+            .header(header(["#", "Input"]))
+            .block(panel_block(" Inputs ", self.focus == Panel::Inputs))
+            .row_highlight_style(highlight_style(self.focus == Panel::Inputs));
+        frame.render_stateful_widget(inputs, inputs_area, &mut self.inputs);
+
+        let output_rows = output_ports.iter().map(|output_port| {
+            // The outputs fed by the input under the cursor
+            let style =
+                if output_port.input_port.is_some() && output_port.input_port == selected_input {
+                    Style::new().fg(Color::Green)
+                } else {
+                    Style::new()
+                };
+            let source = output_port
+                .input_port
+                .map(|id| {
+                    let label = input_ports
+                        .iter()
+                        .find(|input_port| input_port.id == id)
+                        .map(|input_port| input_port.label.as_str())
+                        .unwrap_or_default();
+                    format!("{id} {label}")
+                })
+                .unwrap_or_default();
+            Row::new([
+                output_port.id.to_string(),
+                output_port.label.clone().unwrap_or_default(),
+                output_port
+                    .lock_state
+                    .as_ref()
+                    .map(lock_label)
+                    .unwrap_or_default()
+                    .to_string(),
+                source,
+            ])
+            .style(style)
+        });
+        let outputs = Table::new(
+            output_rows,
+            [
+                Constraint::Length(4),
+                Constraint::Min(8),
+                Constraint::Length(8),
+                Constraint::Min(8),
+            ],
+        )
+        .header(header(["#", "Output", "Lock", "Input"]))
+        .block(panel_block(" Outputs ", self.focus == Panel::Outputs))
+        .row_highlight_style(highlight_style(self.focus == Panel::Outputs));
+        frame.render_stateful_widget(outputs, outputs_area, &mut self.outputs);
+
+        let first_line = match &self.search {
+            Some(search) => format!("/{search}"),
+            None => self.status.clone(),
+        };
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(first_line),
+                Line::from(HELP).style(Style::new().add_modifier(Modifier::DIM)),
+            ]),
+            status,
+        );
+
+        if let Some((output, input)) = self.take {
+            let label = |ports: Vec<(usize, String)>, id: usize| {
+                ports
+                    .into_iter()
+                    .find(|(port, _)| *port == id)
+                    .map(|(_, label)| label)
+                    .unwrap_or_default()
+            };
+            let input_label = label(
+                input_ports
+                    .into_iter()
+                    .map(|port| (port.id, port.label))
+                    .collect(),
+                input,
+            );
+            let output_label = label(
+                output_ports
+                    .into_iter()
+                    .map(|port| (port.id, port.label.unwrap_or_default()))
+                    .collect(),
+                output,
+            );
+            let question = format!(
+                "Route the input {input} '{input_label}' to the output {output} '{output_label}'? (y/n)"
+            );
+            let area = centered(frame.area(), 60, 5);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(question)
+                    .wrap(Wrap { trim: true })
+                    .block(Block::bordered().title(" Take ")),
+                area,
+            );
+        }
+    }
+}
+
+// Keep the cursor on an existing row
+fn clamp(state: &mut TableState, nb_rows: usize) {
+    let selected = match (state.selected(), nb_rows) {
+        (_, 0) => None,
+        (Some(selected), _) => Some(selected.min(nb_rows - 1)),
+        (None, _) => Some(0),
+    };
+    state.select(selected);
+}
+
+fn header<const N: usize>(titles: [&'static str; N]) -> Row<'static> {
+    Row::new(titles).style(Style::new().add_modifier(Modifier::BOLD))
+}
+
+fn panel_block(title: &'static str, focused: bool) -> Block<'static> {
+    let block = Block::bordered().title(title);
+    if focused {
+        block.border_style(Style::new().fg(Color::Cyan))
+    } else {
+        block
+    }
+}
+
+fn highlight_style(focused: bool) -> Style {
+    if focused {
+        Style::new().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::new().add_modifier(Modifier::UNDERLINED)
+    }
+}
+
+fn lock_label(lock_status: &LockStatus) -> &'static str {
+    match lock_status {
+        LockStatus::Locked => "locked",
+        LockStatus::Owned => "owned",
+        LockStatus::Unlocked | LockStatus::ForceUnlock => "",
+    }
+}
+
+// A rectangle of `width` by `height` at the center of `area`
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
@@ -0,0 +1,132 @@
+use crate::display::{format_input_labels, format_output_labels, pad};
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use std::path::Path;
+use tera::{Context, Tera};
+use videohub_proto::protocol::HubInfo;
+use videohub_server_api_def::defs;
+
+// Built in the binary, to run it from anywhere
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "device_info.j2",
+        include_str!("../templates/device_info.j2"),
+    ),
+    ("matrix.j2", include_str!("../templates/matrix.j2")),
+    ("crosspoint.j2", include_str!("../templates/crosspoint.j2")),
+    ("routes.j2", include_str!("../templates/routes.j2")),
+];
+const CUSTOM_TEMPLATE: &str = "custom";
+
+/// The built-in templates displaying the info
+#[derive(Clone, Copy, ValueEnum)]
+pub enum View {
+    /// The device with its input and output ports
+    DeviceInfo,
+    /// The outputs fed by every input
+    Matrix,
+    /// A grid of the outputs by the inputs, marking the routes
+    Crosspoint,
+    /// One line per output with its input
+    Routes,
+}
+
+impl View {
+    fn template_name(self) -> &'static str {
+        match self {
+            View::DeviceInfo => "device_info.j2",
+            View::Matrix => "matrix.j2",
+            View::Crosspoint => "crosspoint.j2",
+            View::Routes => "routes.j2",
+        }
+    }
+}
+
+/// Render the info with a built-in view, or with a Tera template file, without the last new lines.
+pub fn render(hub_info: &HubInfo, view: View, template: Option<&Path>) -> Result<String> {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(TEMPLATES.iter().copied())?;
+    tera.register_function("format_input_labels", format_input_labels);
+    tera.register_function("format_output_labels", format_output_labels);
+    tera.register_filter("pad", pad);
+
+    let template_name = match template {
+        Some(path) => {
+            tera.add_template_file(path, Some(CUSTOM_TEMPLATE))
+                .with_context(|| format!("Invalid template {}", path.display()))?;
+            CUSTOM_TEMPLATE
+        }
+        None => view.template_name(),
+    };
+    let rendered = tera.render(template_name, &context(hub_info))?;
+    Ok(rendered.trim_end_matches('\n').to_string())
+}
+
+/// The values given to the templates
+fn context(hub_info: &HubInfo) -> Context {
+    let mut context = Context::new();
+
+    context.insert("version", &hub_info.protocol_preamble.version);
+    context.insert("device_present", &hub_info.device_info.device_present);
+    context.insert("model_name", &hub_info.device_info.model_name);
+    context.insert("friendly_name", &hub_info.device_info.friendly_name);
+    context.insert("unique_id", &hub_info.device_info.unique_id);
+    context.insert("nb_video_inputs", &hub_info.device_info.nb_video_inputs);
+    context.insert("nb_video_outputs", &hub_info.device_info.nb_video_outputs);
+    context.insert(
+        "nb_video_processing_units",
+        &hub_info.device_info.nb_video_processing_units,
+    );
+    context.insert(
+        "nb_video_monitoring_outputs",
+        &hub_info.device_info.nb_video_monitoring_outputs,
+    );
+    context.insert("nb_serial_ports", &hub_info.device_info.nb_serial_ports);
+    context.insert("input_labels", &hub_info.input_labels);
+    context.insert("output_labels", &hub_info.output_labels);
+    context.insert("video_output_routing", &hub_info.video_output_routing);
+    context.insert("video_output_locks", &hub_info.video_output_locks);
+    // With the field names of the web server API
+    context.insert("input_ports", &defs::input_ports(hub_info));
+    context.insert("output_ports", &defs::output_ports(hub_info));
+    context.insert(
+        "configuration",
+        &defs::Configuration::from(hub_info.configuration.clone()),
+    );
+    context
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use videohub_proto::protocol::{Label, LockStatus, OutputLock, Route};
+
+    #[test]
+    fn test_crosspoint_columns() {
+        let mut hub_info = HubInfo::default();
+        hub_info.input_labels = (0..3).map(|id| Label(id, format!("In {id}"))).collect();
+        hub_info.output_labels = (0..3).map(|id| Label(id, format!("Out {id}"))).collect();
+        hub_info.video_output_locks = vec![
+            OutputLock(0, LockStatus::Unlocked),
+            OutputLock(1, LockStatus::Locked),
+            OutputLock(2, LockStatus::Owned),
+        ];
+        hub_info.video_output_routing = vec![Route(0, 0), Route(1, 1), Route(2, 2)];
+
+        let rendered = render(&hub_info, View::Crosspoint, None).unwrap();
+        let lines: Vec<Vec<char>> = rendered
+            .lines()
+            .map(|line| line.chars().collect())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        // The number of each input ends in the column of the routes from it
+        let header = &lines[1];
+        for (output, row) in lines[2..].iter().enumerate() {
+            assert_eq!(row.len(), header.len());
+            let column = row.iter().position(|c| *c == '●').unwrap();
+            assert_eq!(header[column], char::from_digit(output as u32, 10).unwrap());
+        }
+        assert_eq!(lines[3][25..27], [' ', 'L']);
+        assert_eq!(lines[4][25..27], [' ', 'O']);
+    }
+}
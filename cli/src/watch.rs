@@ -0,0 +1,168 @@
+use crate::{display::format_difference, format::Format};
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use futures::executor::block_on;
+use log::warn;
+use std::{thread, time::Duration};
+use videohub_proto::{hub, protocol::HubInfo, Hub};
+use videohub_server_api_def::defs::{self, AuditEntry, ChangeKind, Difference, Numbering};
+
+/// How long to wait before connecting again to the videohub
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The ports whose changes are printed, all of them when not set. Numbered as the protocol does
+pub struct WatchFilter {
+    pub outputs: Option<Vec<usize>>,
+    pub inputs: Option<Vec<usize>>,
+}
+
+impl WatchFilter {
+    fn matches(&self, difference: &Difference) -> bool {
+        let matches = |ports: &Option<Vec<usize>>, port: Option<usize>| {
+            ports
+                .as_ref()
+                .is_none_or(|ports| port.is_some_and(|port| ports.contains(&port)))
+        };
+        let output = matches(&self.outputs, Some(difference.port));
+        match difference.kind {
+            // Only the ports of the kind filtered are shown
+            ChangeKind::InputLabel => {
+                self.outputs.is_none() && matches(&self.inputs, Some(difference.port))
+            }
+            ChangeKind::OutputLabel | ChangeKind::OutputLock => self.inputs.is_none() && output,
+            ChangeKind::Route => output && matches(&self.inputs, difference.target.parse().ok()),
+            ChangeKind::TakeMode | ChangeKind::DevicePresent => true,
+        }
+    }
+}
+
+/// Print the changes reported by the videohub until interrupted, the connection is opened again
+/// when it is lost. In JSON, one audit entry per line.
+pub fn watch(
+    videohub: &Hub,
+    address: &str,
+    filter: &WatchFilter,
+    numbering: Numbering,
+    format: Format,
+) -> Result<()> {
+    if !matches!(format, Format::Table | Format::Json) {
+        return Err(anyhow!("watch prints tables or JSON lines"));
+    }
+    let print = |difference: Difference| {
+        if !filter.matches(&difference) {
+            return;
+        }
+        let difference = numbering.to_user(difference);
+        match format {
+            Format::Json => {
+                let entry = AuditEntry {
+                    timestamp: Utc::now(),
+                    hub: address.to_string(),
+                    user: None,
+                    client: None,
+                    kind: difference.kind,
+                    port: difference.port,
+                    before: difference.current,
+                    after: difference.target,
+                };
+                println!("{}", serde_json::to_string(&entry).unwrap());
+            }
+            _ => println!(
+                "{} {}",
+                Local::now().format("%H:%M:%S"),
+                format_difference(&difference)
+            ),
+        }
+    };
+
+    let mut hub_info = None;
+    loop {
+        if let Err(err) = block_on(follow(videohub, &mut hub_info, &print)) {
+            warn!("Lost the connection with the videohub: {err}");
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+// Print the changes until the connection is lost
+async fn follow(
+    videohub: &Hub,
+    hub_info: &mut Option<HubInfo>,
+    print: &impl Fn(Difference),
+) -> hub::Result<()> {
+    let mut session = videohub.connect().await?;
+    let prelude = session.read_prelude().await?;
+    // The changes made while the connection was lost
+    if let Some(previous) = hub_info {
+        defs::state_differences(previous, &prelude)
+            .into_iter()
+            .for_each(print);
+    }
+    let state = hub_info.insert(prelude);
+    loop {
+        match session.next_block().await {
+            Ok(block) => {
+                defs::differences(state, &block).into_iter().for_each(print);
+                state.apply(block);
+            }
+            Err(hub::Error::DeserializeError(err)) => {
+                warn!("Invalid block from the videohub: {err}");
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn difference(kind: ChangeKind, port: usize, target: &str) -> Difference {
+        Difference {
+            kind,
+            port,
+            current: None,
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let all = WatchFilter {
+            outputs: None,
+            inputs: None,
+        };
+        assert!(all.matches(&difference(ChangeKind::Route, 3, "4")));
+        assert!(all.matches(&difference(ChangeKind::InputLabel, 3, "CAM")));
+
+        let outputs = WatchFilter {
+            outputs: Some(vec![0, 1]),
+            inputs: None,
+        };
+        assert!(outputs.matches(&difference(ChangeKind::Route, 1, "7")));
+        assert!(!outputs.matches(&difference(ChangeKind::Route, 2, "7")));
+        assert!(outputs.matches(&difference(ChangeKind::OutputLock, 0, "L")));
+        assert!(!outputs.matches(&difference(ChangeKind::InputLabel, 0, "CAM")));
+        assert!(outputs.matches(&difference(ChangeKind::TakeMode, 0, "true")));
+
+        let inputs = WatchFilter {
+            outputs: None,
+            inputs: Some(vec![4]),
+        };
+        assert!(inputs.matches(&difference(ChangeKind::Route, 9, "4")));
+        assert!(!inputs.matches(&difference(ChangeKind::Route, 9, "5")));
+        assert!(inputs.matches(&difference(ChangeKind::InputLabel, 4, "CAM")));
+        assert!(!inputs.matches(&difference(ChangeKind::InputLabel, 5, "CAM")));
+        assert!(!inputs.matches(&difference(ChangeKind::OutputLabel, 4, "PGM")));
+        assert!(inputs.matches(&difference(ChangeKind::DevicePresent, 0, "false")));
+
+        let both = WatchFilter {
+            outputs: Some(vec![1]),
+            inputs: Some(vec![4]),
+        };
+        assert!(both.matches(&difference(ChangeKind::Route, 1, "4")));
+        assert!(!both.matches(&difference(ChangeKind::Route, 1, "5")));
+        assert!(!both.matches(&difference(ChangeKind::Route, 2, "4")));
+        assert!(!both.matches(&difference(ChangeKind::OutputLabel, 1, "PGM")));
+    }
+}
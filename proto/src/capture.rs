@@ -0,0 +1,331 @@
+//! Capture files of the raw bytes exchanged with a videohub device.
+//!
+//! A capture is a sequence of records. Each record starts with a header line holding the
+//! timestamp (seconds since the UNIX epoch, with 6 digits of microseconds), the direction (TX for
+//! bytes sent to the device, RX for bytes received from it) and the number of bytes, followed by
+//! the raw bytes and a newline:
+//!
+//! ```text
+//! 1666180800.123456 RX 33
+//! PROTOCOL PREAMBLE:
+//! Version: 2.3
+//!
+//! ```
+
+use super::hub;
+use crate::protocol::{de, HubInfo};
+use async_std::{
+    io::WriteExt,
+    net::{TcpListener, TcpStream},
+    task,
+};
+use futures::StreamExt;
+use log::{info, warn};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    net::SocketAddrV4,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to access the capture file")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid record header '{0}' in the capture file")]
+    InvalidHeader(String),
+    #[error("The record starting with '{0}' is truncated")]
+    TruncatedRecord(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Bytes sent to the videohub
+    Sent,
+    /// Bytes received from the videohub
+    Received,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Direction::Sent => "TX",
+            Direction::Received => "RX",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "TX" => Ok(Direction::Sent),
+            "RX" => Ok(Direction::Received),
+            _ => Err(Error::InvalidHeader(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Time since the UNIX epoch
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    pub fn now(direction: Direction, data: &[u8]) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            direction,
+            data: data.to_vec(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = format!(
+            "{}.{:06} {} {}\n",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.direction,
+            self.data.len()
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&self.data);
+        bytes.push(b'\n');
+        bytes
+    }
+}
+
+/// Appends the records to a capture file. Cloning it shares the same file.
+#[derive(Debug, Clone)]
+pub struct CaptureWriter {
+    file: Arc<Mutex<File>>,
+}
+
+impl CaptureWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    pub fn record(&self, direction: Direction, data: &[u8]) -> Result<()> {
+        let record = Record::now(direction, data);
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&record.encode())?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+pub fn parse(mut bytes: &[u8]) -> Result<Vec<Record>> {
+    let mut records = vec![];
+    while !bytes.is_empty() {
+        let header_len = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| Error::InvalidHeader(String::from_utf8_lossy(bytes).to_string()))?;
+        let header = String::from_utf8_lossy(&bytes[..header_len]).to_string();
+        let invalid_header = || Error::InvalidHeader(header.clone());
+
+        let mut fields = header.split(' ');
+        let (secs, micros) = fields
+            .next()
+            .and_then(|timestamp| timestamp.split_once('.'))
+            .ok_or_else(invalid_header)?;
+        // Written with 6 digits, `.5` would otherwise be read as 5 microseconds
+        if micros.len() != 6 || !micros.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid_header());
+        }
+        let micros: u32 = micros.parse().map_err(|_| invalid_header())?;
+        let timestamp = Duration::new(secs.parse().map_err(|_| invalid_header())?, micros * 1_000);
+        let direction = fields.next().ok_or_else(invalid_header)?.parse()?;
+        let len: usize = fields
+            .next()
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(invalid_header)?;
+
+        let data_start = header_len + 1;
+        // The data is followed by a newline
+        if bytes.len() < data_start + len + 1 {
+            return Err(Error::TruncatedRecord(header));
+        }
+        records.push(Record {
+            timestamp,
+            direction,
+            data: bytes[data_start..data_start + len].to_vec(),
+        });
+        bytes = &bytes[data_start + len + 1..];
+    }
+    Ok(records)
+}
+
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<Record>> {
+    parse(&std::fs::read(path)?)
+}
+
+/// All the bytes received from the videohub, as text.
+pub fn received(records: &[Record]) -> String {
+    let bytes: Vec<u8> = records
+        .iter()
+        .filter(|record| record.direction == Direction::Received)
+        .flat_map(|record| record.data.iter().copied())
+        .collect();
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Feed the bytes received in a capture back through the protocol deserializer.
+pub fn replay(records: &[Record]) -> hub::Result<HubInfo> {
+    Ok(de::from_str(&received(records))?)
+}
+
+/// Act as a videohub: every client connecting to `addr` gets the received bytes of the capture,
+/// with the same delays between them as when they were captured. The clients are served at the
+/// same time, each from the start of the capture.
+pub async fn serve(addr: SocketAddrV4, records: Vec<Record>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Replaying capture on {addr}");
+    let records = Arc::new(records);
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+        info!("Replaying capture to {peer}");
+        let records = records.clone();
+        task::spawn(async move {
+            if let Err(err) = replay_to(stream, &records).await {
+                warn!("Replay to {peer} interrupted: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn replay_to(mut stream: TcpStream, records: &[Record]) -> Result<()> {
+    let mut previous: Option<Duration> = None;
+    for record in records
+        .iter()
+        .filter(|record| record.direction == Direction::Received)
+    {
+        if let Some(previous) = previous {
+            task::sleep(record.timestamp.saturating_sub(previous)).await;
+        }
+        previous = Some(record.timestamp);
+        stream.write_all(&record.data).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trip() {
+        let records = vec![
+            Record {
+                timestamp: Duration::new(1666180800, 123_456_000),
+                direction: Direction::Received,
+                data: b"PROTOCOL PREAMBLE:\nVersion: 2.3\n\n".to_vec(),
+            },
+            Record {
+                timestamp: Duration::new(1666180801, 0),
+                direction: Direction::Sent,
+                data: b"VIDEO OUTPUT ROUTING:\n3 5\n\n".to_vec(),
+            },
+        ];
+        let bytes: Vec<u8> = records.iter().flat_map(Record::encode).collect();
+        assert!(bytes.starts_with(b"1666180800.123456 RX 33\nPROTOCOL PREAMBLE:"));
+        assert_eq!(parse(&bytes).unwrap(), records);
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let bytes = b"1666180800.000000 RX 10\nVIDEO\n";
+        assert!(matches!(parse(bytes), Err(Error::TruncatedRecord(_))));
+    }
+
+    #[test]
+    fn test_invalid_timestamp() {
+        for header in [
+            "1.1000000 RX 0",
+            "1.4294967 RX 0",
+            "1.-1 RX 0",
+            "1 RX 0",
+            "1.5 RX 0",
+            "1.+12345 RX 0",
+            "1. RX 0",
+        ] {
+            let bytes = format!("{header}\n\n");
+            assert!(
+                matches!(parse(bytes.as_bytes()), Err(Error::InvalidHeader(_))),
+                "{header}"
+            );
+        }
+        let records = parse(b"1.999999 RX 0\n\n1.500000 TX 0\n\n").unwrap();
+        assert_eq!(records[0].timestamp, Duration::new(1, 999_999_000));
+        assert_eq!(records[1].timestamp, Duration::new(1, 500_000_000));
+    }
+
+    #[test]
+    fn test_serve_clients_together() {
+        let records = vec![
+            Record {
+                timestamp: Duration::from_secs(0),
+                direction: Direction::Received,
+                data: b"PROTOCOL PREAMBLE:\n".to_vec(),
+            },
+            Record {
+                timestamp: Duration::from_secs(2),
+                direction: Direction::Received,
+                data: b"Version: 2.3\n\n".to_vec(),
+            },
+        ];
+        // A free port
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, port);
+        task::block_on(async {
+            task::spawn(serve(addr, records));
+            task::sleep(Duration::from_millis(100)).await;
+            let first = TcpStream::connect(addr).await.unwrap();
+            let mut second = TcpStream::connect(addr).await.unwrap();
+            // The second client does not wait for the end of the replay to the first
+            let mut buf = [0; 64];
+            let read = async_std::io::timeout(
+                Duration::from_secs(1),
+                async_std::io::ReadExt::read(&mut second, &mut buf),
+            )
+            .await
+            .unwrap();
+            assert_eq!(&buf[..read], b"PROTOCOL PREAMBLE:\n");
+            drop(first);
+        });
+    }
+
+    #[test]
+    fn test_received() {
+        let bytes = b"1.000000 RX 19\nPROTOCOL PREAMBLE:\n\n\
+                      2.000000 TX 4\nPING\n\
+                      3.000000 RX 14\nVersion: 2.3\n\n\n";
+        let records = parse(bytes).unwrap();
+        assert_eq!(received(&records), "PROTOCOL PREAMBLE:\nVersion: 2.3\n\n");
+    }
+}
@@ -1,9 +1,12 @@
 use super::protocol::{de, ser, BlockType, HubInfo};
+use crate::capture::{CaptureWriter, Direction};
 use crate::protocol;
-use async_std::net::TcpStream;
-use futures::io::{AsyncReadExt, AsyncWriteExt};
+use async_std::{io::BufReader, net::TcpStream};
+use futures::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use log::{debug, trace, warn};
 use std::net::Ipv4Addr;
 use std::net::SocketAddrV4;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -12,13 +15,53 @@ pub enum Error {
     ConnectionError(#[from] std::io::Error),
     #[error("Failed to read bytes from the videohub")]
     ReadError,
+    #[error("The videohub did not answer in time")]
+    Timeout,
     #[error("An error occured during the protocol deserialization")]
     DeserializeError(#[from] protocol::error::Error),
 }
 
-#[derive(Debug)]
+impl Error {
+    /// Where a deserialization error happened in the data received from the videohub.
+    pub fn position(&self) -> Option<&protocol::error::Position> {
+        match self {
+            Error::DeserializeError(err) => err.position(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Hub {
     socket_addr: SocketAddrV4,
+    capture: Option<CaptureWriter>,
+}
+
+/// A connection kept open with the videohub, which sends a block every time its state changes.
+#[derive(Debug)]
+pub struct Session {
+    socket_addr: SocketAddrV4,
+    capture: Option<CaptureWriter>,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+/// Sends blocks over the connection of a session, while the session waits for the changes.
+#[derive(Debug, Clone)]
+pub struct SessionWriter {
+    socket_addr: SocketAddrV4,
+    capture: Option<CaptureWriter>,
+    writer: TcpStream,
+}
+
+/// What the videohub sends over a session
+#[derive(Debug)]
+pub enum Message {
+    Block(BlockType),
+    /// A command, or a ping, was accepted
+    Ack,
+    /// A command was refused
+    Nak,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -28,7 +71,23 @@ pub const DEFAULT_DEVICE_PORT: u16 = 9990;
 impl Hub {
     pub fn new(ip: Ipv4Addr, port: u16) -> Self {
         let socket_addr = SocketAddrV4::new(ip, port);
-        Self { socket_addr }
+        Self {
+            socket_addr,
+            capture: None,
+        }
+    }
+
+    /// Record every byte sent to and received from the videohub in a capture file.
+    pub fn with_capture(mut self, capture: CaptureWriter) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Check if a connection to the videohub can be opened within `timeout`.
+    pub async fn is_reachable(&self, timeout: Duration) -> bool {
+        async_std::io::timeout(timeout, TcpStream::connect(&self.socket_addr))
+            .await
+            .is_ok()
     }
 
     pub async fn read(&self) -> Result<HubInfo> {
@@ -37,6 +96,10 @@ impl Hub {
         let mut content = "".to_string();
         loop {
             let nb_bytes = stream.read(&mut buffer).await?;
+            if nb_bytes == 0 {
+                return Err(Error::ReadError);
+            }
+            self.trace(Direction::Received, &buffer[..nb_bytes]);
             let partial_content = String::from_utf8(buffer[..nb_bytes].to_vec()).unwrap();
             content.push_str(&partial_content);
             if partial_content.contains("END PRELUDE") {
@@ -46,9 +109,168 @@ impl Hub {
         Ok(de::from_str(&content)?)
     }
 
+    /// Read the state like `read`, failing with `Error::Timeout` when the videohub does not
+    /// connect and send it all within `timeout`.
+    pub async fn read_timeout(&self, timeout: Duration) -> Result<HubInfo> {
+        async_std::future::timeout(timeout, self.read())
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
     pub async fn write(&self, block: BlockType) -> Result<usize> {
         let block = ser::to_string(&block)?;
         let mut stream = TcpStream::connect(&self.socket_addr).await?;
-        Ok(stream.write(block.as_bytes()).await?)
+        let nb_bytes = stream.write(block.as_bytes()).await?;
+        self.trace(Direction::Sent, &block.as_bytes()[..nb_bytes]);
+        Ok(nb_bytes)
+    }
+
+    /// Open a connection that stays open to receive the changes made on the videohub.
+    pub async fn connect(&self) -> Result<Session> {
+        let stream = TcpStream::connect(&self.socket_addr).await?;
+        Ok(Session {
+            socket_addr: self.socket_addr,
+            capture: self.capture.clone(),
+            reader: BufReader::new(stream.clone()),
+            writer: stream,
+        })
+    }
+
+    fn trace(&self, direction: Direction, data: &[u8]) {
+        trace_bytes(&self.socket_addr, &self.capture, direction, data);
+    }
+}
+
+impl Session {
+    /// Wait for the next block sent by the videohub. The blocks that are not supported are skipped.
+    pub async fn next_block(&mut self) -> Result<BlockType> {
+        loop {
+            match self.next_message().await? {
+                Message::Block(block) => return Ok(block),
+                message => debug!("{} Skipping message {message:?}", self.socket_addr),
+            }
+        }
+    }
+
+    /// Wait for the next block or acknowledgment sent by the videohub. The blocks that are not
+    /// supported are skipped.
+    pub async fn next_message(&mut self) -> Result<Message> {
+        loop {
+            let block = self.read_block().await?;
+            match de::block_from_str(&block) {
+                Err(protocol::error::Error::UnsupportedBlock(name)) => {
+                    debug!("{} Skipping unsupported block {name}", self.socket_addr);
+                }
+                Err(protocol::error::Error::ExpectedBlockHeader(header)) => match header.as_str() {
+                    "ACK" => return Ok(Message::Ack),
+                    "NAK" => return Ok(Message::Nak),
+                    _ => debug!("{} Skipping message {header}", self.socket_addr),
+                },
+                result => return Ok(Message::Block(result?)),
+            }
+        }
+    }
+
+    /// Wait for the next block for at most `timeout`, None when none was received. A block partly
+    /// received when the time is over is lost.
+    pub async fn next_block_timeout(&mut self, timeout: Duration) -> Result<Option<BlockType>> {
+        match async_std::future::timeout(timeout, self.next_block()).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Read the blocks sent by the videohub when the connection is opened.
+    pub async fn read_prelude(&mut self) -> Result<HubInfo> {
+        let mut hub_info = HubInfo::default();
+        loop {
+            match self.next_block().await? {
+                BlockType::EndPrelude(_) => return Ok(hub_info),
+                block => hub_info.apply(block),
+            }
+        }
+    }
+
+    pub async fn write(&mut self, block: BlockType) -> Result<usize> {
+        self.writer().write(block).await
+    }
+
+    /// A writer sharing the connection, to send blocks from another thread.
+    pub fn writer(&self) -> SessionWriter {
+        SessionWriter {
+            socket_addr: self.socket_addr,
+            capture: self.capture.clone(),
+            writer: self.writer.clone(),
+        }
+    }
+
+    // Read the lines up to the blank line ending a block
+    async fn read_block(&mut self) -> Result<String> {
+        let mut block = String::new();
+        let mut nb_blank_lines = 0;
+        loop {
+            let nb_bytes = self.reader.read_line(&mut block).await?;
+            if nb_bytes == 0 {
+                return Err(Error::ReadError);
+            }
+            if block == "\n" {
+                // Blank lines between blocks
+                nb_blank_lines += 1;
+                block.clear();
+            } else if block.ends_with("\n\n") {
+                break;
+            }
+        }
+        trace_bytes(
+            &self.socket_addr,
+            &self.capture,
+            Direction::Received,
+            ("\n".repeat(nb_blank_lines) + &block).as_bytes(),
+        );
+        Ok(block)
+    }
+}
+
+impl SessionWriter {
+    /// Ask the videohub for an acknowledgment, received by the session as `Message::Ack`.
+    pub async fn ping(&mut self) -> Result<()> {
+        let ping = "PING:\n\n";
+        self.writer.write_all(ping.as_bytes()).await?;
+        trace_bytes(
+            &self.socket_addr,
+            &self.capture,
+            Direction::Sent,
+            ping.as_bytes(),
+        );
+        Ok(())
+    }
+
+    pub async fn write(&mut self, block: BlockType) -> Result<usize> {
+        let block = ser::to_string(&block)?;
+        self.writer.write_all(block.as_bytes()).await?;
+        trace_bytes(
+            &self.socket_addr,
+            &self.capture,
+            Direction::Sent,
+            block.as_bytes(),
+        );
+        Ok(block.len())
+    }
+}
+
+fn trace_bytes(
+    socket_addr: &SocketAddrV4,
+    capture: &Option<CaptureWriter>,
+    direction: Direction,
+    data: &[u8],
+) {
+    trace!(
+        "{socket_addr} {direction} {}",
+        String::from_utf8_lossy(data).escape_debug()
+    );
+    if let Some(capture) = capture {
+        if let Err(err) = capture.record(direction, data) {
+            warn!("Failed to write to the capture file: {err}");
+        }
     }
 }
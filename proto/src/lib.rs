@@ -1,7 +1,9 @@
 #[cfg(feature = "protocol")]
 pub mod protocol;
 
+#[cfg(feature = "hub")]
+pub mod capture;
 #[cfg(feature = "hub")]
 pub mod hub;
 #[cfg(feature = "hub")]
-pub use hub::{Hub, DEFAULT_DEVICE_PORT};
+pub use hub::{Hub, Message, Session, SessionWriter, DEFAULT_DEVICE_PORT};
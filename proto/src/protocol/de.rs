@@ -1,4 +1,7 @@
-use super::error::{Error, Result};
+use super::{
+    error::{Error, Position, Result},
+    BlockType, EndPrelude,
+};
 use serde::{
     de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor},
     forward_to_deserialize_any, Deserialize,
@@ -6,6 +9,7 @@ use serde::{
 use std::ops::{AddAssign, MulAssign};
 
 pub struct Deserializer<'de> {
+    original: &'de str,
     input: &'de str,
 }
 
@@ -13,12 +17,67 @@ pub fn from_str<'a, T>(s: &'a str) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer { input: s };
-    let t = T::deserialize(&mut deserializer)?;
+    let mut deserializer = Deserializer::new(s);
+    let t = T::deserialize(&mut deserializer).map_err(|err| deserializer.locate(err))?;
+    Ok(t)
+}
+
+/// Deserialize a single block, like the ones the device sends when its state changes.
+pub fn block_from_str(s: &str) -> Result<BlockType> {
+    let header = s.lines().next().unwrap_or_default();
+    let name = header
+        .strip_suffix(':')
+        .ok_or_else(|| Error::ExpectedBlockHeader(header.to_string()))?;
+    // The content of the block starts after the header and its '\n'
+    let body_start = (header.len() + 1).min(s.len());
+    let block = match name {
+        "PROTOCOL PREAMBLE" => BlockType::ProtocolPreamble(from_block_body(s, body_start)?),
+        "VIDEOHUB DEVICE" => BlockType::DeviceInfo(from_block_body(s, body_start)?),
+        "INPUT LABELS" => BlockType::InputLabels(from_block_body(s, body_start)?),
+        "OUTPUT LABELS" => BlockType::OutputLabels(from_block_body(s, body_start)?),
+        "VIDEO OUTPUT LOCKS" => BlockType::VideoOutputLocks(from_block_body(s, body_start)?),
+        "VIDEO OUTPUT ROUTING" => BlockType::VideoOutputRouting(from_block_body(s, body_start)?),
+        "VIDEO INPUT STATUS" => BlockType::VideoInputStatus(from_block_body(s, body_start)?),
+        "VIDEO OUTPUT STATUS" => BlockType::VideoOutputStatus(from_block_body(s, body_start)?),
+        "ALARM STATUS" => BlockType::AlarmStatus(from_block_body(s, body_start)?),
+        "CONFIGURATION" => BlockType::Configuration(from_block_body(s, body_start)?),
+        "END PRELUDE" => BlockType::EndPrelude(EndPrelude),
+        _ => return Err(Error::UnsupportedBlock(name.to_string())),
+    };
+    Ok(block)
+}
+
+// Deserialize the content of a block, keeping error positions relative to the whole block.
+fn from_block_body<'a, T>(block: &'a str, body_start: usize) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(block);
+    deserializer.input = &block[body_start..];
+    let t = T::deserialize(&mut deserializer).map_err(|err| deserializer.locate(err))?;
     Ok(t)
 }
 
 impl<'de> Deserializer<'de> {
+    pub fn new(input: &'de str) -> Self {
+        Deserializer {
+            original: input,
+            input,
+        }
+    }
+
+    // Attach the current position in the input to an error.
+    fn locate(&self, err: Error) -> Error {
+        if err.position().is_some() {
+            return err;
+        }
+        let offset = self.original.len() - self.input.len();
+        Error::Located {
+            position: Position::locate(self.original, offset),
+            error: Box::new(err),
+        }
+    }
+
     // Look at the first character in the input without consuming it.
     fn peek_char(&mut self) -> Result<char> {
         self.input.chars().next().ok_or(Error::Eof)
@@ -47,12 +106,13 @@ impl<'de> Deserializer<'de> {
     where
         T: AddAssign<T> + MulAssign<T> + From<u8>,
     {
-        let mut int = match self.next_char()? {
+        let mut int = match self.peek_char()? {
             ch @ '0'..='9' => T::from(ch as u8 - b'0'),
             _ => {
                 return Err(Error::ExpectedInteger);
             }
         };
+        self.next_char()?;
         loop {
             match self.input.chars().next() {
                 Some(ch @ '0'..='9') => {
@@ -263,9 +323,9 @@ impl<'de, 'a> SeqAccess<'de> for NewLineSeparated<'a, 'de> {
             return Ok(None);
         }
 
-        let result = seed.deserialize(&mut *self.de).map(Some);
+        let result = seed.deserialize(&mut *self.de)?;
         self.de.next_char()?; // consume the '\n' between elements
-        result
+        Ok(Some(result))
     }
 }
 
@@ -292,9 +352,9 @@ impl<'de, 'a> MapAccess<'de> for NewLineSeparated<'a, 'de> {
         }
 
         self.de.next_char()?; // remove the whitespace between ':' and the value
-        let result = seed.deserialize(&mut *self.de);
+        let result = seed.deserialize(&mut *self.de)?;
         self.de.next_char()?; // consume the \n
-        result
+        Ok(result)
     }
 }
 
@@ -319,12 +379,12 @@ impl<'de, 'a> SeqAccess<'de> for SpaceSeparated<'a, 'de> {
             return Ok(None);
         }
 
-        let result = seed.deserialize(&mut *self.de).map(Some);
+        let result = seed.deserialize(&mut *self.de)?;
         if !self.de.input.is_empty() && self.de.peek_char()? == ' ' {
             self.de.next_char()?; // consume the whitespace between elements
         }
 
-        result
+        Ok(Some(result))
     }
 }
 
@@ -493,6 +553,10 @@ mod test {
                 Route(3, 28),
                 Route(97, 45),
             ],
+            // Only sent by some models
+            video_input_status: vec![],
+            video_output_status: vec![],
+            alarm_status: Alarms::new(),
             configuration: Configuration { take_mode: true },
             end_prelude: EndPrelude,
         };
@@ -540,4 +604,147 @@ mod test {
         let result: HubInfo = from_str(s).unwrap();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_hub_info_status() {
+        let s = "PROTOCOL PREAMBLE:\n\
+                       Version: 2.7\n\
+                       \n\
+                       VIDEOHUB DEVICE:\n\
+                       Device present: true\n\
+                       Model name: Some model name\n\
+                       Friendly name: Bar\n\
+                       Unique ID: XXXX\n\
+                       Video inputs: 2\n\
+                       Video processing units: 0\n\
+                       Video outputs: 1\n\
+                       Video monitoring outputs: 0\n\
+                       Serial ports: 0\n\
+                       \n\
+                       INPUT LABELS:\n\
+                       0 Foo 0\n\
+                       1 Foo 1\n\
+                       \n\
+                       OUTPUT LABELS:\n\
+                       0 Bar 0\n\
+                       \n\
+                       VIDEO OUTPUT LOCKS:\n\
+                       0 U\n\
+                       \n\
+                       VIDEO OUTPUT ROUTING:\n\
+                       0 1\n\
+                       \n\
+                       VIDEO INPUT STATUS:\n\
+                       0 BNC\n\
+                       1 None\n\
+                       \n\
+                       VIDEO OUTPUT STATUS:\n\
+                       0 Optical\n\
+                       \n\
+                       ALARM STATUS:\n\
+                       Fan 1: OK\n\
+                       Power supply 2: Failed\n\
+                       \n\
+                       CONFIGURATION:\n\
+                       Take Mode: false\n\
+                       \n\
+                       END PRELUDE:\n\
+                       \n";
+        let result: HubInfo = from_str(s).unwrap();
+        assert_eq!(
+            result.video_input_status,
+            [
+                PortStatus(0, "BNC".to_string()),
+                PortStatus(1, "None".to_string())
+            ]
+        );
+        assert_eq!(
+            result.video_output_status,
+            [PortStatus(0, "Optical".to_string())]
+        );
+        assert_eq!(
+            result.alarm_status,
+            Alarms::from([
+                ("Fan 1".to_string(), "OK".to_string()),
+                ("Power supply 2".to_string(), "Failed".to_string()),
+            ])
+        );
+        assert_eq!(result.configuration, Configuration { take_mode: false });
+    }
+
+    #[test]
+    fn test_block() {
+        let block = block_from_str("VIDEO OUTPUT ROUTING:\n3 5\n4 6\n\n").unwrap();
+        assert!(matches!(
+            block,
+            BlockType::VideoOutputRouting(routes) if routes == vec![Route(3, 5), Route(4, 6)]
+        ));
+        let block = block_from_str("CONFIGURATION:\nTake Mode: true\n\n").unwrap();
+        assert!(matches!(
+            block,
+            BlockType::Configuration(Configuration { take_mode: true })
+        ));
+        let block = block_from_str("VIDEO OUTPUT STATUS:\n0 Optical\n\n").unwrap();
+        assert!(matches!(
+            block,
+            BlockType::VideoOutputStatus(statuses) if statuses == vec![PortStatus(0, "Optical".to_string())]
+        ));
+        let block = block_from_str("ALARM STATUS:\nFan 1: OK\n\n").unwrap();
+        assert!(matches!(
+            block,
+            BlockType::AlarmStatus(alarms) if alarms["Fan 1"] == "OK"
+        ));
+        let block = block_from_str("END PRELUDE:\n\n").unwrap();
+        assert!(matches!(block, BlockType::EndPrelude(_)));
+    }
+
+    #[test]
+    fn test_block_errors() {
+        assert!(matches!(
+            block_from_str("ACK\n\n"),
+            Err(Error::ExpectedBlockHeader(header)) if header == "ACK"
+        ));
+        assert!(matches!(
+            block_from_str("SERIAL PORT LABELS:\n0 Foo\n\n"),
+            Err(Error::UnsupportedBlock(name)) if name == "SERIAL PORT LABELS"
+        ));
+        let err = block_from_str("INPUT LABELS:\n0 Foo\nBar\n\n").unwrap_err();
+        let position = err.position().unwrap();
+        assert_eq!(position.block.as_deref(), Some("INPUT LABELS"));
+        assert_eq!(position.line, 3);
+        assert_eq!(position.excerpt, "Bar");
+    }
+
+    #[test]
+    fn test_error_position() {
+        let s = "Version: 2.3\n\n";
+        let err = from_str::<DeviceInfo>(s).unwrap_err();
+        let position = err.position().unwrap();
+        assert_eq!(position.block, None);
+        assert_eq!(position.line, 1);
+        assert_eq!(position.excerpt, "Version: 2.3");
+    }
+
+    #[test]
+    fn test_error_position_in_block() {
+        let s = "PROTOCOL PREAMBLE:\n\
+                       Version: 2.3\n\
+                       \n\
+                       VIDEOHUB DEVICE:\n\
+                       Device present: true\n\
+                       Model name: Some model name\n\
+                       Friendly name: Bar\n\
+                       Unique ID: XXXX\n\
+                       Video inputs: forty\n";
+        let err = from_str::<HubInfo>(s).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Located { ref error, .. } if matches!(**error, Error::ExpectedInteger)
+        ));
+        let position = err.position().unwrap();
+        assert_eq!(position.block.as_deref(), Some("VIDEOHUB DEVICE"));
+        assert_eq!(position.line, 9);
+        assert_eq!(position.column, 15);
+        assert_eq!(position.excerpt, "Video inputs: forty");
+    }
 }
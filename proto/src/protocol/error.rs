@@ -1,9 +1,60 @@
-use serde::{de, ser};
+use serde::{de, ser, Deserialize, Serialize};
 use std::{fmt, num::ParseIntError};
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Location of a deserialization error in the text received from the device.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Position {
+    /// Name of the block being parsed, when the error happened inside a block
+    pub block: Option<String>,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number (in characters), starting at 1
+    pub column: usize,
+    /// The full line of input where the error happened
+    pub excerpt: String,
+}
+
+impl Position {
+    /// Compute the position of the byte `offset` inside `input`.
+    pub fn locate(input: &str, offset: usize) -> Self {
+        let consumed = &input[..offset];
+        let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+
+        // Blocks are separated by a blank line and start with an ALL CAPS header ending with ':'
+        let block_start = consumed.rfind("\n\n").map_or(0, |i| i + 2);
+        let block = input[block_start..]
+            .lines()
+            .next()
+            .and_then(|header| header.strip_suffix(':'))
+            .filter(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == ' ')
+            })
+            .map(str::to_string);
+
+        Self {
+            block,
+            line: consumed.matches('\n').count() + 1,
+            column: consumed[line_start..].chars().count() + 1,
+            excerpt: input[line_start..line_end].to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(block) = &self.block {
+            write!(f, "block '{block}', ")?;
+        }
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("The string {0} can't be converted to a valid DevicePresent variant")]
@@ -32,8 +83,27 @@ pub enum Error {
     UnsupportedType,
     #[error("Expected a colon after a key")]
     ExpectedMapColon,
+    #[error("Expected a block header ending with a colon, but found '{0}'")]
+    ExpectedBlockHeader(String),
+    #[error("The block {0} is not supported")]
+    UnsupportedBlock(String),
     #[error("Failed parsing an int from a string")]
     ParseInt(#[from] ParseIntError),
+    #[error("{error} ({position})")]
+    Located {
+        position: Position,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Where the error happened in the input, if it is known.
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            Error::Located { position, .. } => Some(position),
+            _ => None,
+        }
+    }
 }
 
 impl ser::Error for Error {
@@ -9,6 +9,7 @@ pub mod error;
 pub mod ser;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[serde(rename = "")]
@@ -86,6 +87,13 @@ impl Default for LockStatus {
 #[serde(rename = "")]
 pub struct Route(pub usize, pub usize); // (dst, src)
 
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename = "")]
+pub struct PortStatus(pub usize, pub String); // (id, interface), None when nothing is connected
+
+/// The state of the sensors of the device, like "Fan 1: OK", by name
+pub type Alarms = BTreeMap<String, String>;
+
 // Configuraton
 #[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[serde(rename = "")]
@@ -112,12 +120,112 @@ pub struct HubInfo {
     pub video_output_locks: Vec<OutputLock>,
     #[serde(rename = "VIDEO OUTPUT ROUTING")]
     pub video_output_routing: Vec<Route>,
+    // Only sent by some models
+    #[serde(
+        rename = "VIDEO INPUT STATUS",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub video_input_status: Vec<PortStatus>,
+    #[serde(
+        rename = "VIDEO OUTPUT STATUS",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub video_output_status: Vec<PortStatus>,
+    #[serde(
+        rename = "ALARM STATUS",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub alarm_status: Alarms,
     #[serde(rename = "CONFIGURATION")]
     pub configuration: Configuration,
     #[serde(rename = "END PRELUDE")]
     end_prelude: EndPrelude,
 }
 
+impl HubInfo {
+    /// Update the state with a block sent by the device. The blocks sent after the prelude only
+    /// hold the entries that changed.
+    pub fn apply(&mut self, block: BlockType) {
+        match block {
+            BlockType::ProtocolPreamble(protocol_preamble) => {
+                self.protocol_preamble = protocol_preamble
+            }
+            BlockType::DeviceInfo(device_info) => self.device_info = device_info,
+            BlockType::InputLabels(labels) => merge(&mut self.input_labels, labels, |l| l.0),
+            BlockType::OutputLabels(labels) => merge(&mut self.output_labels, labels, |l| l.0),
+            BlockType::VideoOutputLocks(locks) => {
+                merge(&mut self.video_output_locks, locks, |l| l.0)
+            }
+            BlockType::VideoOutputRouting(routes) => {
+                merge(&mut self.video_output_routing, routes, |r| r.0)
+            }
+            BlockType::VideoInputStatus(statuses) => {
+                merge(&mut self.video_input_status, statuses, |s| s.0)
+            }
+            BlockType::VideoOutputStatus(statuses) => {
+                merge(&mut self.video_output_status, statuses, |s| s.0)
+            }
+            BlockType::AlarmStatus(alarms) => self.alarm_status.extend(alarms),
+            BlockType::Configuration(configuration) => self.configuration = configuration,
+            BlockType::EndPrelude(_) => {}
+        }
+    }
+
+    /// The lock of an output as reported to this connection: `Owned` when the lock is held by this
+    /// connection, `Locked` when it is held by another panel.
+    pub fn output_lock(&self, output: usize) -> Option<&LockStatus> {
+        self.video_output_locks
+            .iter()
+            .find(|OutputLock(id, _)| *id == output)
+            .map(|OutputLock(_, lock_status)| lock_status)
+    }
+
+    /// Whether the route of an output can only be changed by another panel.
+    pub fn is_locked_by_other(&self, output: usize) -> bool {
+        self.output_lock(output) == Some(&LockStatus::Locked)
+    }
+
+    /// The blocks of the prelude the device sends for this state.
+    pub fn blocks(&self) -> Vec<BlockType> {
+        let mut blocks = vec![
+            BlockType::ProtocolPreamble(self.protocol_preamble.clone()),
+            BlockType::DeviceInfo(self.device_info.clone()),
+            BlockType::InputLabels(self.input_labels.clone()),
+            BlockType::OutputLabels(self.output_labels.clone()),
+            BlockType::VideoOutputLocks(self.video_output_locks.clone()),
+            BlockType::VideoOutputRouting(self.video_output_routing.clone()),
+        ];
+        // Not sent by the models without them
+        if !self.video_input_status.is_empty() {
+            blocks.push(BlockType::VideoInputStatus(self.video_input_status.clone()));
+        }
+        if !self.video_output_status.is_empty() {
+            blocks.push(BlockType::VideoOutputStatus(
+                self.video_output_status.clone(),
+            ));
+        }
+        if !self.alarm_status.is_empty() {
+            blocks.push(BlockType::AlarmStatus(self.alarm_status.clone()));
+        }
+        blocks.push(BlockType::Configuration(self.configuration.clone()));
+        blocks.push(BlockType::EndPrelude(EndPrelude));
+        blocks
+    }
+}
+
+// Replace the entries having the same id, add the others
+fn merge<T>(entries: &mut Vec<T>, updates: Vec<T>, id: impl Fn(&T) -> usize) {
+    for update in updates {
+        match entries.iter_mut().find(|entry| id(entry) == id(&update)) {
+            Some(entry) => *entry = update,
+            None => entries.push(update),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub enum BlockType {
     #[serde(rename(serialize = "PROTOCOL PREAMBLE:\n"))]
@@ -132,6 +240,12 @@ pub enum BlockType {
     VideoOutputLocks(Vec<OutputLock>),
     #[serde(rename(serialize = "VIDEO OUTPUT ROUTING:\n"))]
     VideoOutputRouting(Vec<Route>),
+    #[serde(rename(serialize = "VIDEO INPUT STATUS:\n"))]
+    VideoInputStatus(Vec<PortStatus>),
+    #[serde(rename(serialize = "VIDEO OUTPUT STATUS:\n"))]
+    VideoOutputStatus(Vec<PortStatus>),
+    #[serde(rename(serialize = "ALARM STATUS:\n"))]
+    AlarmStatus(Alarms),
     #[serde(rename(serialize = "CONFIGURATION:\n"))]
     Configuration(Configuration),
     #[serde(rename(serialize = "END PRELUDE:\n"))]
@@ -181,3 +295,76 @@ impl Display for LockStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut hub_info = HubInfo {
+            input_labels: vec![Label(0, "Foo".to_string()), Label(1, "Bar".to_string())],
+            video_output_routing: vec![Route(0, 1), Route(1, 1)],
+            ..Default::default()
+        };
+        hub_info.apply(BlockType::InputLabels(vec![Label(1, "Baz".to_string())]));
+        hub_info.apply(BlockType::VideoOutputRouting(vec![
+            Route(0, 0),
+            Route(2, 1),
+        ]));
+        hub_info.apply(BlockType::Configuration(Configuration { take_mode: true }));
+
+        assert_eq!(
+            hub_info.input_labels,
+            vec![Label(0, "Foo".to_string()), Label(1, "Baz".to_string())]
+        );
+        assert_eq!(
+            hub_info.video_output_routing,
+            vec![Route(0, 0), Route(1, 1), Route(2, 1)]
+        );
+        assert!(hub_info.configuration.take_mode);
+    }
+
+    #[test]
+    fn test_output_lock() {
+        let hub_info = HubInfo {
+            video_output_locks: vec![
+                OutputLock(0, LockStatus::Owned),
+                OutputLock(1, LockStatus::Locked),
+                OutputLock(2, LockStatus::Unlocked),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(hub_info.output_lock(0), Some(&LockStatus::Owned));
+        assert_eq!(hub_info.output_lock(3), None);
+        assert!(!hub_info.is_locked_by_other(0));
+        assert!(hub_info.is_locked_by_other(1));
+        assert!(!hub_info.is_locked_by_other(2));
+        assert!(!hub_info.is_locked_by_other(3));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let hub_info = HubInfo {
+            input_labels: vec![Label(0, "Foo".to_string())],
+            output_labels: vec![Label(0, "Bar".to_string())],
+            video_output_locks: vec![OutputLock(0, LockStatus::Owned)],
+            video_output_routing: vec![Route(0, 1)],
+            video_input_status: vec![PortStatus(0, "BNC".to_string())],
+            alarm_status: Alarms::from([("Fan 1".to_string(), "OK".to_string())]),
+            configuration: Configuration { take_mode: true },
+            ..Default::default()
+        };
+        let blocks = hub_info.blocks();
+        assert!(!blocks
+            .iter()
+            .any(|block| matches!(block, BlockType::VideoOutputStatus(_))));
+        assert!(matches!(blocks.last(), Some(BlockType::EndPrelude(_))));
+
+        let mut applied = HubInfo::default();
+        for block in blocks {
+            applied.apply(block);
+        }
+        assert_eq!(applied, hub_info);
+    }
+}
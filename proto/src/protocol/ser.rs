@@ -433,6 +433,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_status() {
+        let status = BlockType::VideoInputStatus(vec![
+            PortStatus(0, "BNC".to_string()),
+            PortStatus(1, "None".to_string()),
+        ]);
+        let result = to_string(&status).unwrap();
+        assert_eq!(&result, "VIDEO INPUT STATUS:\n0 BNC\n1 None\n\n");
+        let alarms = BlockType::AlarmStatus(Alarms::from([
+            ("Fan 1".to_string(), "OK".to_string()),
+            ("Power supply 1".to_string(), "Failed".to_string()),
+        ]));
+        let result = to_string(&alarms).unwrap();
+        assert_eq!(
+            &result,
+            "ALARM STATUS:\nFan 1: OK\nPower supply 1: Failed\n\n"
+        );
+    }
+
     #[test]
     fn test_end_prelude() {
         let end = BlockType::EndPrelude(EndPrelude);
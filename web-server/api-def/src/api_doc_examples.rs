@@ -27,3 +27,12 @@ pub fn example_output_ports() -> &'static str {
     "source_port": 16
   }"#
 }
+
+pub fn example_hub() -> &'static str {
+    r#"{
+    "name": "studio-a",
+    "address": "10.26.135.201",
+    "port": 9990,
+    "reachable": true
+  }"#
+}
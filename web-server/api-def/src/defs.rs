@@ -1,6 +1,10 @@
-use super::api_doc_examples::{example_device_info, example_input_ports, example_output_ports};
+use super::api_doc_examples::{
+    example_device_info, example_hub, example_input_ports, example_output_ports,
+};
+use chrono::{DateTime, Utc};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::{cell::Cell, collections::HashMap};
 use videohub_proto::protocol;
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -33,7 +37,7 @@ impl From<DevicePresent> for protocol::DevicePresent {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
 pub enum LockStatus {
     #[serde(rename = "force_unlock")]
     ForceUnlock,
@@ -100,7 +104,7 @@ impl From<protocol::DeviceInfo> for DeviceInfo {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
 #[schemars(example = "example_output_ports")]
 pub struct OutputPort {
     #[serde(rename = "port_number")]
@@ -113,7 +117,7 @@ pub struct OutputPort {
     pub input_port: Option<usize>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
 #[schemars(example = "example_input_ports")]
 pub struct InputPort {
     #[serde(rename = "port_number")]
@@ -122,6 +126,138 @@ pub struct InputPort {
     pub label: String,
 }
 
+/// The input ports of a videohub.
+pub fn input_ports(hub_info: &protocol::HubInfo) -> Vec<InputPort> {
+    hub_info
+        .input_labels
+        .iter()
+        .map(|protocol::Label(id, label)| InputPort {
+            id: *id,
+            label: label.clone(),
+        })
+        .collect()
+}
+
+/// The output ports of a videohub, with all their fields set.
+pub fn output_ports(hub_info: &protocol::HubInfo) -> Vec<OutputPort> {
+    hub_info
+        .output_labels
+        .iter()
+        .zip(&hub_info.video_output_locks)
+        .zip(&hub_info.video_output_routing)
+        .map(
+            |(
+                (protocol::Label(_, output_label), protocol::OutputLock(_, lock_state)),
+                protocol::Route(dst, src),
+            )| OutputPort {
+                id: *dst,
+                input_port: Some(*src),
+                label: Some(output_label.clone()),
+                lock_state: Some(LockStatus::from(lock_state.clone())),
+            },
+        )
+        .collect()
+}
+
+/// The blocks to send to a videohub to apply the fields set in the output ports: the labels, the
+/// locks and then the routes, each in a single block.
+pub fn output_blocks(output_ports: &[OutputPort]) -> Vec<protocol::BlockType> {
+    let labels: Vec<protocol::Label> = output_ports
+        .iter()
+        .filter_map(|output_port| {
+            output_port
+                .label
+                .as_ref()
+                .map(|label| protocol::Label(output_port.id, label.to_string()))
+        })
+        .collect();
+    let lock_statuses: Vec<protocol::OutputLock> = output_ports
+        .iter()
+        .filter_map(|output_port| {
+            output_port.lock_state.as_ref().map(|lock_state| {
+                protocol::OutputLock(output_port.id, lock_state.to_owned().into())
+            })
+        })
+        .collect();
+    let routes: Vec<protocol::Route> = output_ports
+        .iter()
+        .filter_map(|output_port| {
+            output_port
+                .input_port
+                .map(|input_port| protocol::Route(output_port.id, input_port))
+        })
+        .collect();
+
+    let mut blocks = vec![];
+    if !labels.is_empty() {
+        blocks.push(protocol::BlockType::OutputLabels(labels));
+    }
+    if !lock_statuses.is_empty() {
+        blocks.push(protocol::BlockType::VideoOutputLocks(lock_statuses));
+    }
+    if !routes.is_empty() {
+        blocks.push(protocol::BlockType::VideoOutputRouting(routes));
+    }
+    blocks
+}
+
+/// A change reported by a videohub
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(tag = "type", content = "ports", rename_all = "snake_case")]
+pub enum HubEvent {
+    /// The input ports with a new label
+    InputPorts(Vec<InputPort>),
+    /// The output ports that changed, only the fields that changed are set
+    OutputPorts(Vec<OutputPort>),
+}
+
+impl HubEvent {
+    pub fn from_block(block: protocol::BlockType) -> Option<Self> {
+        let output_port = |id| OutputPort {
+            id,
+            label: None,
+            lock_state: None,
+            input_port: None,
+        };
+        match block {
+            protocol::BlockType::InputLabels(labels) => Some(Self::InputPorts(
+                labels
+                    .into_iter()
+                    .map(|protocol::Label(id, label)| InputPort { id, label })
+                    .collect(),
+            )),
+            protocol::BlockType::OutputLabels(labels) => Some(Self::OutputPorts(
+                labels
+                    .into_iter()
+                    .map(|protocol::Label(id, label)| OutputPort {
+                        label: Some(label),
+                        ..output_port(id)
+                    })
+                    .collect(),
+            )),
+            protocol::BlockType::VideoOutputLocks(locks) => Some(Self::OutputPorts(
+                locks
+                    .into_iter()
+                    .map(|protocol::OutputLock(id, lock_state)| OutputPort {
+                        lock_state: Some(lock_state.into()),
+                        ..output_port(id)
+                    })
+                    .collect(),
+            )),
+            protocol::BlockType::VideoOutputRouting(routes) => Some(Self::OutputPorts(
+                routes
+                    .into_iter()
+                    .map(|protocol::Route(dst, src)| OutputPort {
+                        input_port: Some(src),
+                        ..output_port(dst)
+                    })
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+}
+
 // Configuraton
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Configuration {
@@ -135,3 +271,1015 @@ impl From<protocol::Configuration> for Configuration {
         }
     }
 }
+
+impl From<Configuration> for protocol::Configuration {
+    fn from(rhs: Configuration) -> Self {
+        Self {
+            take_mode: rhs.take_mode,
+        }
+    }
+}
+
+/// A videohub managed by the server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[schemars(example = "example_hub")]
+pub struct HubSummary {
+    /// Name used to address the hub in the URLs: /hubs/<name>/...
+    pub name: String,
+    pub address: String,
+    pub port: u16,
+    /// Whether the server has a session opened with the device
+    pub reachable: bool,
+}
+
+/// How fresh the state of a videohub mirrored by the server is
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct CacheState {
+    /// The session with the device is lost or it stopped answering, the state may be outdated
+    pub stale: bool,
+    /// Seconds since the device last confirmed the state
+    pub age: u64,
+}
+
+/// The server process is alive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Health {
+    pub version: String,
+    /// Seconds since the server started
+    pub uptime: u64,
+}
+
+/// Whether the server can serve an up to date state of its videohubs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Readiness {
+    /// Whether all the videohubs are ready
+    pub ready: bool,
+    pub hubs: Vec<HubReadiness>,
+}
+
+/// Whether the server mirrors the current state of a videohub
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct HubReadiness {
+    pub name: String,
+    pub ready: bool,
+    /// Whether the server has a session opened with the device
+    pub reachable: bool,
+    /// Seconds since the device last sent something, None when it never did
+    pub last_seen: Option<u64>,
+    /// Why the videohub is not ready
+    pub reason: Option<String>,
+}
+
+/// Location of an error in the data received from the videohub device
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ErrorPosition {
+    /// Name of the protocol block that failed to be parsed
+    pub block: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    /// The offending line received from the device
+    pub excerpt: String,
+}
+
+impl From<protocol::error::Position> for ErrorPosition {
+    fn from(rhs: protocol::error::Position) -> Self {
+        Self {
+            block: rhs.block,
+            line: rhs.line,
+            column: rhs.column,
+            excerpt: rhs.excerpt,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ApiError {
+    pub message: String,
+    /// Set when the error comes from data the device sent that could not be understood
+    pub position: Option<ErrorPosition>,
+}
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can only read the state of the videohubs
+    Viewer,
+    /// Can also route, label and lock the outputs they are allowed to
+    Operator,
+    /// Can change everything
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct UserInfo {
+    pub name: String,
+    pub role: Role,
+    /// The outputs the user can change, per hub name. All of them when not set.
+    pub outputs: Option<HashMap<String, Vec<usize>>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Credentials {
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Login {
+    /// To send in the `Authorization: Bearer <token>` header. It is also set in a cookie.
+    pub token: String,
+    pub user: UserInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    InputLabel,
+    OutputLabel,
+    OutputLock,
+    Route,
+    TakeMode,
+    DevicePresent,
+}
+
+/// A value of a videohub which differs from the one wanted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Difference {
+    pub kind: ChangeKind,
+    /// The output port, or the input port for an input label. 0 for the take mode and the device
+    /// presence.
+    pub port: usize,
+    /// Not set when the port is unknown to the videohub
+    pub current: Option<String>,
+    pub target: String,
+}
+
+/// The differences between the state of a videohub and the values of a block.
+pub fn differences(hub_info: &protocol::HubInfo, block: &protocol::BlockType) -> Vec<Difference> {
+    let difference = |kind, port, current: Option<String>, target: String| {
+        (current.as_ref() != Some(&target)).then_some(Difference {
+            kind,
+            port,
+            current,
+            target,
+        })
+    };
+    let label = |labels: &[protocol::Label], id| {
+        labels
+            .iter()
+            .find(|label| label.0 == id)
+            .map(|label| label.1.clone())
+    };
+    match block {
+        protocol::BlockType::InputLabels(labels) => labels
+            .iter()
+            .filter_map(|protocol::Label(id, target)| {
+                let current = label(&hub_info.input_labels, *id);
+                difference(ChangeKind::InputLabel, *id, current, target.clone())
+            })
+            .collect(),
+        protocol::BlockType::OutputLabels(labels) => labels
+            .iter()
+            .filter_map(|protocol::Label(id, target)| {
+                let current = label(&hub_info.output_labels, *id);
+                difference(ChangeKind::OutputLabel, *id, current, target.clone())
+            })
+            .collect(),
+        protocol::BlockType::VideoOutputLocks(locks) => locks
+            .iter()
+            .filter_map(|protocol::OutputLock(id, target)| {
+                let current = hub_info
+                    .video_output_locks
+                    .iter()
+                    .find(|lock| lock.0 == *id)
+                    .map(|lock| lock_name(&lock.1));
+                difference(ChangeKind::OutputLock, *id, current, lock_name(target))
+            })
+            .collect(),
+        protocol::BlockType::VideoOutputRouting(routes) => routes
+            .iter()
+            .filter_map(|protocol::Route(dst, src)| {
+                let current = hub_info
+                    .video_output_routing
+                    .iter()
+                    .find(|route| route.0 == *dst)
+                    .map(|route| route.1.to_string());
+                difference(ChangeKind::Route, *dst, current, src.to_string())
+            })
+            .collect(),
+        protocol::BlockType::DeviceInfo(device_info) => {
+            let current = device_present_name(&hub_info.device_info.device_present);
+            let target = device_present_name(&device_info.device_present);
+            difference(ChangeKind::DevicePresent, 0, Some(current), target)
+                .into_iter()
+                .collect()
+        }
+        protocol::BlockType::Configuration(configuration) => {
+            let current = hub_info.configuration.take_mode.to_string();
+            let target = configuration.take_mode.to_string();
+            difference(ChangeKind::TakeMode, 0, Some(current), target)
+                .into_iter()
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The differences between two states of a videohub.
+pub fn state_differences(
+    current: &protocol::HubInfo,
+    target: &protocol::HubInfo,
+) -> Vec<Difference> {
+    [
+        protocol::BlockType::DeviceInfo(target.device_info.clone()),
+        protocol::BlockType::InputLabels(target.input_labels.clone()),
+        protocol::BlockType::OutputLabels(target.output_labels.clone()),
+        protocol::BlockType::VideoOutputLocks(target.video_output_locks.clone()),
+        protocol::BlockType::VideoOutputRouting(target.video_output_routing.clone()),
+        protocol::BlockType::Configuration(target.configuration.clone()),
+    ]
+    .iter()
+    .flat_map(|block| differences(current, block))
+    .collect()
+}
+
+/// The block holding only the values which differ from the state of a videohub, None when none
+/// differ.
+pub fn minimal_block(
+    hub_info: &protocol::HubInfo,
+    block: &protocol::BlockType,
+) -> Option<protocol::BlockType> {
+    fn changed<T: Clone>(
+        hub_info: &protocol::HubInfo,
+        entries: &[T],
+        block: fn(Vec<T>) -> protocol::BlockType,
+    ) -> Option<protocol::BlockType> {
+        let entries: Vec<T> = entries
+            .iter()
+            .filter(|entry| !differences(hub_info, &block(vec![(*entry).clone()])).is_empty())
+            .cloned()
+            .collect();
+        (!entries.is_empty()).then(|| block(entries))
+    }
+    match block {
+        protocol::BlockType::InputLabels(labels) => {
+            changed(hub_info, labels, protocol::BlockType::InputLabels)
+        }
+        protocol::BlockType::OutputLabels(labels) => {
+            changed(hub_info, labels, protocol::BlockType::OutputLabels)
+        }
+        protocol::BlockType::VideoOutputLocks(locks) => {
+            changed(hub_info, locks, protocol::BlockType::VideoOutputLocks)
+        }
+        protocol::BlockType::VideoOutputRouting(routes) => {
+            changed(hub_info, routes, protocol::BlockType::VideoOutputRouting)
+        }
+        block => (!differences(hub_info, block).is_empty()).then(|| block.clone()),
+    }
+}
+
+// Same names as the serialized DevicePresent
+fn device_present_name(device_present: &protocol::DevicePresent) -> String {
+    match device_present {
+        protocol::DevicePresent::Present => "present",
+        protocol::DevicePresent::NotPresent => "not_present",
+        protocol::DevicePresent::NeedUpdate => "need_update",
+    }
+    .to_string()
+}
+
+// Same names as the serialized LockStatus
+fn lock_name(lock_status: &protocol::LockStatus) -> String {
+    match lock_status {
+        protocol::LockStatus::ForceUnlock => "force_unlock",
+        protocol::LockStatus::Locked => "locked",
+        protocol::LockStatus::Owned => "owned",
+        protocol::LockStatus::Unlocked => "unlocked",
+    }
+    .to_string()
+}
+
+/// A change of the state of a videohub
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub hub: String,
+    /// Who made the change through the API, not set for the changes made from other panels
+    pub user: Option<String>,
+    /// Address of the client which made the change through the API
+    pub client: Option<String>,
+    pub kind: ChangeKind,
+    /// The output port, or the input port for an input label. 0 for the take mode and the device
+    /// presence.
+    pub port: usize,
+    /// Not set when the previous value is unknown
+    pub before: Option<String>,
+    pub after: String,
+}
+
+/// Routes, and optionally labels and locks, of some outputs to recall at once
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Salvo {
+    pub name: String,
+    /// The fields which are not set are left untouched by the recall
+    pub outputs: Vec<OutputPort>,
+    /// The user who last saved the salvo on the server. Set by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl Salvo {
+    /// Take the current routes of some outputs. The locks of the other panels are not taken, they
+    /// can not be recalled.
+    pub fn capture(hub_info: &protocol::HubInfo, capture: &SalvoCapture) -> Self {
+        let outputs = output_ports(hub_info)
+            .into_iter()
+            .filter(|output_port| {
+                capture
+                    .outputs
+                    .as_ref()
+                    .is_none_or(|outputs| outputs.contains(&output_port.id))
+            })
+            .map(|output_port| OutputPort {
+                label: output_port.label.filter(|_| capture.labels),
+                lock_state: output_port
+                    .lock_state
+                    .filter(|lock_state| capture.locks && *lock_state != LockStatus::Locked),
+                ..output_port
+            })
+            .collect();
+        Self {
+            name: capture.name.clone(),
+            outputs,
+            owner: None,
+        }
+    }
+
+    /// The blocks to send to recall the salvo, the routes are all in a single block. The locks of
+    /// the other panels are left out, like for the backups.
+    pub fn blocks(&self) -> Vec<protocol::BlockType> {
+        let output_ports: Vec<OutputPort> = self
+            .outputs
+            .iter()
+            .cloned()
+            .map(|output_port| OutputPort {
+                lock_state: output_port
+                    .lock_state
+                    .filter(|lock_state| *lock_state != LockStatus::Locked),
+                ..output_port
+            })
+            .collect();
+        output_blocks(&output_ports)
+    }
+}
+
+/// What to take from the current state in a new salvo
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct SalvoCapture {
+    pub name: String,
+    /// The output ports, all of them when not set
+    pub outputs: Option<Vec<usize>>,
+    /// Also take the output labels
+    #[serde(default)]
+    pub labels: bool,
+    /// Also take the output locks
+    #[serde(default)]
+    pub locks: bool,
+}
+
+/// A route staged while the videohub is in take mode, sent with the others on the take
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct PendingRoute {
+    pub output: usize,
+    pub input: usize,
+}
+
+impl From<PendingRoute> for protocol::Route {
+    fn from(rhs: PendingRoute) -> Self {
+        protocol::Route(rhs.output, rhs.input)
+    }
+}
+
+/// The version of the backups written, the older ones can still be restored
+pub const BACKUP_VERSION: u32 = 1;
+
+/// The state of a videohub which can be restored
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Backup {
+    /// The version of the document format
+    pub version: u32,
+    /// The device the backup was taken from, it is not restored
+    pub device_info: DeviceInfo,
+    pub input_ports: Vec<InputPort>,
+    pub output_ports: Vec<OutputPort>,
+    pub configuration: Configuration,
+    /// How the ports of the document are numbered, from 0 when not set
+    #[serde(default)]
+    pub numbering: Numbering,
+}
+
+impl From<protocol::HubInfo> for Backup {
+    fn from(rhs: protocol::HubInfo) -> Self {
+        Self {
+            version: BACKUP_VERSION,
+            input_ports: input_ports(&rhs),
+            output_ports: output_ports(&rhs),
+            device_info: DeviceInfo::from(rhs.device_info),
+            configuration: Configuration::from(rhs.configuration),
+            numbering: Numbering::ZeroBased,
+        }
+    }
+}
+
+impl Backup {
+    /// The backup of a state numbered as the protocol does, with its ports numbered for the users.
+    pub fn numbered(self, numbering: Numbering) -> Self {
+        let mut backup = numbering.to_user(self);
+        backup.numbering = numbering;
+        backup
+    }
+
+    /// The backup with its ports numbered as the protocol does, whatever their numbering.
+    pub fn into_protocol(self) -> Result<Self, String> {
+        let numbering = self.numbering;
+        let mut backup = numbering.from_user(self)?;
+        backup.numbering = Numbering::ZeroBased;
+        Ok(backup)
+    }
+
+    /// Fails on the backups written by a newer version.
+    pub fn check_version(&self) -> Result<(), String> {
+        if self.version > BACKUP_VERSION {
+            return Err(format!(
+                "Unsupported backup version {}, the latest supported is {BACKUP_VERSION}",
+                self.version
+            ));
+        }
+        Ok(())
+    }
+
+    /// The blocks to send to restore the backup on a videohub, holding only the values which
+    /// differ from its state. The outputs locked by another panel when the backup was taken are
+    /// not locked again: only the locks owned by the connection can be restored from another one.
+    pub fn blocks(&self, hub_info: &protocol::HubInfo) -> Vec<protocol::BlockType> {
+        let input_labels = self
+            .input_ports
+            .iter()
+            .map(|input_port| protocol::Label(input_port.id, input_port.label.clone()))
+            .collect();
+        let output_ports: Vec<OutputPort> = self
+            .output_ports
+            .iter()
+            .cloned()
+            .map(|output_port| OutputPort {
+                lock_state: output_port
+                    .lock_state
+                    .filter(|lock_state| *lock_state != LockStatus::Locked),
+                ..output_port
+            })
+            .collect();
+        let configuration = self.configuration.clone().into();
+        [protocol::BlockType::InputLabels(input_labels)]
+            .into_iter()
+            .chain(output_blocks(&output_ports))
+            .chain([protocol::BlockType::Configuration(configuration)])
+            .filter_map(|block| minimal_block(hub_info, &block))
+            .collect()
+    }
+
+    /// What restoring the backup would change.
+    pub fn differences(&self, hub_info: &protocol::HubInfo) -> Vec<Difference> {
+        self.blocks(hub_info)
+            .iter()
+            .flat_map(|block| differences(hub_info, block))
+            .collect()
+    }
+}
+
+/// How the columns of a table of labels are separated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableFormat {
+    #[default]
+    Csv,
+    /// Tab separated, as the spreadsheets copy and export it
+    Tsv,
+}
+
+impl TableFormat {
+    fn delimiter(self) -> u8 {
+        match self {
+            TableFormat::Csv => b',',
+            TableFormat::Tsv => b'\t',
+        }
+    }
+}
+
+/// The labels as a table of `port,label` rows, after a header.
+pub fn labels_table(labels: &[protocol::Label], format: TableFormat) -> String {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.delimiter())
+        .from_writer(vec![]);
+    // Writing to memory does not fail
+    writer.write_record(["port", "label"]).unwrap();
+    for protocol::Label(id, label) in labels {
+        writer.write_record([&id.to_string(), label]).unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+/// The labels of a table of `port,label` rows, in CSV or TSV. The first row is skipped when it is
+/// a header, starting with `port` as the tables written by [`labels_table`].
+pub fn parse_labels_table(content: &str) -> Result<Vec<protocol::Label>, String> {
+    let format = match content.lines().next() {
+        Some(line) if line.contains('\t') => TableFormat::Tsv,
+        _ => TableFormat::Csv,
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(format.delimiter())
+        .from_reader(content.as_bytes());
+    let mut labels: Vec<protocol::Label> = vec![];
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| err.to_string())?;
+        let line = record
+            .position()
+            .map_or(index + 1, |position| position.line() as usize);
+        let (Some(port), Some(label)) = (record.get(0), record.get(1)) else {
+            return Err(format!("line {line}: expected a port and a label"));
+        };
+        if index == 0 && port.trim().eq_ignore_ascii_case("port") {
+            continue;
+        }
+        let port = port
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("line {line}: '{port}' is not a port number"))?;
+        if label.contains(['\n', '\r']) {
+            return Err(format!(
+                "line {line}: the label of the port {port} has several lines"
+            ));
+        }
+        if labels.iter().any(|protocol::Label(id, _)| *id == port) {
+            return Err(format!("line {line}: the port {port} is given twice"));
+        }
+        labels.push(protocol::Label(port, label.to_string()));
+    }
+    Ok(labels)
+}
+
+/// When a scheduled job runs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// Once, at this date
+    Once(DateTime<Utc>),
+    /// Every time this cron expression matches, in the local time of the server. The seconds are
+    /// the first field, `0 0 18 * * *` runs every day at 18:00.
+    Cron(String),
+}
+
+/// What a scheduled job changes
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobAction {
+    /// Apply the fields set in these output ports
+    Outputs(Vec<OutputPort>),
+    /// Recall the salvo of this name
+    Salvo(String),
+}
+
+/// A change of a videohub made at given times
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Job {
+    pub name: String,
+    pub schedule: Schedule,
+    pub action: JobAction,
+    /// The user who last saved the job, it runs with their current permissions. Set by the
+    /// server
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ScheduledJob {
+    #[serde(flatten)]
+    pub job: Job,
+    /// Not set when the job will not run anymore
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// An execution of a scheduled job
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct JobRun {
+    pub timestamp: DateTime<Utc>,
+    pub hub: String,
+    pub job: String,
+    /// Why the job failed, not set when it succeeded
+    pub error: Option<String>,
+    /// What the job changed
+    pub changes: Vec<Difference>,
+}
+
+/// How the ports are numbered for the users. The protocol numbers them from 0, while the panels
+/// and the patch bays usually number them from 1.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Numbering {
+    #[default]
+    ZeroBased,
+    OneBased,
+}
+
+impl Numbering {
+    /// The number of the first port
+    pub fn first(self) -> usize {
+        match self {
+            Numbering::ZeroBased => 0,
+            Numbering::OneBased => 1,
+        }
+    }
+
+    /// Number the ports of a value for the users.
+    pub fn to_user<T: Renumber>(self, mut value: T) -> T {
+        let first = self.first();
+        value.renumber(&|port| port + first);
+        value
+    }
+
+    /// Number the ports of a value given by a user as the protocol does.
+    pub fn from_user<T: Renumber>(self, mut value: T) -> Result<T, String> {
+        let first = self.first();
+        let invalid = Cell::new(None);
+        value.renumber(&|number| {
+            number.checked_sub(first).unwrap_or_else(|| {
+                invalid.set(Some(number));
+                number
+            })
+        });
+        match invalid.get() {
+            Some(number) => Err(format!(
+                "There is no port {number}, the ports are numbered from {first}"
+            )),
+            None => Ok(value),
+        }
+    }
+}
+
+/// The values holding port numbers, converted between the protocol and the users by
+/// [`Numbering`].
+pub trait Renumber {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize);
+}
+
+impl Renumber for usize {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        *self = convert(*self);
+    }
+}
+
+impl<T: Renumber> Renumber for Option<T> {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        if let Some(value) = self {
+            value.renumber(convert);
+        }
+    }
+}
+
+impl<T: Renumber> Renumber for Vec<T> {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        for value in self {
+            value.renumber(convert);
+        }
+    }
+}
+
+impl<K, T: Renumber> Renumber for HashMap<K, T> {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        for value in self.values_mut() {
+            value.renumber(convert);
+        }
+    }
+}
+
+impl Renumber for InputPort {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.id.renumber(convert);
+    }
+}
+
+impl Renumber for OutputPort {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.id.renumber(convert);
+        self.input_port.renumber(convert);
+    }
+}
+
+impl Renumber for HubEvent {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        match self {
+            HubEvent::InputPorts(input_ports) => input_ports.renumber(convert),
+            HubEvent::OutputPorts(output_ports) => output_ports.renumber(convert),
+        }
+    }
+}
+
+// The values of a route are input ports
+fn renumber_change(
+    kind: ChangeKind,
+    port: &mut usize,
+    values: [Option<&mut String>; 2],
+    convert: &dyn Fn(usize) -> usize,
+) {
+    match kind {
+        ChangeKind::TakeMode | ChangeKind::DevicePresent => {}
+        ChangeKind::Route => {
+            port.renumber(convert);
+            for value in values.into_iter().flatten() {
+                if let Ok(input) = value.parse::<usize>() {
+                    *value = convert(input).to_string();
+                }
+            }
+        }
+        _ => port.renumber(convert),
+    }
+}
+
+impl Renumber for Difference {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        let values = [self.current.as_mut(), Some(&mut self.target)];
+        renumber_change(self.kind, &mut self.port, values, convert);
+    }
+}
+
+impl Renumber for AuditEntry {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        let values = [self.before.as_mut(), Some(&mut self.after)];
+        renumber_change(self.kind, &mut self.port, values, convert);
+    }
+}
+
+impl Renumber for Salvo {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.outputs.renumber(convert);
+    }
+}
+
+impl Renumber for PendingRoute {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.output.renumber(convert);
+        self.input.renumber(convert);
+    }
+}
+
+impl Renumber for SalvoCapture {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.outputs.renumber(convert);
+    }
+}
+
+impl Renumber for Backup {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.input_ports.renumber(convert);
+        self.output_ports.renumber(convert);
+    }
+}
+
+impl Renumber for Job {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        match &mut self.action {
+            JobAction::Outputs(output_ports) => output_ports.renumber(convert),
+            JobAction::Salvo(_) => {}
+        }
+    }
+}
+
+impl Renumber for ScheduledJob {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.job.renumber(convert);
+    }
+}
+
+impl Renumber for JobRun {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.changes.renumber(convert);
+    }
+}
+
+impl Renumber for protocol::Label {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.0.renumber(convert);
+    }
+}
+
+impl Renumber for protocol::OutputLock {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.0.renumber(convert);
+    }
+}
+
+impl Renumber for protocol::Route {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.0.renumber(convert);
+        self.1.renumber(convert);
+    }
+}
+
+impl Renumber for protocol::HubInfo {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.input_labels.renumber(convert);
+        self.output_labels.renumber(convert);
+        self.video_output_locks.renumber(convert);
+        self.video_output_routing.renumber(convert);
+        self.video_input_status.renumber(convert);
+        self.video_output_status.renumber(convert);
+    }
+}
+
+impl Renumber for protocol::PortStatus {
+    fn renumber(&mut self, convert: &dyn Fn(usize) -> usize) {
+        self.0.renumber(convert);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output_port(id: usize, input_port: Option<usize>) -> OutputPort {
+        OutputPort {
+            id,
+            label: Some(format!("Out {id}")),
+            lock_state: None,
+            input_port,
+        }
+    }
+
+    fn labels(labels: &[(usize, &str)]) -> Vec<protocol::Label> {
+        labels
+            .iter()
+            .map(|(port, label)| protocol::Label(*port, label.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_labels_table() {
+        let expected = labels(&[(0, "Cam 1"), (1, "Cam, wide"), (2, "")]);
+        let csv = labels_table(&expected, TableFormat::Csv);
+        assert!(csv.starts_with("port,label\n0,Cam 1\n1,\"Cam, wide\"\n"));
+        assert_eq!(parse_labels_table(&csv), Ok(expected.clone()));
+        let tsv = labels_table(&expected, TableFormat::Tsv);
+        assert_eq!(parse_labels_table(&tsv), Ok(expected.clone()));
+        // Without a header
+        assert_eq!(
+            parse_labels_table("0,Cam 1\n1,\"Cam, wide\"\n2,\n"),
+            Ok(expected)
+        );
+        assert_eq!(
+            parse_labels_table("Port\tLabel\n 3 \tCam\n"),
+            Ok(labels(&[(3, "Cam")]))
+        );
+    }
+
+    #[test]
+    fn test_labels_table_errors() {
+        let error = |content| parse_labels_table(content).unwrap_err();
+        // A typo in the first row is not mistaken for a header
+        assert_eq!(
+            error("l,Cam 1\n2,Cam 2\n"),
+            "line 1: 'l' is not a port number"
+        );
+        assert_eq!(
+            error("port,label\n0,Cam 1\nport,label\n"),
+            "line 3: 'port' is not a port number"
+        );
+        assert_eq!(error("-1,Cam\n"), "line 1: '-1' is not a port number");
+        assert_eq!(
+            error("99999999999999999999999,Cam\n"),
+            "line 1: '99999999999999999999999' is not a port number"
+        );
+        assert_eq!(
+            error("0,Cam 1\n1,Cam 2\n0,Cam 3\n"),
+            "line 3: the port 0 is given twice"
+        );
+        assert_eq!(error("0,Cam 1\n1\n"), "line 2: expected a port and a label");
+        assert_eq!(
+            error("0,\"Cam\n1\"\n"),
+            "line 1: the label of the port 0 has several lines"
+        );
+    }
+
+    #[test]
+    fn test_backup_locks() {
+        let mut hub_info = protocol::HubInfo::default();
+        hub_info.video_output_locks = (0..3)
+            .map(|output| protocol::OutputLock(output, protocol::LockStatus::Unlocked))
+            .collect();
+        let mut backup = Backup::from(hub_info.clone());
+        backup.output_ports = [LockStatus::Owned, LockStatus::Locked, LockStatus::Unlocked]
+            .into_iter()
+            .enumerate()
+            .map(|(id, lock_state)| OutputPort {
+                id,
+                label: None,
+                lock_state: Some(lock_state),
+                input_port: None,
+            })
+            .collect();
+        let blocks = backup.blocks(&hub_info);
+        assert!(matches!(
+            &blocks[..],
+            [protocol::BlockType::VideoOutputLocks(locks)]
+                if *locks == [protocol::OutputLock(0, protocol::LockStatus::Owned)]
+        ));
+        assert_eq!(backup.differences(&hub_info).len(), 1);
+    }
+
+    #[test]
+    fn test_salvo_locks() {
+        let mut hub_info = protocol::HubInfo::default();
+        hub_info.output_labels = (0..3)
+            .map(|output| protocol::Label(output, format!("Out {output}")))
+            .collect();
+        hub_info.video_output_locks = vec![
+            protocol::OutputLock(0, protocol::LockStatus::Owned),
+            protocol::OutputLock(1, protocol::LockStatus::Locked),
+            protocol::OutputLock(2, protocol::LockStatus::Unlocked),
+        ];
+        hub_info.video_output_routing = (0..3).map(|output| protocol::Route(output, 0)).collect();
+        let salvo = Salvo::capture(
+            &hub_info,
+            &SalvoCapture {
+                name: "locks".to_string(),
+                outputs: None,
+                labels: false,
+                locks: true,
+            },
+        );
+        let lock_states: Vec<_> = salvo
+            .outputs
+            .iter()
+            .map(|output_port| output_port.lock_state.clone())
+            .collect();
+        assert_eq!(
+            lock_states,
+            [Some(LockStatus::Owned), None, Some(LockStatus::Unlocked)]
+        );
+
+        // A salvo written with the lock of another panel does not recall it
+        let mut salvo = salvo;
+        salvo.outputs[1].lock_state = Some(LockStatus::Locked);
+        let locks: Vec<_> = salvo
+            .blocks()
+            .into_iter()
+            .filter_map(|block| match block {
+                protocol::BlockType::VideoOutputLocks(locks) => Some(locks),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            locks,
+            [vec![
+                protocol::OutputLock(0, protocol::LockStatus::Owned),
+                protocol::OutputLock(2, protocol::LockStatus::Unlocked),
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_numbering_round_trip() {
+        let ports = vec![output_port(0, Some(3)), output_port(39, None)];
+        for numbering in [Numbering::ZeroBased, Numbering::OneBased] {
+            let user_ports = numbering.to_user(ports.clone());
+            assert_eq!(user_ports[0].id, numbering.first());
+            assert_eq!(user_ports[0].input_port, Some(3 + numbering.first()));
+            assert_eq!(user_ports[1].input_port, None);
+            assert_eq!(numbering.from_user(user_ports), Ok(ports.clone()));
+        }
+    }
+
+    #[test]
+    fn test_numbering_bounds() {
+        assert_eq!(Numbering::ZeroBased.from_user(0), Ok(0));
+        assert_eq!(Numbering::OneBased.from_user(1), Ok(0));
+        assert_eq!(Numbering::OneBased.to_user(0), 1);
+        assert_eq!(
+            Numbering::OneBased.from_user(0),
+            Err("There is no port 0, the ports are numbered from 1".to_string())
+        );
+        // Whichever port is invalid, the whole value is
+        let routes = vec![
+            PendingRoute {
+                output: 1,
+                input: 2,
+            },
+            PendingRoute {
+                output: 2,
+                input: 0,
+            },
+        ];
+        assert!(Numbering::OneBased.from_user(routes).is_err());
+    }
+}
@@ -1,14 +1,33 @@
-use super::{fetch::fetch_device_info, route};
-use videohub_server_api_def::defs::DeviceInfo;
+use super::{
+    audit,
+    fetch::{fetch_device_info, fetch_hubs, fetch_user, logout},
+    login, route,
+};
+use gloo::storage::{LocalStorage, Storage};
+use videohub_server_api_def::defs::{DeviceInfo, HubSummary, UserInfo};
+use web_sys::HtmlSelectElement;
 use yew::prelude::*;
 
+const SELECTED_HUB_STORAGE_KEY: &str = "videohub.selected_hub";
+
 pub struct Model {
-    nb_input_ports: Option<usize>,
-    nb_output_ports: Option<usize>,
+    user: Option<UserInfo>,
+    login_required: bool,
+    show_audit: bool,
+    hubs: Vec<HubSummary>,
+    selected_hub: Option<String>,
     friendly_name: Option<String>,
 }
 
 pub enum Msg {
+    LoginRequired,
+    LoggedIn(UserInfo),
+    Logout,
+    LoggedOut,
+    FetchUser(UserInfo),
+    FetchHubs(Vec<HubSummary>),
+    ToggleAudit,
+    SelectHub(String),
     FetchDeviceInfo(DeviceInfo),
 }
 
@@ -17,35 +36,137 @@ impl Component for Model {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        fetch_device_info(ctx);
+        fetch_hubs(ctx);
         Self {
-            nb_input_ports: Some(0),
-            nb_output_ports: Some(0),
+            user: None,
+            login_required: false,
+            show_audit: false,
+            hubs: vec![],
+            selected_hub: None,
             friendly_name: Some("".to_owned()),
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
+            Msg::LoginRequired => {
+                self.login_required = true;
+                self.user = None;
+                true
+            }
+            Msg::LoggedIn(user) => {
+                self.login_required = false;
+                self.user = Some(user);
+                fetch_hubs(ctx);
+                true
+            }
+            Msg::Logout => {
+                logout(ctx);
+                false
+            }
+            Msg::LoggedOut => {
+                self.selected_hub = None;
+                fetch_hubs(ctx);
+                true
+            }
+            Msg::FetchUser(user) => {
+                self.user = Some(user);
+                true
+            }
+            Msg::FetchHubs(hubs) => {
+                if self.user.is_none() {
+                    fetch_user(ctx);
+                }
+                // Come back to the hub selected last time, if it still exists
+                let stored_hub: Option<String> = LocalStorage::get(SELECTED_HUB_STORAGE_KEY).ok();
+                let selected_hub = stored_hub
+                    .filter(|name| hubs.iter().any(|hub| &hub.name == name))
+                    .or_else(|| hubs.first().map(|hub| hub.name.clone()));
+                self.hubs = hubs;
+                if let Some(hub) = selected_hub {
+                    ctx.link().send_message(Msg::SelectHub(hub));
+                }
+                true
+            }
+            Msg::ToggleAudit => {
+                self.show_audit = !self.show_audit;
+                true
+            }
+            Msg::SelectHub(hub) => {
+                let _ = LocalStorage::set(SELECTED_HUB_STORAGE_KEY, &hub);
+                fetch_device_info(ctx, &hub);
+                self.selected_hub = Some(hub);
+                true
+            }
             Msg::FetchDeviceInfo(device_info) => {
-                self.nb_input_ports = Some(device_info.nb_video_inputs);
-                self.nb_output_ports = Some(device_info.nb_video_outputs);
                 self.friendly_name = Some(device_info.friendly_name);
                 true
             }
         }
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
+    // False positive on the props of login::Model, in the code generated by html!
+    #[allow(clippy::unnecessary_operation)]
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if self.login_required {
+            let on_login = ctx.link().callback(Msg::LoggedIn);
+            return html! {
+                <>
+                    <header style={"color: white; background: rgb(155, 28, 30)"}>
+                        <h1>{"Videohub"}</h1>
+                    </header>
+                    <login::Model {on_login}/>
+                </>
+            };
+        }
+
+        let on_hub_change = ctx.link().callback(|e: Event| {
+            Msg::SelectHub(e.target_unchecked_into::<HtmlSelectElement>().value())
+        });
+
         html! {
             <>
                 <header style={"color: white; background: rgb(155, 28, 30)"}>
                     <h1>{"Videohub"}</h1>
+                    <select id="hub_select" onchange={on_hub_change}>
+                        {
+                            for self.hubs.iter().map(|hub| html!(
+                                <option value={hub.name.clone()} selected={self.selected_hub.as_ref() == Some(&hub.name)}>
+                                    {if hub.reachable { hub.name.clone() } else { format!("{} (unreachable)", hub.name) }}
+                                </option>
+                            ))
+                        }
+                    </select>
                     <p>{format!("Friendly Name: {}", self.friendly_name.as_ref().unwrap())}</p>
+                    <button id="audit_button" onclick={ctx.link().callback(|_| Msg::ToggleAudit)}>
+                        {if self.show_audit { "Hide Audit Log" } else { "Audit Log" }}
+                    </button>
+                    {
+                        match &self.user {
+                            Some(user) => html!(
+                                <p>
+                                    {format!("{} ({:?}) ", user.name, user.role)}
+                                    <button id="logout_button" onclick={ctx.link().callback(|_| Msg::Logout)}>{"Logout"}</button>
+                                </p>
+                            ),
+                            None => html!(),
+                        }
+                    }
                 </header>
                 <div>
-                    <route::Model nb_input_ports={self.nb_input_ports.unwrap()} nb_output_ports={self.nb_output_ports.unwrap()}/>
+                    {
+                        match &self.selected_hub {
+                            Some(hub) => html!(<route::Model hub={hub.clone()}/>),
+                            None => html!(),
+                        }
+                    }
                 </div>
+                {
+                    match &self.selected_hub {
+                        Some(hub) if self.show_audit => html!(<audit::Model hub={hub.clone()}/>),
+                        _ => html!(),
+                    }
+                }
             </>
         }
     }
@@ -0,0 +1,111 @@
+use super::fetch::hub_url;
+use reqwest::Client;
+use videohub_server_api_def::defs::{AuditEntry, ChangeKind};
+use yew::prelude::*;
+
+const NB_ENTRIES: usize = 50;
+
+pub struct Model {
+    hub: String,
+    entries: Vec<AuditEntry>,
+}
+
+pub enum Msg {
+    Fetch,
+    FetchEntries(Vec<AuditEntry>),
+}
+
+#[derive(Properties, PartialEq, Eq)]
+pub struct Props {
+    pub hub: String,
+}
+
+impl Component for Model {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Msg::Fetch);
+        Self {
+            hub: ctx.props().hub.clone(),
+            entries: vec![],
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Fetch => {
+                let url = hub_url(&ctx.props().hub, &format!("audit?limit={NB_ENTRIES}"));
+                ctx.link().send_future(async move {
+                    let client = Client::new();
+                    let entries: Vec<AuditEntry> = client
+                        .get(&url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    Msg::FetchEntries(entries)
+                });
+                false
+            }
+            Msg::FetchEntries(entries) => {
+                self.entries = entries;
+                true
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        if self.hub != ctx.props().hub {
+            self.hub = ctx.props().hub.clone();
+            self.entries.clear();
+            ctx.link().send_message(Msg::Fetch);
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div id="audit">
+                <h1>{"Audit Log"}</h1>
+                <button id="audit_refresh_button" onclick={ctx.link().callback(|_| Msg::Fetch)}>{"Refresh"}</button>
+                <table>
+                    <tr>
+                        <th>{"Time"}</th>
+                        <th>{"User"}</th>
+                        <th>{"Client"}</th>
+                        <th>{"Change"}</th>
+                        <th>{"Before"}</th>
+                        <th>{"After"}</th>
+                    </tr>
+                    {
+                        for self.entries.iter().map(|entry| html!(
+                            <tr>
+                                <td>{entry.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()}</td>
+                                <td>{entry.user.clone().unwrap_or_else(|| "(other panel)".to_string())}</td>
+                                <td>{entry.client.clone().unwrap_or_default()}</td>
+                                <td>{describe(entry)}</td>
+                                <td>{entry.before.clone().unwrap_or_default()}</td>
+                                <td>{entry.after.clone()}</td>
+                            </tr>
+                        ))
+                    }
+                </table>
+            </div>
+        }
+    }
+}
+
+fn describe(entry: &AuditEntry) -> String {
+    match entry.kind {
+        ChangeKind::InputLabel => format!("IN{} label", entry.port),
+        ChangeKind::OutputLabel => format!("OUT{} label", entry.port),
+        ChangeKind::OutputLock => format!("OUT{} lock", entry.port),
+        ChangeKind::Route => format!("OUT{} route", entry.port),
+        ChangeKind::TakeMode => "take mode".to_string(),
+        ChangeKind::DevicePresent => "device present".to_string(),
+    }
+}
@@ -0,0 +1,120 @@
+use videohub_server_api_def::defs::{InputPort, LockStatus, OutputPort, PendingRoute};
+use yew::prelude::*;
+
+/// Size of the cells in pixels, changed by the zoom buttons
+const DEFAULT_CELL_SIZE: usize = 24;
+const MIN_CELL_SIZE: usize = 12;
+const MAX_CELL_SIZE: usize = 48;
+const CELL_SIZE_STEP: usize = 4;
+
+pub enum Msg {
+    ZoomIn,
+    ZoomOut,
+}
+
+/// Grid of the outputs by the inputs, a dot marks the input routed to each output and a circle the
+/// route pending in take mode. Clicking a cell takes its route, or stages it in take mode.
+pub struct Model {
+    cell_size: usize,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub input_ports: Vec<InputPort>,
+    pub output_ports: Vec<OutputPort>,
+    #[prop_or_default]
+    pub pending_routes: Vec<PendingRoute>,
+    /// Called with the output and the input of the cell clicked
+    pub on_take: Callback<(usize, usize)>,
+}
+
+impl Component for Model {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            cell_size: DEFAULT_CELL_SIZE,
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        self.cell_size = match msg {
+            Msg::ZoomIn => (self.cell_size + CELL_SIZE_STEP).min(MAX_CELL_SIZE),
+            Msg::ZoomOut => self
+                .cell_size
+                .saturating_sub(CELL_SIZE_STEP)
+                .max(MIN_CELL_SIZE),
+        };
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+        let link = ctx.link();
+
+        html! {
+            <div id="crosspoint">
+                <div class="toolbar">
+                    <button id="zoom_out_button" onclick={link.callback(|_| Msg::ZoomOut)}>{"-"}</button>
+                    <button id="zoom_in_button" onclick={link.callback(|_| Msg::ZoomIn)}>{"+"}</button>
+                </div>
+                <div class="grid" style={format!("--cell-size: {}px", self.cell_size)}>
+                    <table>
+                        <tr>
+                            <th class="corner">{"OUT \\ IN"}</th>
+                            {
+                                for props.input_ports.iter().map(|input_port| html!(
+                                    <th class="input" title={input_port.label.clone()}>
+                                        {format!("IN{} {}", input_port.id, input_port.label)}
+                                    </th>
+                                ))
+                            }
+                        </tr>
+                        { for props.output_ports.iter().map(|output_port| self.view_row(ctx, output_port)) }
+                    </table>
+                </div>
+            </div>
+        }
+    }
+}
+
+impl Model {
+    fn view_row(&self, ctx: &Context<Self>, output_port: &OutputPort) -> Html {
+        let output = output_port.id;
+        let label = output_port.label.clone().unwrap_or_default();
+        // Only the panel owning a lock can route the output
+        let locked = output_port.lock_state == Some(LockStatus::Locked);
+        let lock_marker = match output_port.lock_state {
+            Some(LockStatus::Locked) => "🔒 ",
+            Some(LockStatus::Owned) => "🔑 ",
+            _ => "",
+        };
+
+        html! {
+            <tr class={classes!(locked.then_some("locked"))}>
+                <th class="output" title={label.clone()}>
+                    {format!("{lock_marker}OUT{output} {label}")}
+                </th>
+                {
+                    for ctx.props().input_ports.iter().map(|input_port| {
+                        let input = input_port.id;
+                        let active = output_port.input_port == Some(input);
+                        let pending = ctx.props().pending_routes.iter().any(|route| {
+                            route.output == output && route.input == input
+                        });
+                        let title = format!("IN{input} {} > OUT{output} {label}", input_port.label);
+                        let on_take = ctx.props().on_take.clone();
+                        let onclick = (!locked && !active)
+                            .then(|| Callback::from(move |_| on_take.emit((output, input))));
+                        html!(
+                            <td class={classes!("cell", active.then_some("active"), pending.then_some("pending"))} {title} {onclick}>
+                                {if active { "●" } else if pending { "○" } else { "" }}
+                            </td>
+                        )
+                    })
+                }
+            </tr>
+        }
+    }
+}
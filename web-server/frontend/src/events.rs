@@ -0,0 +1,48 @@
+use super::{fetch::hub_url, route};
+use videohub_server_api_def::defs::HubEvent;
+use wasm_bindgen::{closure::Closure, JsCast, UnwrapThrowExt};
+use web_sys::{EventSource, MessageEvent};
+use yew::prelude::*;
+
+/// The changes pushed by the server for the hub of a route::Model. The connection is closed when
+/// this is dropped.
+pub struct HubEvents {
+    event_source: EventSource,
+    _on_open: Closure<dyn FnMut(web_sys::Event)>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+pub fn subscribe(ctx: &Context<route::Model>) -> HubEvents {
+    let event_source = EventSource::new(&hub_url(&ctx.props().hub, "events")).unwrap_throw();
+
+    // Changes may have been missed while the stream was not opened: reload everything
+    let link = ctx.link().clone();
+    let on_open = Closure::wrap(Box::new(move |_: web_sys::Event| {
+        link.send_message(route::Msg::FetchVideohubInfo)
+    }) as Box<dyn FnMut(web_sys::Event)>);
+    event_source.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+
+    let link = ctx.link().clone();
+    let on_message = Closure::wrap(Box::new(move |message: MessageEvent| {
+        let event = message
+            .data()
+            .as_string()
+            .and_then(|data| serde_json::from_str::<HubEvent>(&data).ok());
+        if let Some(event) = event {
+            link.send_message(route::Msg::HubEvent(event));
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    event_source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+    HubEvents {
+        event_source,
+        _on_open: on_open,
+        _on_message: on_message,
+    }
+}
+
+impl Drop for HubEvents {
+    fn drop(&mut self) {
+        self.event_source.close();
+    }
+}
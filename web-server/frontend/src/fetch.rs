@@ -1,11 +1,61 @@
 use super::{app, config::HOST_ADDRESS, route};
-use reqwest::Client;
-use videohub_server_api_def::defs::{DeviceInfo, InputPort, OutputPort};
+use reqwest::{Client, StatusCode};
+use videohub_server_api_def::defs::{
+    Configuration, DeviceInfo, HubSummary, InputPort, OutputPort, UserInfo,
+};
 use yew::prelude::*;
 
-pub fn fetch_device_info(ctx: &Context<app::Model>) {
+pub fn hub_url(hub: &str, endpoint: &str) -> String {
+    format!("http://{}/hubs/{}/{}", *HOST_ADDRESS, hub, endpoint)
+}
+
+pub fn fetch_hubs(ctx: &Context<app::Model>) {
+    ctx.link().send_future(async {
+        let url = format!("http://{}/hubs", *HOST_ADDRESS);
+        let client = Client::new();
+        let response = client
+            .get(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .send()
+            .await
+            .unwrap();
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return app::Msg::LoginRequired;
+        }
+        let hubs: Vec<HubSummary> = response.json().await.unwrap();
+        app::Msg::FetchHubs(hubs)
+    });
+}
+
+pub fn fetch_user(ctx: &Context<app::Model>) {
+    ctx.link().send_future(async {
+        let url = format!("http://{}/auth/me", *HOST_ADDRESS);
+        let client = Client::new();
+        let user: UserInfo = client
+            .get(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        app::Msg::FetchUser(user)
+    });
+}
+
+pub fn logout(ctx: &Context<app::Model>) {
     ctx.link().send_future(async {
-        let url = format!("http://{}/hub/device_info", *HOST_ADDRESS);
+        let url = format!("http://{}/auth/logout", *HOST_ADDRESS);
+        let client = Client::new();
+        client.post(&url).send().await.unwrap();
+        app::Msg::LoggedOut
+    });
+}
+
+pub fn fetch_device_info(ctx: &Context<app::Model>, hub: &str) {
+    let url = hub_url(hub, "device_info");
+    ctx.link().send_future(async move {
         let client = Client::new();
         let device_info: DeviceInfo = client
             .get(&url)
@@ -21,8 +71,8 @@ pub fn fetch_device_info(ctx: &Context<app::Model>) {
 }
 
 pub fn fetch_input_ports(ctx: &Context<route::Model>) {
-    ctx.link().send_future(async {
-        let url = format!("http://{}/hub/input_ports", *HOST_ADDRESS);
+    let url = hub_url(&ctx.props().hub, "input_ports");
+    ctx.link().send_future(async move {
         let client = Client::new();
         let input_labels: Vec<InputPort> = client
             .get(&url)
@@ -38,8 +88,8 @@ pub fn fetch_input_ports(ctx: &Context<route::Model>) {
 }
 
 pub fn fetch_output_ports(ctx: &Context<route::Model>) {
-    ctx.link().send_future(async {
-        let url = format!("http://{}/hub/output_ports", *HOST_ADDRESS);
+    let url = hub_url(&ctx.props().hub, "output_ports");
+    ctx.link().send_future(async move {
         let client = Client::new();
         let output_ports: Vec<OutputPort> = client
             .get(&url)
@@ -53,3 +103,40 @@ pub fn fetch_output_ports(ctx: &Context<route::Model>) {
         route::Msg::FetchOutputPorts(output_ports)
     });
 }
+
+pub fn fetch_configuration(ctx: &Context<route::Model>) {
+    let url = hub_url(&ctx.props().hub, "configuration");
+    ctx.link().send_future(async move {
+        let client = Client::new();
+        let configuration: Configuration = client
+            .get(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        route::Msg::FetchConfiguration(configuration)
+    });
+}
+
+pub fn fetch_pending_routes(ctx: &Context<route::Model>) {
+    let url = hub_url(&ctx.props().hub, "pending_routes");
+    ctx.link().send_future(async move {
+        let client = Client::new();
+        let response = client
+            .get(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .send()
+            .await
+            .unwrap();
+        // The viewers can not stage routes
+        let pending_routes = if response.status().is_success() {
+            response.json().await.unwrap()
+        } else {
+            vec![]
+        };
+        route::Msg::FetchPendingRoutes(pending_routes)
+    });
+}
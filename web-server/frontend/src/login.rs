@@ -0,0 +1,120 @@
+use super::config::HOST_ADDRESS;
+use reqwest::{Client, StatusCode};
+use videohub_server_api_def::defs::{ApiError, Credentials, Login, UserInfo};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+pub struct Model {
+    name: String,
+    password: String,
+    error: Option<String>,
+}
+
+pub enum Msg {
+    SetName(String),
+    SetPassword(String),
+    Submit,
+    LoggedIn(UserInfo),
+    Failed(String),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub on_login: Callback<UserInfo>,
+}
+
+impl Component for Model {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            name: String::new(),
+            password: String::new(),
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::SetName(name) => {
+                self.name = name;
+                false
+            }
+            Msg::SetPassword(password) => {
+                self.password = password;
+                false
+            }
+            Msg::Submit => {
+                let credentials = Credentials {
+                    name: self.name.clone(),
+                    password: self.password.clone(),
+                };
+                ctx.link().send_future(async move {
+                    match login(&credentials).await {
+                        Ok(login) => Msg::LoggedIn(login.user),
+                        Err(message) => Msg::Failed(message),
+                    }
+                });
+                false
+            }
+            Msg::LoggedIn(user) => {
+                self.error = None;
+                ctx.props().on_login.emit(user);
+                true
+            }
+            Msg::Failed(message) => {
+                self.error = Some(message);
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let onsubmit = link.callback(|e: FocusEvent| {
+            e.prevent_default();
+            Msg::Submit
+        });
+        let on_name_input = link.callback(|e: InputEvent| {
+            Msg::SetName(e.target_unchecked_into::<HtmlInputElement>().value())
+        });
+        let on_password_input = link.callback(|e: InputEvent| {
+            Msg::SetPassword(e.target_unchecked_into::<HtmlInputElement>().value())
+        });
+
+        html! {
+            <form id="login" {onsubmit}>
+                <h1>{"Login"}</h1>
+                <input type="text" placeholder="User name" oninput={on_name_input}/>
+                <input type="password" placeholder="Password" oninput={on_password_input}/>
+                <button type="submit">{"Login"}</button>
+                {
+                    match &self.error {
+                        Some(error) => html!(<p class="error">{error}</p>),
+                        None => html!(),
+                    }
+                }
+            </form>
+        }
+    }
+}
+
+async fn login(credentials: &Credentials) -> Result<Login, String> {
+    let url = format!("http://{}/auth/login", *HOST_ADDRESS);
+    let client = Client::new();
+    let response = client
+        .post(&url)
+        .json(credentials)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    match response.status() {
+        StatusCode::OK => response.json().await.map_err(|err| err.to_string()),
+        _ => Err(response
+            .json::<ApiError>()
+            .await
+            .map(|err| err.message)
+            .unwrap_or_else(|err| err.to_string())),
+    }
+}
@@ -1,6 +1,10 @@
 mod app;
+mod audit;
 mod config;
+mod crosspoint;
+mod events;
 mod fetch;
+mod login;
 mod route;
 
 use crate::app::Model;
@@ -1,10 +1,14 @@
 use super::{
-    config::HOST_ADDRESS,
-    fetch::{fetch_input_ports, fetch_output_ports},
+    crosspoint,
+    events::{subscribe, HubEvents},
+    fetch::{
+        fetch_configuration, fetch_input_ports, fetch_output_ports, fetch_pending_routes, hub_url,
+    },
 };
-use gloo::timers::callback::Interval;
 use reqwest::Client;
-use videohub_server_api_def::defs::{InputPort, OutputPort};
+use videohub_server_api_def::defs::{
+    ApiError, Configuration, HubEvent, InputPort, OutputPort, PendingRoute,
+};
 use wasm_bindgen::{JsCast, UnwrapThrowExt};
 use yew::prelude::*;
 
@@ -18,23 +22,43 @@ pub enum Msg {
     OutPortClicked(usize),
     FetchInputPorts(Vec<InputPort>),
     FetchOutputPorts(Vec<OutputPort>),
+    FetchConfiguration(Configuration),
+    FetchPendingRoutes(Vec<PendingRoute>),
     FetchVideohubInfo,
+    HubEvent(HubEvent),
+    ToggleCrosspoint,
     Route,
+    /// Route an input to an output, from the crosspoint grid
+    CrosspointClicked(usize, usize),
+    /// Send the pending routes, in take mode
+    Take,
+    /// Drop the pending routes, in take mode
+    CancelPending,
     RouteDone,
+    RouteFailed(String),
 }
 
 pub struct Model {
+    hub: String,
     current_in_port_selected: Option<usize>,
     current_out_port_selected: Option<usize>,
     input_ports: Option<Vec<InputPort>>,
     output_ports: Option<Vec<OutputPort>>,
-    _interval_handle: Interval,
+    events: HubEvents,
+    /// Why the last route was refused by the server
+    error: Option<String>,
+    /// The crosspoint grid is shown instead of the buttons
+    crosspoint: bool,
+    /// The routes are staged, then taken at once
+    take_mode: bool,
+    /// The routes staged by the user, in take mode
+    pending_routes: Vec<PendingRoute>,
 }
 
 #[derive(Properties, PartialEq, Eq)]
 pub struct Props {
-    pub nb_input_ports: usize,
-    pub nb_output_ports: usize,
+    /// Name of the videohub, as listed by the server
+    pub hub: String,
 }
 
 impl Component for Model {
@@ -42,19 +66,18 @@ impl Component for Model {
     type Properties = Props;
 
     fn create(ctx: &Context<Self>) -> Self {
-        ctx.link().send_message(Msg::FetchVideohubInfo);
-
-        let fetch_videohub_info_interval_handle = {
-            let link = ctx.link().clone();
-            Interval::new(3_000, move || link.send_message(Msg::FetchVideohubInfo))
-        };
-
+        // The ports are fetched once the events stream is opened, and then kept up to date
         Self {
+            hub: ctx.props().hub.clone(),
             current_in_port_selected: None,
             current_out_port_selected: None,
             input_ports: None,
             output_ports: None,
-            _interval_handle: fetch_videohub_info_interval_handle,
+            events: subscribe(ctx),
+            error: None,
+            crosspoint: false,
+            take_mode: false,
+            pending_routes: vec![],
         }
     }
 
@@ -78,44 +101,121 @@ impl Component for Model {
                 self.output_ports = Some(output_ports);
                 true
             }
+            Msg::FetchConfiguration(configuration) => {
+                self.take_mode = configuration.take_mode;
+                true
+            }
+            Msg::FetchPendingRoutes(pending_routes) => {
+                self.error = None;
+                self.pending_routes = pending_routes;
+                true
+            }
             Msg::FetchVideohubInfo => {
                 fetch_input_ports(ctx);
                 fetch_output_ports(ctx);
+                fetch_configuration(ctx);
+                fetch_pending_routes(ctx);
+                true
+            }
+            Msg::HubEvent(HubEvent::InputPorts(changes)) => {
+                let input_ports = self.input_ports.iter_mut().flatten();
+                for input_port in input_ports {
+                    if let Some(change) = changes.iter().find(|change| change.id == input_port.id) {
+                        input_port.label = change.label.clone();
+                    }
+                }
+                true
+            }
+            Msg::HubEvent(HubEvent::OutputPorts(changes)) => {
+                let output_ports = self.output_ports.iter_mut().flatten();
+                for output_port in output_ports {
+                    for change in changes.iter().filter(|change| change.id == output_port.id) {
+                        if change.label.is_some() {
+                            output_port.label = change.label.clone();
+                        }
+                        if change.lock_state.is_some() {
+                            output_port.lock_state = change.lock_state.clone();
+                        }
+                        if change.input_port.is_some() {
+                            output_port.input_port = change.input_port;
+                        }
+                    }
+                }
+                if self.current_in_port_selected.is_some()
+                    || self.current_out_port_selected.is_some()
+                {
+                    self.update_button_colors(ctx);
+                }
+                true
+            }
+            Msg::ToggleCrosspoint => {
+                self.crosspoint = !self.crosspoint;
+                self.current_in_port_selected = None;
+                self.current_out_port_selected = None;
                 true
             }
             Msg::Route => {
-                let body = serde_json::to_string(&vec![OutputPort {
-                    id: self.current_out_port_selected.unwrap(),
-                    input_port: self.current_in_port_selected,
-                    label: None,
-                    lock_state: None,
-                }])
-                .unwrap();
-
-                ctx.link().send_future(async {
-                    let client = Client::new();
-                    client
-                        .put(format!("http://{}/hub/output_ports", *HOST_ADDRESS))
-                        .header(reqwest::header::CONTENT_TYPE, "application/json")
-                        .body(body)
-                        .send()
-                        .await
-                        .unwrap();
-                    Msg::RouteDone
-                });
+                let output = self.current_out_port_selected.unwrap();
+                match self.current_in_port_selected {
+                    Some(input) if self.take_mode => {
+                        stage_route(ctx, PendingRoute { output, input });
+                        self.current_in_port_selected = None;
+                        self.current_out_port_selected = None;
+                        self.update_button_colors(ctx);
+                    }
+                    input => send_route(ctx, output, input),
+                }
+                true
+            }
+            Msg::CrosspointClicked(output, input) => {
+                if self.take_mode {
+                    stage_route(ctx, PendingRoute { output, input });
+                } else {
+                    send_route(ctx, output, Some(input));
+                }
+                false
+            }
+            Msg::Take => {
+                send_pending(ctx, "pending_routes/take", reqwest::Method::POST);
+                false
+            }
+            Msg::CancelPending => {
+                send_pending(ctx, "pending_routes", reqwest::Method::DELETE);
+                false
+            }
+            Msg::RouteFailed(message) => {
+                self.error = Some(message);
                 true
             }
             Msg::RouteDone => {
+                self.error = None;
                 ctx.link().send_message(Msg::FetchVideohubInfo);
                 self.current_in_port_selected = None;
                 self.current_out_port_selected = None;
-                self.set_default_input_buttons_colors(ctx);
-                self.set_default_output_buttons_colors(ctx);
+                self.set_default_input_buttons_colors();
+                self.set_default_output_buttons_colors();
                 true
             }
         }
     }
 
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        // Another hub has been selected: start over with its ports
+        if self.hub != ctx.props().hub {
+            self.hub = ctx.props().hub.clone();
+            self.current_in_port_selected = None;
+            self.current_out_port_selected = None;
+            self.input_ports = None;
+            self.output_ports = None;
+            self.take_mode = false;
+            self.pending_routes = vec![];
+            self.events = subscribe(ctx);
+        }
+        true
+    }
+
+    // False positive on the props of crosspoint::Model, in the code generated by html!
+    #[allow(clippy::unnecessary_operation)]
     fn view(&self, ctx: &Context<Self>) -> Html {
         let link = ctx.link();
 
@@ -129,8 +229,34 @@ impl Component for Model {
             None => vec![],
         };
 
+        let toggle_crosspoint = html! {
+            <button id="crosspoint_button" onclick={link.callback(|_| Msg::ToggleCrosspoint)}>
+                {if self.crosspoint { "Buttons" } else { "Crosspoints" }}
+            </button>
+        };
+        let error = match &self.error {
+            Some(error) => html!(<p class="error">{error}</p>),
+            None => html!(),
+        };
+        let pending_routes = self.view_pending_routes(ctx);
+
+        if self.crosspoint {
+            let on_take = link.callback(|(output, input)| Msg::CrosspointClicked(output, input));
+            let pending_routes_props = self.pending_routes.clone();
+            return html! {
+                <>
+                    {toggle_crosspoint}
+                    {error}
+                    {pending_routes}
+                    <crosspoint::Model {input_ports} {output_ports} pending_routes={pending_routes_props} {on_take}/>
+                </>
+            };
+        }
+
         html! {
             <>
+                {toggle_crosspoint}
+                {pending_routes}
                 <div id="input_ports">
                     <h1 style={"text-align: center"}>{"Input Ports"}</h1>
                     {
@@ -150,7 +276,10 @@ impl Component for Model {
                     }
                 </div>
                 <div id="route">
-                    <button id={"route_button"} onclick={link.callback(move |_| Msg::Route)}>{"Route"}</button>
+                    <button id={"route_button"} onclick={link.callback(move |_| Msg::Route)}>
+                        {if self.take_mode { "Preview" } else { "Route" }}
+                    </button>
+                    {error}
                 </div>
             </>
         }
@@ -158,17 +287,65 @@ impl Component for Model {
 }
 
 impl Model {
+    // The routes staged in take mode, to review before taking them
+    fn view_pending_routes(&self, ctx: &Context<Self>) -> Html {
+        if !self.take_mode {
+            return html!();
+        }
+        let link = ctx.link();
+        let input_label = |id| {
+            let input_ports = self.input_ports.iter().flatten();
+            input_ports
+                .filter(|input_port| input_port.id == id)
+                .map(|input_port| input_port.label.clone())
+                .next()
+                .unwrap_or_default()
+        };
+        let output_label = |id| {
+            let output_ports = self.output_ports.iter().flatten();
+            output_ports
+                .filter(|output_port| output_port.id == id)
+                .find_map(|output_port| output_port.label.clone())
+                .unwrap_or_default()
+        };
+        let empty = self.pending_routes.is_empty();
+
+        html! {
+            <div id="pending_routes">
+                <h2>{format!("Take mode: {} pending route(s)", self.pending_routes.len())}</h2>
+                <ul>
+                    {
+                        for self.pending_routes.iter().map(|route| html!(
+                            <li>
+                                {format!(
+                                    "IN{} {} > OUT{} {}",
+                                    route.input,
+                                    input_label(route.input),
+                                    route.output,
+                                    output_label(route.output)
+                                )}
+                            </li>
+                        ))
+                    }
+                </ul>
+                <button id="take_button" disabled={empty} onclick={link.callback(|_| Msg::Take)}>{"Take"}</button>
+                <button id="cancel_button" disabled={empty} onclick={link.callback(|_| Msg::CancelPending)}>{"Cancel"}</button>
+            </div>
+        }
+    }
+
     fn update_button_colors(&self, ctx: &Context<Self>) {
-        self.set_default_output_buttons_colors(ctx);
-        self.set_default_input_buttons_colors(ctx);
+        self.set_default_output_buttons_colors();
+        self.set_default_input_buttons_colors();
         self.set_input_routed_to_output_color(ctx);
         self.set_output_routed_to_input_color(ctx);
         self.set_focused_input_button_color(ctx);
         self.set_focused_output_button_color(ctx);
     }
 
-    fn set_default_input_buttons_colors(&self, ctx: &Context<Self>) {
-        Self::set_default_buttons_colors("in_button_", ctx.props().nb_input_ports);
+    fn set_default_input_buttons_colors(&self) {
+        let input_ports = self.input_ports.iter().flatten();
+        Self::set_default_buttons_colors("in_button_", input_ports.map(|input_port| input_port.id));
     }
 
     fn set_focused_input_button_color(&self, _ctx: &Context<Self>) {
@@ -206,8 +383,12 @@ impl Model {
         }
     }
 
-    fn set_default_output_buttons_colors(&self, ctx: &Context<Self>) {
-        Self::set_default_buttons_colors("out_button_", ctx.props().nb_output_ports);
+    fn set_default_output_buttons_colors(&self) {
+        let output_ports = self.output_ports.iter().flatten();
+        Self::set_default_buttons_colors(
+            "out_button_",
+            output_ports.map(|output_port| output_port.id),
+        );
     }
 
     fn set_focused_output_button_color(&self, _ctx: &Context<Self>) {
@@ -216,15 +397,19 @@ impl Model {
         }
     }
 
-    fn set_default_buttons_colors(id_prefix: &str, nb_ports: usize) {
-        for i in 0..nb_ports {
+    // The ports are numbered as the server does, from 0 or 1
+    fn set_default_buttons_colors(id_prefix: &str, ids: impl Iterator<Item = usize>) {
+        for i in ids {
             Self::set_button_color(&format!("{id_prefix}{i}"), DEFAULT_BUTTON_BG_COLOR)
         }
     }
 
     fn set_button_color(element_id: &str, color: &str) {
         let document = web_sys::window().unwrap_throw().document().unwrap_throw();
-        let el = document.get_element_by_id(element_id).unwrap_throw();
+        // The buttons are not there while the crosspoint grid is shown
+        let Some(el) = document.get_element_by_id(element_id) else {
+            return;
+        };
         let button = el.dyn_into::<web_sys::HtmlButtonElement>().unwrap();
         button
             .style()
@@ -232,3 +417,75 @@ impl Model {
             .unwrap_throw();
     }
 }
+
+// The outputs are changed one at a time, the ports refreshed once the server accepted it
+fn send_route(ctx: &Context<Model>, output: usize, input: Option<usize>) {
+    let body = serde_json::to_string(&vec![OutputPort {
+        id: output,
+        input_port: input,
+        label: None,
+        lock_state: None,
+    }])
+    .unwrap();
+
+    let url = hub_url(&ctx.props().hub, "output_ports");
+    ctx.link().send_future(async move {
+        let client = Client::new();
+        let response = client
+            .put(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        if response.status().is_success() {
+            return Msg::RouteDone;
+        }
+        match response.json::<ApiError>().await {
+            Ok(err) => Msg::RouteFailed(err.message),
+            Err(err) => Msg::RouteFailed(err.to_string()),
+        }
+    });
+}
+
+// Stage a route in take mode, the routes staged are given back by the server
+fn stage_route(ctx: &Context<Model>, route: PendingRoute) {
+    let body = serde_json::to_string(&vec![route]).unwrap();
+    let url = hub_url(&ctx.props().hub, "pending_routes");
+    ctx.link().send_future(async move {
+        let client = Client::new();
+        let response = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        if response.status().is_success() {
+            return match response.json::<Vec<PendingRoute>>().await {
+                Ok(pending_routes) => Msg::FetchPendingRoutes(pending_routes),
+                Err(err) => Msg::RouteFailed(err.to_string()),
+            };
+        }
+        match response.json::<ApiError>().await {
+            Ok(err) => Msg::RouteFailed(err.message),
+            Err(err) => Msg::RouteFailed(err.to_string()),
+        }
+    });
+}
+
+// Take or cancel the pending routes, they are fetched again with the ports
+fn send_pending(ctx: &Context<Model>, endpoint: &str, method: reqwest::Method) {
+    let url = hub_url(&ctx.props().hub, endpoint);
+    ctx.link().send_future(async move {
+        let client = Client::new();
+        let response = client.request(method, url).send().await.unwrap();
+        if response.status().is_success() {
+            return Msg::RouteDone;
+        }
+        match response.json::<ApiError>().await {
+            Ok(err) => Msg::RouteFailed(err.message),
+            Err(err) => Msg::RouteFailed(err.to_string()),
+        }
+    });
+}
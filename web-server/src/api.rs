@@ -1,168 +1,1197 @@
-use super::config::VIDEOHUB_IPV4_ADDR;
+#[cfg(feature = "mqtt")]
+use super::mqtt::Bridge;
+use super::{
+    audit::{AuditFilter, AuditLog, Author, QueryError},
+    auth::{session_cookie, Admin, Auth, Operator, Token, User, Viewer, SESSION_COOKIE},
+    cached::Cached,
+    config::{self, find_hub, HUBS, PORT_NUMBERING, PUBLIC_METRICS},
+    metrics::Metrics,
+    monitor::{Monitor, Monitors},
+    pending::PendingRoutes,
+    salvos::{check_routes, recall_differences, SalvoError, Salvos},
+    scheduler::{JobError, Scheduler},
+};
 use anyhow::Result;
-use itertools::izip;
-use rocket::{fs::FileServer, response::status::BadRequest, serde::json::Json, Build, Rocket};
-use rocket_okapi::{openapi, openapi_get_routes, swagger_ui::*};
-use std::path::Path;
-use videohub_proto::{protocol, Hub};
-use videohub_server_api_def::defs::{Configuration, DeviceInfo, InputPort, LockStatus, OutputPort};
+use lazy_static::lazy_static;
+use rocket::{
+    fs::FileServer,
+    futures::stream::{self, BoxStream, StreamExt},
+    http::{ContentType, Cookie, CookieJar, Status},
+    response::{
+        status::Custom,
+        stream::{Event, EventStream},
+    },
+    serde::json::Json,
+    tokio::sync::broadcast::error::RecvError,
+    Build, Request, Rocket, Shutdown, State,
+};
+use rocket_okapi::{
+    mount_endpoints_and_merged_docs, openapi, openapi_get_routes_spec, settings::OpenApiSettings,
+    swagger_ui::*,
+};
+use std::{net::IpAddr, path::Path, sync::Arc, time::Instant};
+use videohub_proto::{hub, protocol};
+use videohub_server_api_def::defs::{
+    self, ApiError, AuditEntry, Backup, CacheState, Configuration, Credentials, DeviceInfo,
+    Difference, ErrorPosition, Health, HubSummary, InputPort, Job, JobAction, JobRun, Login,
+    OutputPort, PendingRoute, Readiness, Renumber, Role, Salvo, SalvoCapture, ScheduledJob,
+    TableFormat, UserInfo,
+};
 
-type RequestResult<T> = Result<Json<T>, BadRequest<String>>;
+const DEFAULT_JOB_RUNS_LIMIT: usize = 100;
 
-#[openapi(tag = "Hub Informations")]
-#[get("/device_info", format = "json")]
-pub async fn device_info_get() -> RequestResult<DeviceInfo> {
-    let hub_info: protocol::HubInfo = read_hub_info().await?;
-    Ok(Json(DeviceInfo::from(hub_info.device_info)))
+type RequestError = Custom<Json<ApiError>>;
+type RequestResult<T> = Result<Json<T>, RequestError>;
+type CachedResult<T> = Result<Cached<T>, RequestError>;
+
+lazy_static! {
+    static ref STARTED_AT: Instant = Instant::now();
 }
 
-#[openapi(tag = "Ports")]
-#[get("/input_ports", format = "json")]
-pub async fn input_ports_get() -> RequestResult<Vec<InputPort>> {
-    let hub_info = read_hub_info().await?;
-    let response_data: Vec<InputPort> = hub_info
-        .input_labels
+#[openapi(tag = "Hubs")]
+#[get("/", format = "json")]
+pub fn hubs_get(_user: Viewer, monitors: &State<Monitors>) -> Json<Vec<HubSummary>> {
+    let summaries = HUBS
         .iter()
-        .map(|protocol::Label(id, label)| InputPort {
-            id: *id,
-            label: label.to_string(),
+        .map(|config| HubSummary {
+            name: config.name.clone(),
+            address: config.address.to_string(),
+            port: config.port,
+            reachable: monitors
+                .get(&config.name)
+                .is_some_and(Monitor::is_connected),
         })
         .collect();
-    Ok(Json(response_data))
+    Json(summaries)
+}
+
+/// Server-Sent Events stream of the changes reported by the videohub. The data of each event is
+/// a JSON `HubEvent`. The stream ends when the client falls behind and changes are dropped: it
+/// has to read the whole state again when it reconnects.
+#[openapi(tag = "Hubs")]
+#[get("/<hub_name>/events")]
+pub fn events_get(
+    _user: Viewer,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+    shutdown: Shutdown,
+) -> Result<EventStream<BoxStream<'static, Event>>, RequestError> {
+    let events = get_monitor(monitors, hub_name)?.subscribe();
+    let stream = stream::unfold(events, |mut events| async move {
+        match events.recv().await {
+            Ok(event) => Some((Event::json(&to_user(event)), events)),
+            Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => None,
+        }
+    });
+    Ok(EventStream::from(stream.take_until(shutdown).boxed()))
+}
+
+#[openapi(tag = "Hub Informations")]
+#[get("/<hub_name>/device_info", format = "json")]
+pub async fn device_info_get(
+    _user: Viewer,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+) -> CachedResult<DeviceInfo> {
+    let (hub_info, cache_state) = read_hub_info(hub_name, monitors).await?;
+    Ok(Cached {
+        value: DeviceInfo::from(hub_info.device_info),
+        cache_state,
+    })
+}
+
+#[openapi(tag = "Ports")]
+#[get("/<hub_name>/input_ports", format = "json")]
+pub async fn input_ports_get(
+    _user: Viewer,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+) -> CachedResult<Vec<InputPort>> {
+    let (hub_info, cache_state) = read_hub_info(hub_name, monitors).await?;
+    let response_data = to_user(defs::input_ports(&hub_info));
+    Ok(Cached {
+        value: response_data,
+        cache_state,
+    })
 }
 
 #[openapi(tag = "Ports")]
-#[put("/input_ports", format = "json", data = "<input_ports>")]
-pub async fn input_ports_put(input_ports: Json<Vec<InputPort>>) -> RequestResult<Vec<InputPort>> {
-    let labels: Vec<protocol::Label> = input_ports
+#[put("/<hub_name>/input_ports", format = "json", data = "<input_ports>")]
+pub async fn input_ports_put(
+    admin: Admin,
+    client: Option<IpAddr>,
+    hub_name: &str,
+    input_ports: Json<Vec<InputPort>>,
+    monitors: &State<Monitors>,
+) -> RequestResult<Vec<InputPort>> {
+    let author = Author {
+        user: admin.0.name,
+        client,
+    };
+    let labels: Vec<protocol::Label> = from_user(input_ports.0.clone())?
         .iter()
         .map(|input_port| protocol::Label(input_port.id, input_port.label.to_string()))
         .collect();
-    let _ = write_hub_info(protocol::BlockType::InputLabels(labels)).await?;
+    let block = protocol::BlockType::InputLabels(labels);
+    let _ = write_hub_info(hub_name, monitors, &author, block).await?;
     Ok(input_ports.clone())
 }
 
 #[openapi(tag = "Ports")]
-#[get("/output_ports", format = "json")]
-pub async fn output_ports_get() -> RequestResult<Vec<OutputPort>> {
-    let hub_info = read_hub_info().await?;
-
-    let response_data: Vec<OutputPort> = izip!(
-        hub_info.output_labels,
-        hub_info.video_output_locks,
-        hub_info.video_output_routing
-    )
-    .map(
-        |(
-            protocol::Label(_, output_label),
-            protocol::OutputLock(_, lock_state),
-            protocol::Route(dst, src),
-        )| OutputPort {
-            id: dst,
-            input_port: Some(src),
-            label: Some(output_label),
-            lock_state: Some(LockStatus::from(lock_state)),
-        },
-    )
-    .collect();
-    Ok(Json(response_data))
+#[get("/<hub_name>/output_ports", format = "json")]
+pub async fn output_ports_get(
+    _user: Viewer,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+) -> CachedResult<Vec<OutputPort>> {
+    let (hub_info, cache_state) = read_hub_info(hub_name, monitors).await?;
+
+    let response_data = to_user(defs::output_ports(&hub_info));
+    Ok(Cached {
+        value: response_data,
+        cache_state,
+    })
 }
 
+/// Change the routes, labels and locks of outputs. The routes are refused while the videohub is in
+/// take mode, they are staged with `pending_routes` instead.
 #[openapi(tag = "Ports")]
-#[put("/output_ports", format = "json", data = "<output_ports>")]
+#[put("/<hub_name>/output_ports", format = "json", data = "<output_ports>")]
 pub async fn output_ports_put(
+    operator: Operator,
+    client: Option<IpAddr>,
+    hub_name: &str,
     output_ports: Json<Vec<OutputPort>>,
+    monitors: &State<Monitors>,
 ) -> RequestResult<Vec<OutputPort>> {
-    let labels: Vec<protocol::Label> = output_ports
-        .iter()
-        .filter_map(|output_port| {
-            output_port
-                .label
-                .as_ref()
-                .map(|label| protocol::Label(output_port.id, label.to_string()))
-        })
-        .collect();
-    if !labels.is_empty() {
-        let _ = write_hub_info(protocol::BlockType::OutputLabels(labels)).await?;
+    let user = operator.0;
+    let changes = from_user(output_ports.0.clone())?;
+    check_outputs(
+        &user,
+        hub_name,
+        changes.iter().map(|output_port| output_port.id),
+    )?;
+    if changes.iter().any(|change| change.input_port.is_some()) {
+        let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+        if hub_info.configuration.take_mode {
+            return Err(conflict(format!(
+                "The take mode of '{hub_name}' is enabled, the routes are staged with \
+                 /pending_routes and taken at once"
+            )));
+        }
     }
+    let author = Author {
+        user: user.name,
+        client,
+    };
 
-    let lock_statuses: Vec<protocol::OutputLock> = output_ports
-        .iter()
-        .filter_map(|output_port| {
-            output_port.lock_state.as_ref().map(|lock_state| {
-                protocol::OutputLock(output_port.id, lock_state.to_owned().into())
-            })
-        })
-        .collect();
-    if !lock_statuses.is_empty() {
-        let _ = write_hub_info(protocol::BlockType::VideoOutputLocks(lock_statuses)).await?;
+    for block in defs::output_blocks(&changes) {
+        let _ = write_hub_info(hub_name, monitors, &author, block).await?;
+    }
+
+    Ok(output_ports.clone())
+}
+
+/// The input labels as a `port,label` table, in CSV or in TSV with `tsv`.
+#[openapi(tag = "Labels")]
+#[get("/<hub_name>/input_labels?<tsv>")]
+pub async fn input_labels_get(
+    _user: Viewer,
+    hub_name: &str,
+    tsv: Option<bool>,
+    monitors: &State<Monitors>,
+) -> Result<(ContentType, String), RequestError> {
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    Ok(labels_table(&hub_info.input_labels, tsv))
+}
+
+/// Change the input labels from a `port,label` table, in CSV or TSV. Returns what is changed,
+/// with `dry_run` nothing is sent.
+#[openapi(tag = "Labels")]
+#[post("/<hub_name>/input_labels?<dry_run>", data = "<table>")]
+pub async fn input_labels_post(
+    admin: Admin,
+    client: Option<IpAddr>,
+    hub_name: &str,
+    dry_run: Option<bool>,
+    table: &str,
+    monitors: &State<Monitors>,
+) -> RequestResult<Vec<Difference>> {
+    let labels = parse_labels_table(table)?;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    check_label_ports(&labels, hub_info.input_labels.len(), "input")?;
+    let block = protocol::BlockType::InputLabels(labels);
+    let author = Author {
+        user: admin.0.name,
+        client,
+    };
+    import_labels(hub_name, monitors, &author, &hub_info, block, dry_run).await
+}
+
+/// The output labels as a `port,label` table, in CSV or in TSV with `tsv`.
+#[openapi(tag = "Labels")]
+#[get("/<hub_name>/output_labels?<tsv>")]
+pub async fn output_labels_get(
+    _user: Viewer,
+    hub_name: &str,
+    tsv: Option<bool>,
+    monitors: &State<Monitors>,
+) -> Result<(ContentType, String), RequestError> {
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    Ok(labels_table(&hub_info.output_labels, tsv))
+}
+
+/// Change the output labels from a `port,label` table, in CSV or TSV. Returns what is changed,
+/// with `dry_run` nothing is sent.
+#[openapi(tag = "Labels")]
+#[post("/<hub_name>/output_labels?<dry_run>", data = "<table>")]
+pub async fn output_labels_post(
+    operator: Operator,
+    client: Option<IpAddr>,
+    hub_name: &str,
+    dry_run: Option<bool>,
+    table: &str,
+    monitors: &State<Monitors>,
+) -> RequestResult<Vec<Difference>> {
+    let user = operator.0;
+    let labels = parse_labels_table(table)?;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    check_label_ports(&labels, hub_info.output_labels.len(), "output")?;
+    check_outputs(&user, hub_name, labels.iter().map(|label| label.0))?;
+    let block = protocol::BlockType::OutputLabels(labels);
+    let author = Author {
+        user: user.name,
+        client,
+    };
+    import_labels(hub_name, monitors, &author, &hub_info, block, dry_run).await
+}
+
+#[openapi(tag = "Hub Informations")]
+#[get("/<hub_name>/configuration", format = "json")]
+pub async fn configuration_get(
+    _user: Viewer,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+) -> CachedResult<Configuration> {
+    let (hub_info, cache_state) = read_hub_info(hub_name, monitors).await?;
+    Ok(Cached {
+        value: Configuration::from(hub_info.configuration),
+        cache_state,
+    })
+}
+
+/// The changes of the routes, labels and locks of the videohub, the most recent first. They are
+/// made through the API or from the other panels.
+///
+/// - `since`, `until`: RFC 3339 dates
+/// - `kind`: input_label, output_label, output_lock, route, take_mode or device_present
+/// - `limit`: maximum number of entries, 100 by default
+#[openapi(tag = "Hubs")]
+#[allow(clippy::too_many_arguments)]
+#[get(
+    "/<hub_name>/audit?<since>&<until>&<user>&<kind>&<port>&<limit>",
+    format = "json"
+)]
+pub fn audit_get(
+    _user: Viewer,
+    hub_name: &str,
+    since: Option<String>,
+    until: Option<String>,
+    user: Option<String>,
+    kind: Option<String>,
+    port: Option<usize>,
+    limit: Option<usize>,
+    audit: &State<Arc<AuditLog>>,
+) -> RequestResult<Vec<AuditEntry>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let filter = AuditFilter {
+        since,
+        until,
+        user,
+        kind,
+        port: port.map(from_user).transpose()?,
+        limit,
+    };
+    match audit.query(hub_name, &filter) {
+        Ok(entries) => Ok(Json(to_user(entries))),
+        Err(QueryError::InvalidFilter(message)) => Err(bad_request(message)),
+        Err(QueryError::Io(err)) => Err(Custom(
+            Status::InternalServerError,
+            Json(ApiError {
+                message: format!("Failed to read the audit log: {err}"),
+                position: None,
+            }),
+        )),
+    }
+}
+
+/// Read the whole state from the videohub instead of relying on the state mirrored by the server.
+#[openapi(tag = "Hubs")]
+#[post("/<hub_name>/refresh", format = "json")]
+pub async fn refresh_post(
+    _user: Operator,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+) -> RequestResult<CacheState> {
+    let monitor = get_monitor(monitors, hub_name)?;
+    let (_, cache_state) = refresh(monitor).await?;
+    Ok(Json(cache_state))
+}
+
+/// The labels, routes, locks and configuration of the videohub, to restore with the import.
+#[openapi(tag = "Backup")]
+#[get("/<hub_name>/export", format = "json")]
+pub async fn export_get(
+    _user: Viewer,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+) -> CachedResult<Backup> {
+    let (hub_info, cache_state) = read_hub_info(hub_name, monitors).await?;
+    Ok(Cached {
+        value: Backup::from(hub_info).numbered(*PORT_NUMBERING),
+        cache_state,
+    })
+}
+
+/// Restore an export, only the values which differ are sent. Returns what is changed, with
+/// `dry_run` nothing is sent.
+#[openapi(tag = "Backup")]
+#[post("/<hub_name>/import?<dry_run>", format = "json", data = "<backup>")]
+pub async fn import_post(
+    admin: Admin,
+    client: Option<IpAddr>,
+    hub_name: &str,
+    dry_run: Option<bool>,
+    backup: Json<Backup>,
+    monitors: &State<Monitors>,
+) -> RequestResult<Vec<Difference>> {
+    backup.check_version().map_err(bad_request)?;
+    // Numbered as the server which exported it
+    let backup = backup.0.into_protocol().map_err(bad_request)?;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    let differences = backup.differences(&hub_info);
+    if !dry_run.unwrap_or(false) {
+        let author = Author {
+            user: admin.0.name,
+            client,
+        };
+        for block in backup.blocks(&hub_info) {
+            let _ = write_hub_info(hub_name, monitors, &author, block).await?;
+        }
+    }
+    Ok(Json(to_user(differences)))
+}
+
+/// The routes staged by the user, until they are taken or cancelled.
+#[openapi(tag = "Take")]
+#[get("/<hub_name>/pending_routes", format = "json")]
+pub fn pending_routes_get(
+    operator: Operator,
+    hub_name: &str,
+    pending_routes: &State<PendingRoutes>,
+) -> RequestResult<Vec<PendingRoute>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    Ok(Json(to_user(
+        pending_routes.get(hub_name, &operator.0.name),
+    )))
+}
+
+/// Stage routes until the take, when the videohub is in take mode. They replace the routes staged
+/// before for the same outputs. Returns all the routes staged by the user.
+#[openapi(tag = "Take")]
+#[post("/<hub_name>/pending_routes", format = "json", data = "<routes>")]
+pub async fn pending_routes_post(
+    operator: Operator,
+    hub_name: &str,
+    routes: Json<Vec<PendingRoute>>,
+    monitors: &State<Monitors>,
+    pending_routes: &State<PendingRoutes>,
+) -> RequestResult<Vec<PendingRoute>> {
+    let user = operator.0;
+    let routes = from_user(routes.0)?;
+    check_outputs(&user, hub_name, routes.iter().map(|route| route.output))?;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    if !hub_info.configuration.take_mode {
+        return Err(conflict(format!(
+            "The take mode of '{hub_name}' is disabled, its outputs are routed directly"
+        )));
+    }
+    let nb_outputs = hub_info.output_labels.len();
+    check_ports(
+        routes.iter().map(|route| route.output),
+        nb_outputs,
+        "output",
+    )?;
+    let nb_inputs = hub_info.input_labels.len();
+    check_ports(routes.iter().map(|route| route.input), nb_inputs, "input")?;
+    let staged = pending_routes.add(hub_name, &user.name, routes);
+    Ok(Json(to_user(staged)))
+}
+
+/// Cancel the routes staged by the user.
+#[openapi(tag = "Take")]
+#[delete("/<hub_name>/pending_routes")]
+pub fn pending_routes_delete(
+    operator: Operator,
+    hub_name: &str,
+    pending_routes: &State<PendingRoutes>,
+) -> Result<Status, RequestError> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    pending_routes.clear(hub_name, &operator.0.name);
+    Ok(Status::NoContent)
+}
+
+/// Take the routes staged by the user, they are sent in a single block. Returns what was changed.
+#[openapi(tag = "Take")]
+#[post("/<hub_name>/pending_routes/take")]
+pub async fn pending_routes_take_post(
+    operator: Operator,
+    client: Option<IpAddr>,
+    hub_name: &str,
+    monitors: &State<Monitors>,
+    pending_routes: &State<PendingRoutes>,
+) -> RequestResult<Vec<Difference>> {
+    let user = operator.0;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    let routes = pending_routes.clear(hub_name, &user.name);
+    if routes.is_empty() {
+        return Ok(Json(vec![]));
+    }
+    // Things may have changed since the routes were staged
+    if let Err(err) = check_take(&user, hub_name, &hub_info, &routes) {
+        pending_routes.add(hub_name, &user.name, routes);
+        return Err(err);
     }
+    let block = protocol::BlockType::VideoOutputRouting(
+        routes.iter().cloned().map(protocol::Route::from).collect(),
+    );
+    let differences = defs::differences(&hub_info, &block);
+    let author = Author {
+        user: user.name.clone(),
+        client,
+    };
+    if let Err(err) = write_hub_info(hub_name, monitors, &author, block).await {
+        // Staged again for another try
+        pending_routes.add(hub_name, &user.name, routes);
+        return Err(err);
+    }
+    Ok(Json(to_user(differences)))
+}
 
-    let routes: Vec<protocol::Route> = output_ports
+fn check_take(
+    user: &User,
+    hub_name: &str,
+    hub_info: &protocol::HubInfo,
+    routes: &[PendingRoute],
+) -> Result<(), RequestError> {
+    if !hub_info.configuration.take_mode {
+        return Err(conflict(format!(
+            "The take mode of '{hub_name}' is disabled, the staged routes can not be taken"
+        )));
+    }
+    check_outputs(user, hub_name, routes.iter().map(|route| route.output))?;
+    match routes
         .iter()
-        .filter_map(|output_port| {
-            output_port
-                .input_port
-                .as_ref()
-                .map(|input_port| protocol::Route(output_port.id, *input_port))
+        .find(|route| hub_info.is_locked_by_other(route.output))
+    {
+        Some(route) => Err(conflict(format!(
+            "The output {} of '{hub_name}' is locked by another panel",
+            PORT_NUMBERING.to_user(route.output)
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[openapi(tag = "Salvos")]
+#[get("/<hub_name>/salvos", format = "json")]
+pub fn salvos_get(
+    _user: Viewer,
+    hub_name: &str,
+    salvos: &State<Arc<Salvos>>,
+) -> RequestResult<Vec<Salvo>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    salvos
+        .list(hub_name)
+        .map(|salvos| Json(to_user(salvos)))
+        .map_err(salvo_error)
+}
+
+/// Save the current routes of some outputs as a new salvo, the user has to be allowed to route all
+/// of them.
+#[openapi(tag = "Salvos")]
+#[post("/<hub_name>/salvos", format = "json", data = "<capture>")]
+pub async fn salvos_post(
+    operator: Operator,
+    hub_name: &str,
+    capture: Json<SalvoCapture>,
+    monitors: &State<Monitors>,
+    salvos: &State<Arc<Salvos>>,
+) -> RequestResult<Salvo> {
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    let user = operator.0;
+    let salvo = Salvo {
+        owner: Some(user.name.clone()),
+        ..Salvo::capture(&hub_info, &from_user(capture.0)?)
+    };
+    check_outputs(
+        &user,
+        hub_name,
+        salvo.outputs.iter().map(|output| output.id),
+    )?;
+    salvos
+        .create(hub_name, salvo.clone())
+        .map_err(salvo_error)?;
+    Ok(Json(to_user(salvo)))
+}
+
+#[openapi(tag = "Salvos")]
+#[get("/<hub_name>/salvos/<name>", format = "json")]
+pub fn salvo_get(
+    _user: Viewer,
+    hub_name: &str,
+    name: &str,
+    salvos: &State<Arc<Salvos>>,
+) -> RequestResult<Salvo> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    salvos
+        .get(hub_name, name)
+        .map(|salvo| Json(to_user(salvo)))
+        .map_err(salvo_error)
+}
+
+/// Replace a salvo, it is renamed when the name of the body differs. Only its owner or an admin can
+/// replace it, the user replacing it becomes its owner.
+#[openapi(tag = "Salvos")]
+#[put("/<hub_name>/salvos/<name>", format = "json", data = "<salvo>")]
+pub fn salvo_put(
+    operator: Operator,
+    hub_name: &str,
+    name: &str,
+    salvo: Json<Salvo>,
+    salvos: &State<Arc<Salvos>>,
+) -> RequestResult<Salvo> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let user = operator.0;
+    let existing = salvos.get(hub_name, name).map_err(salvo_error)?;
+    check_owner(
+        &user,
+        existing.owner.as_deref(),
+        &format!("the salvo '{name}'"),
+    )?;
+    let salvo = Salvo {
+        owner: Some(user.name.clone()),
+        ..from_user(salvo.0)?
+    };
+    check_outputs(
+        &user,
+        hub_name,
+        salvo.outputs.iter().map(|output| output.id),
+    )?;
+    salvos
+        .replace(hub_name, name, salvo.clone())
+        .map_err(salvo_error)?;
+    Ok(Json(to_user(salvo)))
+}
+
+/// Only the owner of the salvo or an admin can delete it.
+#[openapi(tag = "Salvos")]
+#[delete("/<hub_name>/salvos/<name>")]
+pub fn salvo_delete(
+    operator: Operator,
+    hub_name: &str,
+    name: &str,
+    salvos: &State<Arc<Salvos>>,
+) -> Result<Status, RequestError> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let existing = salvos.get(hub_name, name).map_err(salvo_error)?;
+    check_owner(
+        &operator.0,
+        existing.owner.as_deref(),
+        &format!("the salvo '{name}'"),
+    )?;
+    salvos.delete(hub_name, name).map_err(salvo_error)?;
+    Ok(Status::NoContent)
+}
+
+/// What recalling the salvo would change.
+#[openapi(tag = "Salvos")]
+#[get("/<hub_name>/salvos/<name>/diff", format = "json")]
+pub async fn salvo_diff_get(
+    _user: Viewer,
+    hub_name: &str,
+    name: &str,
+    monitors: &State<Monitors>,
+    salvos: &State<Arc<Salvos>>,
+) -> RequestResult<Vec<Difference>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let salvo = salvos.get(hub_name, name).map_err(salvo_error)?;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    Ok(Json(to_user(recall_differences(&hub_info, &salvo))))
+}
+
+/// Apply a salvo, all its routes are taken at once. Returns what was changed. It is refused in take
+/// mode, and when it routes an output locked by another panel.
+#[openapi(tag = "Salvos")]
+#[post("/<hub_name>/salvos/<name>/recall")]
+pub async fn salvo_recall_post(
+    operator: Operator,
+    client: Option<IpAddr>,
+    hub_name: &str,
+    name: &str,
+    monitors: &State<Monitors>,
+    salvos: &State<Arc<Salvos>>,
+) -> RequestResult<Vec<Difference>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let user = operator.0;
+    let salvo = salvos.get(hub_name, name).map_err(salvo_error)?;
+    check_outputs(
+        &user,
+        hub_name,
+        salvo.outputs.iter().map(|output| output.id),
+    )?;
+    let (hub_info, _) = read_hub_info(hub_name, monitors).await?;
+    check_routes(hub_name, &hub_info, &salvo.outputs).map_err(conflict)?;
+    let differences = recall_differences(&hub_info, &salvo);
+    let author = Author {
+        user: user.name,
+        client,
+    };
+    for block in salvo.blocks() {
+        let _ = write_hub_info(hub_name, monitors, &author, block).await?;
+    }
+    Ok(Json(to_user(differences)))
+}
+
+#[openapi(tag = "Scheduler")]
+#[get("/<hub_name>/jobs", format = "json")]
+pub fn jobs_get(
+    _user: Viewer,
+    hub_name: &str,
+    scheduler: &State<Arc<Scheduler>>,
+) -> RequestResult<Vec<ScheduledJob>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    Ok(Json(to_user(scheduler.list(hub_name))))
+}
+
+/// Schedule a change, once or repeatedly. The job runs with the permissions of the user creating
+/// it, who is its owner.
+#[openapi(tag = "Scheduler")]
+#[post("/<hub_name>/jobs", format = "json", data = "<job>")]
+pub fn jobs_post(
+    operator: Operator,
+    hub_name: &str,
+    job: Json<Job>,
+    salvos: &State<Arc<Salvos>>,
+    scheduler: &State<Arc<Scheduler>>,
+) -> RequestResult<ScheduledJob> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let user = operator.0;
+    let job = Job {
+        owner: Some(user.name.clone()),
+        ..from_user(job.0)?
+    };
+    check_job(&user, hub_name, &job, salvos)?;
+    scheduler.create(hub_name, job.clone()).map_err(job_error)?;
+    scheduler
+        .get(hub_name, &job.name)
+        .map(|job| Json(to_user(job)))
+        .map_err(job_error)
+}
+
+#[openapi(tag = "Scheduler")]
+#[get("/<hub_name>/jobs/<name>", format = "json")]
+pub fn job_get(
+    _user: Viewer,
+    hub_name: &str,
+    name: &str,
+    scheduler: &State<Arc<Scheduler>>,
+) -> RequestResult<ScheduledJob> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    scheduler
+        .get(hub_name, name)
+        .map(|job| Json(to_user(job)))
+        .map_err(job_error)
+}
+
+/// Replace a job, it is renamed when the name of the body differs. Only its owner or an admin can
+/// replace it, the user replacing it becomes its owner.
+#[openapi(tag = "Scheduler")]
+#[put("/<hub_name>/jobs/<name>", format = "json", data = "<job>")]
+pub fn job_put(
+    operator: Operator,
+    hub_name: &str,
+    name: &str,
+    job: Json<Job>,
+    salvos: &State<Arc<Salvos>>,
+    scheduler: &State<Arc<Scheduler>>,
+) -> RequestResult<ScheduledJob> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let user = operator.0;
+    let existing = scheduler.get(hub_name, name).map_err(job_error)?;
+    check_owner(
+        &user,
+        existing.job.owner.as_deref(),
+        &format!("the job '{name}'"),
+    )?;
+    // The job now runs with the permissions of the user saving it
+    let job = Job {
+        owner: Some(user.name.clone()),
+        ..from_user(job.0)?
+    };
+    check_job(&user, hub_name, &job, salvos)?;
+    scheduler
+        .replace(hub_name, name, job.clone())
+        .map_err(job_error)?;
+    scheduler
+        .get(hub_name, &job.name)
+        .map(|job| Json(to_user(job)))
+        .map_err(job_error)
+}
+
+/// Only the owner of the job or an admin can delete it.
+#[openapi(tag = "Scheduler")]
+#[delete("/<hub_name>/jobs/<name>")]
+pub fn job_delete(
+    operator: Operator,
+    hub_name: &str,
+    name: &str,
+    scheduler: &State<Arc<Scheduler>>,
+) -> Result<Status, RequestError> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    let existing = scheduler.get(hub_name, name).map_err(job_error)?;
+    check_owner(
+        &operator.0,
+        existing.job.owner.as_deref(),
+        &format!("the job '{name}'"),
+    )?;
+    scheduler.delete(hub_name, name).map_err(job_error)?;
+    Ok(Status::NoContent)
+}
+
+/// The runs of the jobs, the most recent first. 100 at most by default.
+#[openapi(tag = "Scheduler")]
+#[get("/<hub_name>/job_runs?<limit>", format = "json")]
+pub fn job_runs_get(
+    _user: Viewer,
+    hub_name: &str,
+    limit: Option<usize>,
+    scheduler: &State<Arc<Scheduler>>,
+) -> RequestResult<Vec<JobRun>> {
+    find_hub(hub_name).ok_or_else(|| unknown_hub(hub_name))?;
+    scheduler
+        .runs(hub_name, limit.unwrap_or(DEFAULT_JOB_RUNS_LIMIT))
+        .map(|runs| Json(to_user(runs)))
+        .map_err(|err| {
+            Custom(
+                Status::InternalServerError,
+                Json(ApiError {
+                    message: format!("Failed to read the job runs: {err}"),
+                    position: None,
+                }),
+            )
         })
-        .collect();
-    if !routes.is_empty() {
-        let _ = write_hub_info(protocol::BlockType::VideoOutputRouting(routes)).await?;
+}
+
+/// The metrics of the videohubs and of the server, in the Prometheus text format. No
+/// authentication is required with `public_metrics`.
+#[openapi(tag = "Metrics")]
+#[get("/metrics")]
+pub fn metrics_get(
+    user: Option<Viewer>,
+    monitors: &State<Monitors>,
+    metrics: &State<Metrics>,
+) -> Result<(ContentType, String), Status> {
+    if user.is_none() && !*PUBLIC_METRICS {
+        return Err(Status::Unauthorized);
+    }
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    Ok((content_type, metrics.render(monitors)))
+}
+
+/// Whether the server is running, for liveness probes. No authentication is required.
+#[openapi(tag = "Health")]
+#[get("/health")]
+pub fn health_get() -> Json<Health> {
+    Json(Health {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime: STARTED_AT.elapsed().as_secs(),
+    })
+}
+
+/// Whether the server has a session with the videohubs, all of them or only `hub`, and they
+/// answered recently. Service Unavailable when one is not ready, for readiness probes. No
+/// authentication is required.
+#[openapi(tag = "Health")]
+#[get("/ready?<hub>")]
+pub fn ready_get(
+    hub: Option<&str>,
+    monitors: &State<Monitors>,
+) -> Result<Custom<Json<Readiness>>, RequestError> {
+    let hubs = match hub {
+        Some(hub_name) => vec![get_monitor(monitors, hub_name)?.readiness()],
+        None => monitors.iter().map(Monitor::readiness).collect(),
+    };
+    let ready = hubs.iter().all(|hub| hub.ready);
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    Ok(Custom(status, Json(Readiness { ready, hubs })))
+}
+
+/// Open a session. Its token is returned and set in a cookie.
+#[openapi(tag = "Authentication")]
+#[post("/login", format = "json", data = "<credentials>")]
+pub fn login_post(
+    credentials: Json<Credentials>,
+    auth: &State<Arc<Auth>>,
+    cookies: &CookieJar<'_>,
+) -> RequestResult<Login> {
+    if !auth.is_enabled() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ApiError {
+                message: "The authentication is not enabled on this server".to_string(),
+                position: None,
+            }),
+        ));
     }
+    let (token, user) = auth
+        .login(&credentials.name, &credentials.password)
+        .ok_or_else(|| {
+            Custom(
+                Status::Unauthorized,
+                Json(ApiError {
+                    message: "Invalid user name or password".to_string(),
+                    position: None,
+                }),
+            )
+        })?;
+    cookies.add(session_cookie(token.clone()));
+    Ok(Json(Login {
+        token,
+        user: UserInfo::from(user),
+    }))
+}
 
-    Ok(output_ports.clone())
+#[openapi(tag = "Authentication")]
+#[post("/logout")]
+pub fn logout_post(token: Token, auth: &State<Arc<Auth>>, cookies: &CookieJar<'_>) -> Status {
+    auth.logout(&token.0);
+    cookies.remove(Cookie::named(SESSION_COOKIE));
+    Status::NoContent
 }
 
-#[openapi(tag = "Hub Informations")]
-#[get("/configuration", format = "json")]
-pub async fn configuration_get() -> RequestResult<Configuration> {
-    let hub_info = read_hub_info().await?;
-    Ok(Json(Configuration::from(hub_info.configuration)))
+#[openapi(tag = "Authentication")]
+#[get("/me", format = "json")]
+pub fn me_get(viewer: Viewer) -> Json<UserInfo> {
+    Json(UserInfo::from(viewer.0))
 }
 
-async fn read_hub_info() -> Result<protocol::HubInfo, BadRequest<String>> {
-    let hub = get_hub();
-    let hub_info = hub
+#[catch(401)]
+fn unauthorized() -> Json<ApiError> {
+    Json(ApiError {
+        message: "Authentication required".to_string(),
+        position: None,
+    })
+}
+
+#[catch(403)]
+fn forbidden_catcher(request: &Request) -> Json<ApiError> {
+    Json(ApiError {
+        message: format!("Not allowed to access {}", request.uri()),
+        position: None,
+    })
+}
+
+// The mirrored state, read from the videohub when the server has none yet
+async fn read_hub_info(
+    hub_name: &str,
+    monitors: &Monitors,
+) -> Result<(protocol::HubInfo, CacheState), RequestError> {
+    get_monitor(monitors, hub_name)?
         .read()
         .await
-        .map_err(|_| BadRequest(Some("Failed to read videohub device infos".to_string())))?;
-    Ok(hub_info)
+        .map_err(|err| request_error("Failed to read videohub device infos", &err))
+}
+
+async fn refresh(monitor: &Monitor) -> Result<(protocol::HubInfo, CacheState), RequestError> {
+    monitor
+        .refresh()
+        .await
+        .map_err(|err| request_error("Failed to read videohub device infos", &err))
 }
 
-async fn write_hub_info(hub_info: protocol::BlockType) -> Result<usize, BadRequest<String>> {
-    let hub = get_hub();
-    let nb_bytes = hub
-        .write(hub_info)
+// Write a block, the changes it makes are recorded in the audit log
+async fn write_hub_info(
+    hub_name: &str,
+    monitors: &Monitors,
+    author: &Author,
+    hub_info: protocol::BlockType,
+) -> Result<usize, RequestError> {
+    get_monitor(monitors, hub_name)?
+        .write(author, hub_info)
         .await
-        .map_err(|_| BadRequest(Some("Failed to write infos to videohub device".to_string())))?;
-    Ok(nb_bytes)
+        .map_err(|err| request_error("Failed to write infos to videohub device", &err))
 }
 
-fn get_hub() -> Hub {
-    Hub::new(
-        *VIDEOHUB_IPV4_ADDR,
-        videohub_proto::hub::DEFAULT_DEVICE_PORT,
+fn request_error(message: &str, err: &hub::Error) -> RequestError {
+    let mut message = format!("{message}: {err}");
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    Custom(
+        Status::BadRequest,
+        Json(ApiError {
+            message,
+            position: err.position().cloned().map(ErrorPosition::from),
+        }),
     )
 }
 
-pub fn start() -> Rocket<Build> {
-    rocket::build()
-        .mount(
-            "/hub",
-            openapi_get_routes![
-                device_info_get,
-                input_ports_get,
-                input_ports_put,
-                output_ports_get,
-                output_ports_put,
-                configuration_get,
-            ],
+// The labels numbered for the user
+fn labels_table(labels: &[protocol::Label], tsv: Option<bool>) -> (ContentType, String) {
+    let labels = to_user(labels.to_vec());
+    if tsv.unwrap_or(false) {
+        let content_type = ContentType::new("text", "tab-separated-values");
+        (content_type, defs::labels_table(&labels, TableFormat::Tsv))
+    } else {
+        (
+            ContentType::CSV,
+            defs::labels_table(&labels, TableFormat::Csv),
         )
+    }
+}
+
+// The labels of a table given by the user, numbered as the protocol does
+fn parse_labels_table(table: &str) -> Result<Vec<protocol::Label>, RequestError> {
+    from_user(defs::parse_labels_table(table).map_err(bad_request)?)
+}
+
+fn check_label_ports(
+    labels: &[protocol::Label],
+    nb_ports: usize,
+    side: &str,
+) -> Result<(), RequestError> {
+    check_ports(labels.iter().map(|label| label.0), nb_ports, side)
+}
+
+fn check_ports(
+    mut ports: impl Iterator<Item = usize>,
+    nb_ports: usize,
+    side: &str,
+) -> Result<(), RequestError> {
+    match ports.find(|port| *port >= nb_ports) {
+        Some(port) => Err(bad_request(format!(
+            "Unknown {side} port {}, the videohub has {nb_ports} {side}s",
+            to_user(port)
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Only the labels which differ are sent
+async fn import_labels(
+    hub_name: &str,
+    monitors: &Monitors,
+    author: &Author,
+    hub_info: &protocol::HubInfo,
+    block: protocol::BlockType,
+    dry_run: Option<bool>,
+) -> RequestResult<Vec<Difference>> {
+    let differences = defs::differences(hub_info, &block);
+    if !dry_run.unwrap_or(false) {
+        if let Some(block) = defs::minimal_block(hub_info, &block) {
+            let _ = write_hub_info(hub_name, monitors, author, block).await?;
+        }
+    }
+    Ok(Json(to_user(differences)))
+}
+
+// Forbidden when the user cannot change one of the outputs the job changes
+fn check_job(user: &User, hub_name: &str, job: &Job, salvos: &Salvos) -> Result<(), RequestError> {
+    let outputs = match &job.action {
+        JobAction::Outputs(outputs) => outputs.clone(),
+        JobAction::Salvo(name) => salvos.get(hub_name, name).map_err(salvo_error)?.outputs,
+    };
+    check_outputs(user, hub_name, outputs.iter().map(|output| output.id))
+}
+
+fn job_error(err: JobError) -> RequestError {
+    let status = match err {
+        JobError::AlreadyExists(_) => Status::Conflict,
+        JobError::NotFound(_) => Status::NotFound,
+        JobError::InvalidSchedule(_) => Status::BadRequest,
+        JobError::Io(_) => Status::InternalServerError,
+    };
+    Custom(
+        status,
+        Json(ApiError {
+            message: err.to_string(),
+            position: None,
+        }),
+    )
+}
+
+fn salvo_error(err: SalvoError) -> RequestError {
+    let status = match err {
+        SalvoError::AlreadyExists(_) => Status::Conflict,
+        SalvoError::NotFound(_) => Status::NotFound,
+        SalvoError::Io(_) | SalvoError::Invalid(_) => Status::InternalServerError,
+    };
+    Custom(
+        status,
+        Json(ApiError {
+            message: err.to_string(),
+            position: None,
+        }),
+    )
+}
+
+fn get_monitor<'a>(monitors: &'a Monitors, hub_name: &str) -> Result<&'a Monitor, RequestError> {
+    monitors.get(hub_name).ok_or_else(|| unknown_hub(hub_name))
+}
+
+// Forbidden when the user cannot change one of the outputs
+fn check_outputs(
+    user: &User,
+    hub_name: &str,
+    outputs: impl Iterator<Item = usize>,
+) -> Result<(), RequestError> {
+    user.check_outputs(hub_name, outputs).map_err(forbidden)
+}
+
+// Forbidden when the user is neither the owner of what is changed nor an admin
+fn check_owner(user: &User, owner: Option<&str>, what: &str) -> Result<(), RequestError> {
+    if user.role == Role::Admin || owner == Some(user.name.as_str()) {
+        Ok(())
+    } else {
+        Err(forbidden(format!(
+            "Only the owner of {what} or an admin can change it"
+        )))
+    }
+}
+
+// A value given by the user, numbered as the protocol does
+fn from_user<T: Renumber>(value: T) -> Result<T, RequestError> {
+    PORT_NUMBERING.from_user(value).map_err(bad_request)
+}
+
+// A value numbered as the protocol does, numbered for the user
+fn to_user<T: Renumber>(value: T) -> T {
+    PORT_NUMBERING.to_user(value)
+}
+
+fn bad_request(message: String) -> RequestError {
+    Custom(
+        Status::BadRequest,
+        Json(ApiError {
+            message,
+            position: None,
+        }),
+    )
+}
+
+fn conflict(message: String) -> RequestError {
+    Custom(
+        Status::Conflict,
+        Json(ApiError {
+            message,
+            position: None,
+        }),
+    )
+}
+
+fn forbidden(message: String) -> RequestError {
+    Custom(
+        Status::Forbidden,
+        Json(ApiError {
+            message,
+            position: None,
+        }),
+    )
+}
+
+fn unknown_hub(hub_name: &str) -> RequestError {
+    Custom(
+        Status::NotFound,
+        Json(ApiError {
+            message: format!("Unknown videohub '{hub_name}'"),
+            position: None,
+        }),
+    )
+}
+
+pub fn start() -> Rocket<Build> {
+    config::initialize();
+    lazy_static::initialize(&STARTED_AT);
+    let audit = Arc::new(AuditLog::from_config());
+    let salvos = Arc::new(Salvos::from_config());
+    let mut rocket = rocket::build()
+        .manage(Arc::new(Auth::from_config()))
+        .manage(Monitors::new(audit.clone()))
+        .manage(audit)
+        .manage(salvos)
+        .manage(PendingRoutes::default())
+        .manage(Metrics::default())
+        .manage(Arc::new(Scheduler::from_config()))
+        .attach(Monitors::fairing())
+        .attach(Scheduler::fairing())
+        .attach(Metrics::fairing())
+        .register("/", catchers![unauthorized, forbidden_catcher]);
+    #[cfg(feature = "mqtt")]
+    {
+        rocket = rocket.attach(Bridge::fairing());
+    }
+    let openapi_settings = OpenApiSettings::default();
+    mount_endpoints_and_merged_docs! {
+        rocket, "/", openapi_settings,
+        "/hubs" => openapi_get_routes_spec![
+            hubs_get,
+            events_get,
+            device_info_get,
+            input_ports_get,
+            input_ports_put,
+            output_ports_get,
+            output_ports_put,
+            input_labels_get,
+            input_labels_post,
+            output_labels_get,
+            output_labels_post,
+            configuration_get,
+            refresh_post,
+            audit_get,
+            export_get,
+            import_post,
+            pending_routes_get,
+            pending_routes_post,
+            pending_routes_delete,
+            pending_routes_take_post,
+            salvos_get,
+            salvos_post,
+            salvo_get,
+            salvo_put,
+            salvo_delete,
+            salvo_diff_get,
+            salvo_recall_post,
+            jobs_get,
+            jobs_post,
+            job_get,
+            job_put,
+            job_delete,
+            job_runs_get,
+        ],
+        "/auth" => openapi_get_routes_spec![login_post, logout_post, me_get],
+        "/" => openapi_get_routes_spec![metrics_get, health_get, ready_get],
+    };
+    rocket
         .mount(
             "/doc/",
             make_swagger_ui(&SwaggerUIConfig {
-                url: "/hub/openapi.json".to_owned(),
+                url: "/openapi.json".to_owned(),
                 ..Default::default()
             }),
         )
@@ -171,3 +1200,23 @@ pub fn start() -> Rocket<Build> {
             FileServer::from(Path::new("web-server/frontend/dist/")),
         )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_label_ports() {
+        let labels = parse_labels_table("port,label\n0,Cam 1\n3,Cam 4\n").unwrap();
+        assert!(check_label_ports(&labels, 4, "input").is_ok());
+        let Custom(status, Json(error)) = check_label_ports(&labels, 3, "input").unwrap_err();
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(
+            error.message,
+            format!(
+                "Unknown input port {}, the videohub has 3 inputs",
+                PORT_NUMBERING.to_user(3)
+            )
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use super::config::AUDIT_FILE;
+use chrono::{DateTime, Utc};
+use log::warn;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use videohub_proto::protocol::BlockType;
+use videohub_server_api_def::defs::{AuditEntry, ChangeKind, Difference};
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+/// How much of the audit file is read at once by the queries, from its end
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Who made a change through the API.
+#[derive(Debug, Clone)]
+pub struct Author {
+    pub user: String,
+    pub client: Option<IpAddr>,
+}
+
+/// The audit entry of a change made to a videohub.
+pub fn entry(change: Difference, hub: &str, author: Option<&Author>) -> AuditEntry {
+    AuditEntry {
+        timestamp: Utc::now(),
+        hub: hub.to_string(),
+        user: author.map(|author| author.user.clone()),
+        client: author
+            .and_then(|author| author.client)
+            .map(|client| client.to_string()),
+        kind: change.kind,
+        port: change.port,
+        before: change.current,
+        after: change.target,
+    }
+}
+
+/// The ports a block may change.
+pub fn targets(block: &BlockType) -> Vec<(ChangeKind, usize)> {
+    match block {
+        BlockType::InputLabels(labels) => labels
+            .iter()
+            .map(|label| (ChangeKind::InputLabel, label.0))
+            .collect(),
+        BlockType::OutputLabels(labels) => labels
+            .iter()
+            .map(|label| (ChangeKind::OutputLabel, label.0))
+            .collect(),
+        BlockType::VideoOutputLocks(locks) => locks
+            .iter()
+            .map(|lock| (ChangeKind::OutputLock, lock.0))
+            .collect(),
+        BlockType::VideoOutputRouting(routes) => routes
+            .iter()
+            .map(|route| (ChangeKind::Route, route.0))
+            .collect(),
+        BlockType::Configuration(_) => vec![(ChangeKind::TakeMode, 0)],
+        _ => vec![],
+    }
+}
+
+/// The query parameters of the audit endpoint.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    /// RFC 3339 date, only the changes made since then
+    pub since: Option<String>,
+    /// RFC 3339 date, only the changes made before then
+    pub until: Option<String>,
+    pub user: Option<String>,
+    /// input_label, output_label, output_lock, route, take_mode or device_present
+    pub kind: Option<String>,
+    pub port: Option<usize>,
+    /// Maximum number of entries returned, the most recent first. 100 by default.
+    pub limit: Option<usize>,
+}
+
+struct Query {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    user: Option<String>,
+    kind: Option<ChangeKind>,
+    port: Option<usize>,
+    limit: usize,
+}
+
+impl AuditFilter {
+    fn parse(&self) -> Result<Query, String> {
+        let date = |date: &Option<String>| {
+            date.as_ref()
+                .map(|date| {
+                    DateTime::parse_from_rfc3339(date)
+                        .map(|date| date.with_timezone(&Utc))
+                        .map_err(|err| format!("Invalid date '{date}': {err}"))
+                })
+                .transpose()
+        };
+        let kind = self
+            .kind
+            .as_ref()
+            .map(|kind| {
+                serde_json::from_value(serde_json::Value::String(kind.clone()))
+                    .map_err(|_| format!("Invalid change kind '{kind}'"))
+            })
+            .transpose()?;
+        Ok(Query {
+            since: date(&self.since)?,
+            until: date(&self.until)?,
+            user: self.user.clone(),
+            kind,
+            port: self.port,
+            limit: self.limit.unwrap_or(DEFAULT_QUERY_LIMIT),
+        })
+    }
+}
+
+impl Query {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+            && self
+                .user
+                .as_ref()
+                .is_none_or(|user| entry.user.as_ref() == Some(user))
+            && self.kind.is_none_or(|kind| entry.kind == kind)
+            && self.port.is_none_or(|port| entry.port == port)
+    }
+}
+
+#[derive(Debug)]
+pub enum QueryError {
+    InvalidFilter(String),
+    Io(io::Error),
+}
+
+/// The changes of the videohubs, appended to a JSON lines file.
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Use the `audit_file` of Rocket.toml.
+    pub fn from_config() -> Self {
+        Self::open(&*AUDIT_FILE).unwrap_or_else(|err| {
+            panic!(
+                "Failed to open the audit file {}: {err}",
+                AUDIT_FILE.display()
+            )
+        })
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        let line = serde_json::to_string(&entry).unwrap() + "\n";
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            warn!("Failed to write to the audit file: {err}");
+        }
+    }
+
+    /// The entries of a hub matching the filter, the most recent first. The file is read from its
+    /// end, until enough entries are found or they are older than `since`.
+    pub fn query(&self, hub: &str, filter: &AuditFilter) -> Result<Vec<AuditEntry>, QueryError> {
+        let query = filter.parse().map_err(QueryError::InvalidFilter)?;
+        let file = File::open(&self.path).map_err(QueryError::Io)?;
+        let mut lines = ReverseLines::new(file, READ_BLOCK_SIZE).map_err(QueryError::Io)?;
+        let mut entries = vec![];
+        while entries.len() < query.limit {
+            let line = match lines.next() {
+                Some(line) => line.map_err(QueryError::Io)?,
+                None => break,
+            };
+            let entry = match serde_json::from_str::<AuditEntry>(&line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Invalid entry in the audit file: {err}");
+                    continue;
+                }
+            };
+            // The entries are appended as the changes are made, the next ones are older
+            if query.since.is_some_and(|since| entry.timestamp < since) {
+                break;
+            }
+            if entry.hub == hub && query.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The lines of a file from the last one, read by blocks from its end.
+struct ReverseLines {
+    file: File,
+    block_size: usize,
+    // Where the bytes not read yet end
+    position: u64,
+    // The bytes read but not returned, starting with a line which may be incomplete
+    buffer: Vec<u8>,
+}
+
+impl ReverseLines {
+    fn new(mut file: File, block_size: usize) -> io::Result<Self> {
+        let position = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file,
+            block_size,
+            position,
+            buffer: vec![],
+        })
+    }
+
+    fn read_block(&mut self) -> io::Result<()> {
+        let len = self.block_size.min(self.position as usize);
+        self.position -= len as u64;
+        let mut block = vec![0; len];
+        self.file.seek(SeekFrom::Start(self.position))?;
+        self.file.read_exact(&mut block)?;
+        block.append(&mut self.buffer);
+        self.buffer = block;
+        Ok(())
+    }
+}
+
+impl Iterator for ReverseLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.buffer.iter().rposition(|&byte| byte == b'\n') {
+                Some(index) => {
+                    let line = self.buffer.split_off(index + 1);
+                    self.buffer.truncate(index);
+                    line
+                }
+                None if self.position > 0 => {
+                    if let Err(err) = self.read_block() {
+                        // Nothing more is read after an error
+                        self.position = 0;
+                        self.buffer.clear();
+                        return Some(Err(err));
+                    }
+                    continue;
+                }
+                None if self.buffer.is_empty() => return None,
+                // The first line of the file
+                None => std::mem::take(&mut self.buffer),
+            };
+            // The blank lines, as the end of the file, are skipped
+            if !line.is_empty() {
+                return Some(Ok(String::from_utf8_lossy(&line).into_owned()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs;
+
+    fn temp_path(test_name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "videohub-audit-{test_name}-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_reverse_lines() {
+        let path = temp_path("lines");
+        fs::write(&path, "first\nsecond line\n\nthird\n").unwrap();
+        for block_size in [1, 4, 1024] {
+            let lines = ReverseLines::new(File::open(&path).unwrap(), block_size).unwrap();
+            let lines: Vec<_> = lines.map(Result::unwrap).collect();
+            assert_eq!(lines, ["third", "second line", "first"], "{block_size}");
+        }
+        fs::write(&path, "").unwrap();
+        let mut lines = ReverseLines::new(File::open(&path).unwrap(), 4).unwrap();
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn test_query() {
+        let path = temp_path("query");
+        let audit = AuditLog::open(&path).unwrap();
+        for minute in 0..10 {
+            audit.record(AuditEntry {
+                timestamp: Utc.with_ymd_and_hms(2022, 10, 19, 12, minute, 0).unwrap(),
+                hub: if minute % 2 == 0 { "main" } else { "other" }.to_string(),
+                user: Some("alice".to_string()),
+                client: None,
+                kind: ChangeKind::Route,
+                port: minute as usize,
+                before: None,
+                after: "1".to_string(),
+            });
+        }
+        let ports = |filter: AuditFilter| -> Vec<usize> {
+            let entries = audit.query("main", &filter).unwrap();
+            entries.into_iter().map(|entry| entry.port).collect()
+        };
+        assert_eq!(ports(AuditFilter::default()), [8, 6, 4, 2, 0]);
+        let filter = AuditFilter {
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(ports(filter), [8, 6]);
+        let filter = AuditFilter {
+            since: Some("2022-10-19T12:03:00Z".to_string()),
+            until: Some("2022-10-19T12:08:00Z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ports(filter), [6, 4]);
+        let filter = AuditFilter {
+            kind: Some("route_label".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            audit.query("main", &filter),
+            Err(QueryError::InvalidFilter(_))
+        ));
+    }
+}
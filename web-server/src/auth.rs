@@ -0,0 +1,488 @@
+use super::config::{PORT_NUMBERING, USERS_FILE};
+use log::warn;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::{
+    figment::{
+        providers::{Format, Toml},
+        Figment,
+    },
+    http::{Cookie, SameSite, Status},
+    outcome::IntoOutcome,
+    request::{FromRequest, Outcome, Request},
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{
+        Object, RefOr, Response, Responses, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+    },
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+use videohub_server_api_def::defs::{Role, UserInfo};
+
+/// The cookie holding the session token, the browsers can not set headers on an EventSource.
+pub const SESSION_COOKIE: &str = "videohub_session";
+const SESSION_DURATION: Duration = Duration::from_secs(12 * 60 * 60);
+const TOKEN_LEN: usize = 32;
+const SECURITY_SCHEME: &str = "token";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub role: Role,
+    /// The outputs the user can change, per hub name. All of them when not set.
+    #[serde(default)]
+    pub outputs: Option<HashMap<String, Vec<usize>>>,
+}
+
+impl User {
+    // Everybody when the authentication is disabled
+    fn anonymous() -> Self {
+        Self {
+            name: "anonymous".to_string(),
+            role: Role::Admin,
+            outputs: None,
+        }
+    }
+
+    pub fn can_change_output(&self, hub_name: &str, output: usize) -> bool {
+        if self.role == Role::Admin {
+            return true;
+        }
+        match &self.outputs {
+            Some(outputs) => outputs
+                .get(hub_name)
+                .is_some_and(|outputs| outputs.contains(&output)),
+            None => true,
+        }
+    }
+
+    /// Fails on the first of the outputs, numbered as the protocol does, the user can not change.
+    pub fn check_outputs(
+        &self,
+        hub_name: &str,
+        outputs: impl IntoIterator<Item = usize>,
+    ) -> Result<(), String> {
+        // The outputs of the users are numbered as in the API
+        let mut outputs = outputs
+            .into_iter()
+            .map(|output| PORT_NUMBERING.to_user(output));
+        match outputs.find(|output| !self.can_change_output(hub_name, *output)) {
+            Some(output) => Err(format!(
+                "User '{}' is not allowed to change the output {output} of '{hub_name}'",
+                self.name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<User> for UserInfo {
+    fn from(rhs: User) -> Self {
+        Self {
+            name: rhs.name,
+            role: rhs.role,
+            outputs: rhs.outputs,
+        }
+    }
+}
+
+/// Where the user accounts come from.
+pub trait UserProvider: Send + Sync {
+    /// The user having these credentials.
+    fn authenticate(&self, name: &str, password: &str) -> Option<User>;
+    /// The user owning this API token.
+    fn find_by_token(&self, token: &str) -> Option<User>;
+    /// The user of this name.
+    fn find_by_name(&self, name: &str) -> Option<User>;
+}
+
+#[derive(Debug, Deserialize)]
+struct UserEntry {
+    #[serde(flatten)]
+    user: User,
+    /// bcrypt hash of the password
+    password: String,
+    /// API tokens for the scripts, which do not need to log in
+    #[serde(default)]
+    tokens: Vec<String>,
+}
+
+/// The users listed in a TOML file:
+///
+/// ```toml
+/// [[users]]
+/// name = "studio-a"
+/// password = "$2y$10$..."
+/// role = "operator"
+/// tokens = ["..."]
+/// [users.outputs]
+/// main = [0, 1, 2]
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct FileUsers {
+    users: Vec<UserEntry>,
+}
+
+impl FileUsers {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<rocket::figment::Error>> {
+        // A missing file would be read as an empty one
+        let content = fs::read_to_string(path).map_err(|err| Box::new(err.to_string().into()))?;
+        Ok(Figment::from(Toml::string(&content)).extract()?)
+    }
+}
+
+impl UserProvider for FileUsers {
+    fn authenticate(&self, name: &str, password: &str) -> Option<User> {
+        self.users
+            .iter()
+            .find(|entry| entry.user.name == name)
+            .filter(|entry| bcrypt::verify(password, &entry.password).unwrap_or(false))
+            .map(|entry| entry.user.clone())
+    }
+
+    fn find_by_token(&self, token: &str) -> Option<User> {
+        // Compared in constant time, the time taken does not tell how much of a token is right
+        self.users
+            .iter()
+            .find(|entry| {
+                entry
+                    .tokens
+                    .iter()
+                    .any(|t| t.as_bytes().ct_eq(token.as_bytes()).into())
+            })
+            .map(|entry| entry.user.clone())
+    }
+
+    fn find_by_name(&self, name: &str) -> Option<User> {
+        self.users
+            .iter()
+            .find(|entry| entry.user.name == name)
+            .map(|entry| entry.user.clone())
+    }
+}
+
+struct Session {
+    user: User,
+    expires_at: Instant,
+}
+
+/// The users and their login sessions. Without a provider, the authentication is disabled.
+pub struct Auth {
+    provider: Option<Box<dyn UserProvider>>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Auth {
+    pub fn new(provider: Option<Box<dyn UserProvider>>) -> Self {
+        Self {
+            provider,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Use the `users_file` of Rocket.toml.
+    pub fn from_config() -> Self {
+        let provider = USERS_FILE.as_ref().map(|path| {
+            let users = FileUsers::load(path).unwrap_or_else(|err| {
+                panic!("Failed to load the users file {}: {err}", path.display())
+            });
+            Box::new(users) as Box<dyn UserProvider>
+        });
+        if provider.is_none() {
+            warn!("No users_file configured, anybody can use the API");
+        }
+        Self::new(provider)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// Open a session, returns its token.
+    pub fn login(&self, name: &str, password: &str) -> Option<(String, User)> {
+        let user = self.provider.as_ref()?.authenticate(name, password)?;
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LEN)
+            .map(char::from)
+            .collect();
+        let session = Session {
+            user: user.clone(),
+            expires_at: Instant::now() + SESSION_DURATION,
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires_at > Instant::now());
+        sessions.insert(token.clone(), session);
+        Some((token, user))
+    }
+
+    /// The current account of a user, with its role and outputs. Everybody is the anonymous
+    /// admin when the authentication is disabled.
+    pub fn user(&self, name: &str) -> Option<User> {
+        match &self.provider {
+            Some(provider) => provider.find_by_name(name),
+            None => Some(User::anonymous()),
+        }
+    }
+
+    pub fn logout(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+
+    fn find_user(&self, token: &str) -> Option<User> {
+        let session_user = self
+            .sessions
+            .lock()
+            .unwrap()
+            .get(token)
+            .filter(|session| session.expires_at > Instant::now())
+            .map(|session| session.user.clone());
+        session_user.or_else(|| self.provider.as_ref()?.find_by_token(token))
+    }
+
+    async fn authorize(&self, request: &Request<'_>, role: Role) -> Outcome<User, ()> {
+        if !self.is_enabled() {
+            return Outcome::Success(User::anonymous());
+        }
+        let token = match request.guard::<Token>().await {
+            Outcome::Success(Token(token)) => token,
+            _ => return Outcome::Failure((Status::Unauthorized, ())),
+        };
+        match self.find_user(&token) {
+            Some(user) if user.role >= role => Outcome::Success(user),
+            Some(_) => Outcome::Failure((Status::Forbidden, ())),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+pub fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+/// The token of the request, from the `Authorization: Bearer` header or the session cookie.
+pub struct Token(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Token {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let header_token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string);
+        let cookie_token = || {
+            request
+                .cookies()
+                .get(SESSION_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+        };
+        header_token
+            .or_else(cookie_token)
+            .map(Token)
+            .into_outcome((Status::Unauthorized, ()))
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for Token {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(security_input())
+    }
+
+    fn get_responses(_gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Ok(error_responses(&[(401, "Missing token")]))
+    }
+}
+
+fn security_input() -> RequestHeaderInput {
+    let scheme = SecurityScheme {
+        description: Some(format!(
+            "Session or API token, also accepted in the `{SESSION_COOKIE}` cookie"
+        )),
+        data: SecuritySchemeData::Http {
+            scheme: "bearer".to_string(),
+            bearer_format: None,
+        },
+        extensions: Object::default(),
+    };
+    let mut requirement = SecurityRequirement::new();
+    requirement.insert(SECURITY_SCHEME.to_string(), vec![]);
+    RequestHeaderInput::Security(SECURITY_SCHEME.to_string(), scheme, requirement)
+}
+
+fn error_responses(errors: &[(u16, &str)]) -> Responses {
+    let mut responses = Responses::default();
+    for (status, description) in errors {
+        let response = Response {
+            description: description.to_string(),
+            ..Default::default()
+        };
+        responses
+            .responses
+            .insert(status.to_string(), RefOr::Object(response));
+    }
+    responses
+}
+
+// A request guard letting through the users having at least the given role
+macro_rules! role_guard {
+    ($(#[$attr:meta])* $guard:ident, $role:expr) => {
+        $(#[$attr])*
+        pub struct $guard(pub User);
+
+        #[rocket::async_trait]
+        impl<'r> FromRequest<'r> for $guard {
+            type Error = ();
+
+            async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+                let auth = match request.rocket().state::<Arc<Auth>>() {
+                    Some(auth) => auth,
+                    None => return Outcome::Failure((Status::InternalServerError, ())),
+                };
+                auth.authorize(request, $role).await.map($guard)
+            }
+        }
+
+        impl<'r> OpenApiFromRequest<'r> for $guard {
+            fn from_request_input(
+                _gen: &mut OpenApiGenerator,
+                _name: String,
+                _required: bool,
+            ) -> rocket_okapi::Result<RequestHeaderInput> {
+                Ok(security_input())
+            }
+
+            fn get_responses(_gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+                Ok(error_responses(&[
+                    (401, "Not logged in"),
+                    (403, "The role of the user does not allow it"),
+                ]))
+            }
+        }
+    };
+}
+
+role_guard!(
+    /// Any logged in user
+    Viewer,
+    Role::Viewer
+);
+role_guard!(
+    /// A user allowed to change the outputs
+    Operator,
+    Role::Operator
+);
+role_guard!(
+    /// A user allowed to change everything
+    Admin,
+    Role::Admin
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn load(test_name: &str) -> FileUsers {
+        // The lowest cost, to keep the tests fast
+        let hash = bcrypt::hash("secret", 4).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "videohub-users-{test_name}-{}.toml",
+            std::process::id()
+        ));
+        let content = format!(
+            r#"
+[[users]]
+name = "alice"
+password = "{hash}"
+role = "operator"
+tokens = ["alice-token"]
+[users.outputs]
+main = [0, 1]
+
+[[users]]
+name = "bob"
+password = "{hash}"
+role = "viewer"
+"#
+        );
+        fs::write(&path, content).unwrap();
+        FileUsers::load(&path).unwrap()
+    }
+
+    #[test]
+    fn test_load_users() {
+        let users = load("load");
+        let alice = users.find_by_name("alice").unwrap();
+        assert_eq!(alice.role, Role::Operator);
+        assert_eq!(alice.outputs.unwrap()["main"], [0, 1]);
+        assert_eq!(users.find_by_name("bob").unwrap().role, Role::Viewer);
+        assert!(users.find_by_name("carol").is_none());
+
+        let path = std::env::temp_dir().join(format!("videohub-users-{}.toml", std::process::id()));
+        fs::write(&path, "[[users]]\nname = \"carol\"\n").unwrap();
+        assert!(FileUsers::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let users = load("authenticate");
+        assert_eq!(users.authenticate("alice", "secret").unwrap().name, "alice");
+        assert!(users.authenticate("alice", "wrong").is_none());
+        assert!(users.authenticate("carol", "secret").is_none());
+        assert_eq!(users.find_by_token("alice-token").unwrap().name, "alice");
+        assert!(users.find_by_token("alice-toke").is_none());
+        assert!(users.find_by_token("").is_none());
+    }
+
+    #[test]
+    fn test_sessions() {
+        let auth = Auth::new(Some(Box::new(load("sessions"))));
+        assert!(auth.login("bob", "wrong").is_none());
+        let (token, user) = auth.login("bob", "secret").unwrap();
+        assert_eq!(user.name, "bob");
+        assert_eq!(auth.find_user(&token).unwrap().name, "bob");
+        assert_eq!(auth.find_user("alice-token").unwrap().name, "alice");
+        auth.logout(&token);
+        assert!(auth.find_user(&token).is_none());
+
+        // Everybody is an admin without authentication
+        let auth = Auth::new(None);
+        assert_eq!(auth.user("anybody").unwrap().role, Role::Admin);
+    }
+
+    #[test]
+    fn test_roles() {
+        assert!(Role::Viewer < Role::Operator);
+        assert!(Role::Operator < Role::Admin);
+
+        let users = load("roles");
+        let alice = users.find_by_name("alice").unwrap();
+        assert!(alice.can_change_output("main", 1));
+        assert!(!alice.can_change_output("main", 2));
+        assert!(!alice.can_change_output("other", 0));
+        let admin = User {
+            role: Role::Admin,
+            ..alice
+        };
+        assert!(admin.can_change_output("other", 0));
+    }
+}
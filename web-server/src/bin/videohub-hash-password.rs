@@ -0,0 +1,11 @@
+//! Print the bcrypt hash of the password read from stdin, for the `password` of the users file.
+
+use std::io::{self, BufRead};
+
+fn main() -> anyhow::Result<()> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]);
+    println!("{}", bcrypt::hash(password, bcrypt::DEFAULT_COST)?);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use rocket::{
+    response::{self, Responder},
+    serde::json::Json,
+    Request, Response,
+};
+use rocket_okapi::JsonSchema;
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{Header, ParameterValue, RefOr, Responses},
+    response::OpenApiResponderInner,
+    util::ensure_status_code_exists,
+};
+use serde::Serialize;
+use videohub_server_api_def::defs::CacheState;
+
+/// A JSON response built from the state mirrored by the server. How fresh it is is given by the
+/// `X-Videohub-State-Age` (in seconds) and `X-Hub-State` (`live` or `stale`) headers. The HTTP
+/// `Age` header is left to the caches.
+pub struct Cached<T> {
+    pub value: T,
+    pub cache_state: CacheState,
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Cached<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let state = if self.cache_state.stale {
+            "stale"
+        } else {
+            "live"
+        };
+        Response::build_from(Json(self.value).respond_to(request)?)
+            .raw_header("X-Videohub-State-Age", self.cache_state.age.to_string())
+            .raw_header("X-Hub-State", state)
+            .ok()
+    }
+}
+
+impl<T: Serialize + JsonSchema + Send> OpenApiResponderInner for Cached<T> {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Json::<T>::responses(gen)?;
+        if let RefOr::Object(response) = ensure_status_code_exists(&mut responses, 200) {
+            let mut header = |name: &str, description: &str, schema| {
+                let header = Header {
+                    description: Some(description.to_owned()),
+                    required: true,
+                    deprecated: false,
+                    allow_empty_value: false,
+                    value: ParameterValue::Schema {
+                        style: None,
+                        explode: None,
+                        allow_reserved: false,
+                        schema,
+                        example: None,
+                        examples: None,
+                    },
+                    extensions: Default::default(),
+                };
+                response.headers.insert(name.to_owned(), header.into());
+            };
+            header(
+                "X-Videohub-State-Age",
+                "Seconds since the videohub last confirmed its state",
+                gen.json_schema::<u64>(),
+            );
+            header(
+                "X-Hub-State",
+                "`stale` when the session with the videohub is lost or it stopped answering, `live` \
+                 otherwise",
+                gen.json_schema::<String>(),
+            );
+        }
+        Ok(responses)
+    }
+}
@@ -1,14 +1,154 @@
 use lazy_static::lazy_static;
 use rocket::Config;
-use std::{net::Ipv4Addr, str::FromStr};
+use serde::Deserialize;
+use std::{collections::HashSet, net::Ipv4Addr, path::PathBuf};
+use videohub_proto::DEFAULT_DEVICE_PORT;
+use videohub_server_api_def::defs::Numbering;
 
-const DEFAULT_VIDEOHUB_IPV4_ADDR: &str = "10.26.135.196";
+const DEFAULT_VIDEOHUB_IPV4_ADDR: Ipv4Addr = Ipv4Addr::new(10, 26, 135, 196);
+const DEFAULT_HUB_NAME: &str = "default";
+const DEFAULT_AUDIT_FILE: &str = "audit.jsonl";
+const DEFAULT_SALVOS_DIR: &str = "salvos";
+const DEFAULT_JOBS_FILE: &str = "jobs.json";
+const DEFAULT_JOB_RUNS_FILE: &str = "job_runs.jsonl";
+#[cfg(feature = "mqtt")]
+const DEFAULT_MQTT_PORT: u16 = 1883;
+#[cfg(feature = "mqtt")]
+const DEFAULT_MQTT_CLIENT_ID: &str = "videohub-server";
+#[cfg(feature = "mqtt")]
+const DEFAULT_MQTT_PREFIX: &str = "videohub";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HubConfig {
+    /// Name used in the URLs: /hubs/<name>/...
+    pub name: String,
+    pub address: Ipv4Addr,
+    #[serde(default = "default_device_port")]
+    pub port: u16,
+}
+
+fn default_device_port() -> u16 {
+    DEFAULT_DEVICE_PORT
+}
 
 lazy_static! {
-    pub static ref VIDEOHUB_IPV4_ADDR: Ipv4Addr = {
-        let s: String = Config::figment()
-            .extract_inner("videohub_addr")
-            .unwrap_or_else(|_| DEFAULT_VIDEOHUB_IPV4_ADDR.to_string());
-        Ipv4Addr::from_str(&s).unwrap()
+    /// The videohubs listed in the `hubs` array of Rocket.toml, their names are unique. When
+    /// there is none, a single hub named "default" is created from `videohub_addr`.
+    pub static ref HUBS: Vec<HubConfig> = {
+        let hubs: Vec<HubConfig> = optional("hubs").unwrap_or_default();
+        let mut names = HashSet::new();
+        if let Some(hub) = hubs.iter().find(|hub| !names.insert(&hub.name)) {
+            panic!("Several videohubs are named '{}' in the configuration", hub.name);
+        }
+        if !hubs.is_empty() {
+            return hubs;
+        }
+        vec![HubConfig {
+            name: DEFAULT_HUB_NAME.to_string(),
+            address: optional("videohub_addr").unwrap_or(DEFAULT_VIDEOHUB_IPV4_ADDR),
+            port: DEFAULT_DEVICE_PORT,
+        }]
     };
 }
+
+lazy_static! {
+    /// The `users_file` of Rocket.toml, listing the accounts allowed to use the API.
+    pub static ref USERS_FILE: Option<PathBuf> = optional("users_file");
+
+    /// The `audit_file` of Rocket.toml, where the changes of the videohubs are appended.
+    pub static ref AUDIT_FILE: PathBuf =
+        optional("audit_file").unwrap_or_else(|| PathBuf::from(DEFAULT_AUDIT_FILE));
+
+    /// The `salvos_dir` of Rocket.toml, where the salvos of every videohub are saved.
+    pub static ref SALVOS_DIR: PathBuf =
+        optional("salvos_dir").unwrap_or_else(|| PathBuf::from(DEFAULT_SALVOS_DIR));
+
+    /// The `jobs_file` of Rocket.toml, where the scheduled jobs are saved.
+    pub static ref JOBS_FILE: PathBuf =
+        optional("jobs_file").unwrap_or_else(|| PathBuf::from(DEFAULT_JOBS_FILE));
+
+    /// The `port_numbering` of Rocket.toml, how the ports are numbered in the API: `zero_based`, as
+    /// the protocol does, or `one_based`. The files written by the server keep the protocol
+    /// numbering.
+    pub static ref PORT_NUMBERING: Numbering = optional("port_numbering").unwrap_or_default();
+
+    /// The `public_metrics` of Rocket.toml, whether the metrics can be scraped without
+    /// authentication.
+    pub static ref PUBLIC_METRICS: bool = optional("public_metrics").unwrap_or_default();
+
+    /// The `job_runs_file` of Rocket.toml, where the executions of the scheduled jobs are appended.
+    pub static ref JOB_RUNS_FILE: PathBuf =
+        optional("job_runs_file").unwrap_or_else(|| PathBuf::from(DEFAULT_JOB_RUNS_FILE));
+}
+
+/// The MQTT broker the videohubs are bridged to
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    /// The first level of all the topics: <prefix>/<hub>/...
+    #[serde(default = "default_mqtt_prefix")]
+    pub prefix: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The account of `users_file` the commands are checked against and written as. Without it,
+    /// the bridge is read-only when the authentication is enabled.
+    pub user: Option<String>,
+    /// Only publish the state, the commands are not subscribed to.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[cfg(feature = "mqtt")]
+fn default_mqtt_port() -> u16 {
+    DEFAULT_MQTT_PORT
+}
+
+#[cfg(feature = "mqtt")]
+fn default_mqtt_client_id() -> String {
+    DEFAULT_MQTT_CLIENT_ID.to_string()
+}
+
+#[cfg(feature = "mqtt")]
+fn default_mqtt_prefix() -> String {
+    DEFAULT_MQTT_PREFIX.to_string()
+}
+
+#[cfg(feature = "mqtt")]
+lazy_static! {
+    /// The `mqtt` table of Rocket.toml, there is no bridge when it is not set.
+    pub static ref MQTT: Option<MqttConfig> = optional("mqtt");
+}
+
+/// Read the settings of Rocket.toml now, so that an invalid one fails at launch rather than when
+/// it is first used.
+pub fn initialize() {
+    lazy_static::initialize(&HUBS);
+    lazy_static::initialize(&PORT_NUMBERING);
+    lazy_static::initialize(&USERS_FILE);
+    lazy_static::initialize(&PUBLIC_METRICS);
+    lazy_static::initialize(&AUDIT_FILE);
+    lazy_static::initialize(&SALVOS_DIR);
+    lazy_static::initialize(&JOBS_FILE);
+    lazy_static::initialize(&JOB_RUNS_FILE);
+    #[cfg(feature = "mqtt")]
+    lazy_static::initialize(&MQTT);
+}
+
+// A setting of Rocket.toml which may be missing, but not invalid
+fn optional<T: serde::de::DeserializeOwned>(key: &str) -> Option<T> {
+    let figment = Config::figment();
+    figment.find_value(key).ok()?;
+    match figment.extract_inner(key) {
+        Ok(value) => Some(value),
+        Err(err) => panic!("Invalid `{key}` in the configuration: {err}"),
+    }
+}
+
+pub fn find_hub(name: &str) -> Option<&'static HubConfig> {
+    HUBS.iter().find(|hub| hub.name == name)
+}
@@ -2,7 +2,17 @@
 extern crate rocket;
 
 mod api;
+mod audit;
+mod auth;
+mod cached;
 mod config;
+mod metrics;
+mod monitor;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod pending;
+mod salvos;
+mod scheduler;
 
 #[launch]
 fn rocket() -> _ {
@@ -0,0 +1,208 @@
+use super::{
+    config::PORT_NUMBERING,
+    monitor::{Monitor, Monitors},
+};
+use rocket::fairing::AdHoc;
+use std::{collections::BTreeMap, fmt::Display, sync::Mutex};
+use videohub_proto::protocol::{LockStatus, PortStatus};
+
+/// The requests answered by the server. The state of the videohubs is read from their monitors
+/// when the metrics are rendered.
+#[derive(Default)]
+pub struct Metrics {
+    // By method, route and status code
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+}
+
+impl Metrics {
+    /// Count the requests once they are answered.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_response("Request metrics", |request, response| {
+            Box::pin(async move {
+                // The routes are counted by their template, the other paths together
+                let route = request
+                    .route()
+                    .map_or_else(|| "unmatched".to_string(), |route| route.uri.to_string());
+                if let Some(metrics) = request.rocket().state::<Metrics>() {
+                    let key = (request.method().to_string(), route, response.status().code);
+                    *metrics.requests.lock().unwrap().entry(key).or_default() += 1;
+                }
+            })
+        })
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self, monitors: &Monitors) -> String {
+        let mut output = Output::default();
+
+        output.family(
+            "videohub_up",
+            "gauge",
+            "Whether the session with the videohub is open",
+        );
+        for monitor in monitors.iter() {
+            let up = u8::from(monitor.is_connected());
+            output.sample("videohub_up", &[("hub", monitor.name())], up);
+        }
+
+        output.family(
+            "videohub_prelude_latency_seconds",
+            "gauge",
+            "How long the videohub took to send its state when the session was opened",
+        );
+        for monitor in monitors.iter() {
+            if let Some(latency) = monitor.stats().prelude_latency {
+                let labels = [("hub", monitor.name())];
+                output.sample(
+                    "videohub_prelude_latency_seconds",
+                    &labels,
+                    latency.as_secs_f64(),
+                );
+            }
+        }
+
+        output.family(
+            "videohub_ping_rtt_seconds",
+            "gauge",
+            "Round trip time of the last ping acknowledged by the videohub",
+        );
+        for monitor in monitors.iter() {
+            if let Some(rtt) = monitor.stats().ping_rtt {
+                let labels = [("hub", monitor.name())];
+                output.sample("videohub_ping_rtt_seconds", &labels, rtt.as_secs_f64());
+            }
+        }
+
+        output.family(
+            "videohub_route_changes_total",
+            "counter",
+            "Routes changed since the server started, through the API or from the other panels",
+        );
+        for monitor in monitors.iter() {
+            let labels = [("hub", monitor.name())];
+            let route_changes = monitor.stats().route_changes;
+            output.sample("videohub_route_changes_total", &labels, route_changes);
+        }
+
+        output.family(
+            "videohub_output_locks",
+            "gauge",
+            "Outputs by lock state, as seen from the session of the server",
+        );
+        for monitor in monitors.iter() {
+            output_locks(&mut output, monitor);
+        }
+
+        output.family(
+            "videohub_input_connected",
+            "gauge",
+            "Whether a source is connected to the input, with its interface",
+        );
+        for monitor in monitors.iter() {
+            if let Some((hub_info, _)) = monitor.hub_info() {
+                let statuses = &hub_info.video_input_status;
+                port_statuses(&mut output, "videohub_input_connected", monitor, statuses);
+            }
+        }
+
+        output.family(
+            "videohub_output_connected",
+            "gauge",
+            "Whether a destination is connected to the output, with its interface",
+        );
+        for monitor in monitors.iter() {
+            if let Some((hub_info, _)) = monitor.hub_info() {
+                let statuses = &hub_info.video_output_status;
+                port_statuses(&mut output, "videohub_output_connected", monitor, statuses);
+            }
+        }
+
+        output.family(
+            "videohub_alarm_ok",
+            "gauge",
+            "Whether an alarm of the videohub, like a fan or a power supply, reports OK",
+        );
+        for monitor in monitors.iter() {
+            let Some((hub_info, _)) = monitor.hub_info() else {
+                continue;
+            };
+            for (name, status) in &hub_info.alarm_status {
+                let labels = [("hub", monitor.name()), ("name", name), ("status", status)];
+                output.sample("videohub_alarm_ok", &labels, u8::from(status == "OK"));
+            }
+        }
+
+        output.family(
+            "videohub_http_requests_total",
+            "counter",
+            "Requests answered by the server, by route",
+        );
+        for ((method, route, status), count) in self.requests.lock().unwrap().iter() {
+            let status = status.to_string();
+            let labels = [
+                ("method", method.as_str()),
+                ("route", route),
+                ("status", &status),
+            ];
+            output.sample("videohub_http_requests_total", &labels, count);
+        }
+
+        output.0
+    }
+}
+
+fn output_locks(output: &mut Output, monitor: &Monitor) {
+    let Some((hub_info, _)) = monitor.hub_info() else {
+        return;
+    };
+    for (state, lock_status) in [
+        ("locked", LockStatus::Locked),
+        ("owned", LockStatus::Owned),
+        ("unlocked", LockStatus::Unlocked),
+    ] {
+        let count = hub_info
+            .video_output_locks
+            .iter()
+            .filter(|lock| lock.1 == lock_status)
+            .count();
+        let labels = [("hub", monitor.name()), ("state", state)];
+        output.sample("videohub_output_locks", &labels, count);
+    }
+}
+
+// The ports are numbered as in the API
+fn port_statuses(output: &mut Output, name: &str, monitor: &Monitor, statuses: &[PortStatus]) {
+    for PortStatus(port, interface) in statuses {
+        let port = PORT_NUMBERING.to_user(*port).to_string();
+        let labels = [
+            ("hub", monitor.name()),
+            ("port", &port),
+            ("interface", interface),
+        ];
+        output.sample(name, &labels, u8::from(interface != "None"));
+    }
+}
+
+#[derive(Default)]
+struct Output(String);
+
+impl Output {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        self.0 += &format!("# HELP {name} {help}\n# TYPE {name} {kind}\n");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(label, value)| format!("{label}=\"{}\"", escape(value)))
+            .collect();
+        self.0 += &format!("{name}{{{}}} {value}\n", labels.join(","));
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,497 @@
+use super::{
+    audit::{self, AuditLog, Author},
+    config::{HubConfig, HUBS},
+};
+use log::{info, warn};
+use rocket::{
+    fairing::AdHoc,
+    tokio::{
+        self,
+        sync::{broadcast, Mutex as AsyncMutex},
+        time::sleep,
+    },
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use videohub_proto::{hub, protocol, Hub, Message, Session, SessionWriter};
+use videohub_server_api_def::defs::{
+    self, CacheState, ChangeKind, Difference, HubEvent, HubReadiness,
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const EVENTS_CAPACITY: usize = 256;
+/// How long the changes written through the API are waited for, to know who made them
+const ATTRIBUTION_DELAY: Duration = Duration::from_secs(10);
+/// How often the round trip time of the sessions is measured
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a videohub can stay silent before its state is not trusted, three pings unanswered
+const STALE_AFTER: Duration = Duration::from_secs(45);
+
+/// Keeps a session open with every videohub, mirrors their state, records the changes they report
+/// in the audit log and forwards them to the subscribers.
+#[derive(Clone)]
+pub struct Monitors {
+    hubs: HashMap<String, Arc<Monitor>>,
+}
+
+pub struct Monitor {
+    config: &'static HubConfig,
+    audit: Arc<AuditLog>,
+    events: broadcast::Sender<HubEvent>,
+    mirror: RwLock<Mirror>,
+    expected: Mutex<Vec<Expected>>,
+    stats: Mutex<Stats>,
+    // The connection of the session while it is open, the blocks and the pings are sent one at a
+    // time over it
+    writer: AsyncMutex<Option<SessionWriter>>,
+}
+
+/// Measures of the session with a videohub, for the metrics and the readiness
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    /// How long the videohub took to send its prelude, when the session was last opened
+    pub prelude_latency: Option<Duration>,
+    /// The round trip time of the last ping acknowledged
+    pub ping_rtt: Option<Duration>,
+    /// The routes changed since the server started, through the API or from the other panels
+    pub route_changes: u64,
+    // When the last ping was sent, until it is acknowledged
+    ping_sent_at: Option<Instant>,
+}
+
+// A change written through the API, not yet reported by the videohub
+struct Expected {
+    kind: ChangeKind,
+    port: usize,
+    author: Author,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct Mirror {
+    hub_info: Option<protocol::HubInfo>,
+    /// When the videohub last sent something, confirming the state
+    last_seen: Option<Instant>,
+    connected: bool,
+}
+
+impl Monitors {
+    pub fn new(audit: Arc<AuditLog>) -> Self {
+        let hubs = HUBS
+            .iter()
+            .map(|config| {
+                let monitor = Monitor::new(config, audit.clone());
+                (config.name.clone(), Arc::new(monitor))
+            })
+            .collect();
+        Self { hubs }
+    }
+
+    pub fn get(&self, hub_name: &str) -> Option<&Monitor> {
+        self.hubs.get(hub_name).map(Arc::as_ref)
+    }
+
+    /// The monitors in the order of the configuration.
+    pub fn iter(&self) -> impl Iterator<Item = &Monitor> {
+        HUBS.iter().filter_map(|config| self.get(&config.name))
+    }
+
+    /// Start monitoring the videohubs once the server is running.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_liftoff("Videohub monitors", |rocket| {
+            Box::pin(async move {
+                let monitors = rocket.state::<Monitors>().unwrap();
+                for config in HUBS.iter() {
+                    let monitor = monitors.hubs[&config.name].clone();
+                    tokio::spawn(monitor.run());
+                }
+            })
+        })
+    }
+}
+
+impl Monitor {
+    fn new(config: &'static HubConfig, audit: Arc<AuditLog>) -> Self {
+        Self {
+            config,
+            audit,
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            mirror: RwLock::new(Mirror::default()),
+            expected: Mutex::new(vec![]),
+            stats: Mutex::new(Stats::default()),
+            writer: AsyncMutex::new(None),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<HubEvent> {
+        self.events.subscribe()
+    }
+
+    /// Whether the session with the videohub is open, keeping the mirrored state up to date.
+    pub fn is_connected(&self) -> bool {
+        self.mirror.read().unwrap().connected
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Whether the session with the videohub is open and it answered recently, so that the
+    /// mirrored state can be trusted.
+    pub fn readiness(&self) -> HubReadiness {
+        let (reachable, last_seen) = {
+            let mirror = self.mirror.read().unwrap();
+            (
+                mirror.connected,
+                mirror.last_seen.map(|last_seen| last_seen.elapsed()),
+            )
+        };
+        let reason = match last_seen {
+            _ if !reachable => Some("No session with the videohub".to_string()),
+            Some(last_seen) if last_seen > STALE_AFTER => Some(format!(
+                "The videohub did not answer for {} seconds",
+                last_seen.as_secs()
+            )),
+            _ => None,
+        };
+        HubReadiness {
+            name: self.config.name.clone(),
+            ready: reason.is_none(),
+            reachable,
+            last_seen: last_seen.map(|last_seen| last_seen.as_secs()),
+            reason,
+        }
+    }
+
+    /// The last known state of the videohub, `None` until it could be read once.
+    pub fn hub_info(&self) -> Option<(protocol::HubInfo, CacheState)> {
+        let mirror = self.mirror.read().unwrap();
+        let hub_info = mirror.hub_info.clone()?;
+        Some((hub_info, mirror.cache_state()))
+    }
+
+    /// The last known state of the videohub, read from it when there is none yet.
+    pub async fn read(&self) -> hub::Result<(protocol::HubInfo, CacheState)> {
+        match self.hub_info() {
+            Some(hub_info) => Ok(hub_info),
+            None => self.refresh().await,
+        }
+    }
+
+    /// Read the whole state from the videohub and mirror it.
+    pub async fn refresh(&self) -> hub::Result<(protocol::HubInfo, CacheState)> {
+        let hub_info = self.hub().read().await?;
+        let cache_state = self.replace(hub_info.clone());
+        Ok((hub_info, cache_state))
+    }
+
+    /// Write a block to the videohub, the changes it makes are recorded with the author. It is
+    /// sent over the session when it is open, so that the locks taken belong to the connection
+    /// the state is mirrored from. Without a session, a connection is opened for the block.
+    pub async fn write(&self, author: &Author, block: protocol::BlockType) -> hub::Result<usize> {
+        self.expect(author, &block);
+        let nb_bytes = match self.writer.lock().await.as_mut() {
+            Some(writer) => writer.write(block.clone()).await?,
+            None => self.hub().write(block.clone()).await?,
+        };
+        self.written(author, block);
+        Ok(nb_bytes)
+    }
+
+    fn hub(&self) -> Hub {
+        Hub::new(self.config.address, self.config.port)
+    }
+
+    /// Replace the whole mirrored state, the subscribers receive all the ports.
+    pub fn replace(&self, hub_info: protocol::HubInfo) -> CacheState {
+        let blocks = [
+            protocol::BlockType::InputLabels(hub_info.input_labels.clone()),
+            protocol::BlockType::OutputLabels(hub_info.output_labels.clone()),
+            protocol::BlockType::VideoOutputLocks(hub_info.video_output_locks.clone()),
+            protocol::BlockType::VideoOutputRouting(hub_info.video_output_routing.clone()),
+        ];
+        let (changes, cache_state) = {
+            let mut mirror = self.mirror.write().unwrap();
+            // The changes made while the videohub was not monitored
+            let changes = match &mirror.hub_info {
+                Some(previous) => defs::state_differences(previous, &hub_info),
+                None => vec![],
+            };
+            mirror.hub_info = Some(hub_info);
+            mirror.last_seen = Some(Instant::now());
+            (changes, mirror.cache_state())
+        };
+        self.record(changes);
+        for block in blocks {
+            self.publish(block);
+        }
+        cache_state
+    }
+
+    /// Attribute to `author` the changes the videohub will report for this block, which is about
+    /// to be written through the API.
+    pub fn expect(&self, author: &Author, block: &protocol::BlockType) {
+        let now = Instant::now();
+        let mut expected = self.expected.lock().unwrap();
+        expected.retain(|expected| expected.expires_at > now);
+        for (kind, port) in audit::targets(block) {
+            expected.push(Expected {
+                kind,
+                port,
+                author: author.clone(),
+                expires_at: now + ATTRIBUTION_DELAY,
+            });
+        }
+    }
+
+    /// A block has been written through the API. Without a session, the videohub will not report
+    /// the changes so they are applied and recorded now. Until the state could be read once, there
+    /// is nothing to apply them to: the next read fetches the whole state.
+    pub fn written(&self, author: &Author, block: protocol::BlockType) {
+        if self.is_connected() {
+            return;
+        }
+        let changes = {
+            let mut mirror = self.mirror.write().unwrap();
+            match mirror.hub_info.as_mut() {
+                Some(hub_info) => {
+                    let changes = defs::differences(hub_info, &block);
+                    hub_info.apply(block);
+                    changes
+                }
+                // Recorded without their previous value
+                None => defs::differences(&protocol::HubInfo::default(), &block),
+            }
+        };
+        for change in changes {
+            self.audit
+                .record(audit::entry(change, &self.config.name, Some(author)));
+        }
+    }
+
+    fn apply(&self, block: protocol::BlockType) {
+        let changes = {
+            let mut mirror = self.mirror.write().unwrap();
+            match mirror.hub_info.as_mut() {
+                Some(hub_info) => {
+                    let changes = defs::differences(hub_info, &block);
+                    hub_info.apply(block.clone());
+                    changes
+                }
+                None => vec![],
+            }
+        };
+        self.record(changes);
+        self.publish(block);
+    }
+
+    fn record(&self, changes: Vec<Difference>) {
+        let route_changes = changes
+            .iter()
+            .filter(|change| change.kind == ChangeKind::Route)
+            .count();
+        self.stats.lock().unwrap().route_changes += route_changes as u64;
+        for change in changes {
+            let author = self.author(&change);
+            self.audit
+                .record(audit::entry(change, &self.config.name, author.as_ref()));
+        }
+    }
+
+    // Who made a change reported by the videohub, None when it comes from another panel
+    fn author(&self, change: &Difference) -> Option<Author> {
+        let now = Instant::now();
+        let mut expected = self.expected.lock().unwrap();
+        let index = expected.iter().position(|expected| {
+            expected.kind == change.kind
+                && expected.port == change.port
+                && expected.expires_at > now
+        })?;
+        Some(expected.remove(index).author)
+    }
+
+    fn publish(&self, block: protocol::BlockType) {
+        if let Some(event) = HubEvent::from_block(block) {
+            // Nobody listening is not an error
+            let _ = self.events.send(event);
+        }
+    }
+
+    fn set_connected(&self, connected: bool) {
+        self.mirror.write().unwrap().connected = connected;
+    }
+
+    async fn run(self: Arc<Self>) {
+        loop {
+            if let Err(err) = self.run_session().await {
+                warn!(
+                    "Lost the session with the videohub '{}': {err}",
+                    self.config.name
+                );
+            }
+            self.set_connected(false);
+            sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn run_session(self: &Arc<Self>) -> hub::Result<()> {
+        let connected_at = Instant::now();
+        let mut session = self.hub().connect().await?;
+        let hub_info = session.read_prelude().await?;
+        self.stats.lock().unwrap().prelude_latency = Some(connected_at.elapsed());
+        self.set_connected(true);
+        self.replace(hub_info);
+        info!("Monitoring the videohub '{}'", self.config.name);
+
+        *self.writer.lock().await = Some(session.writer());
+        // The pings are acknowledged on the session, stopped with it
+        let pinger = tokio::spawn(self.clone().ping());
+        let result = self.follow(&mut session).await;
+        pinger.abort();
+        *self.writer.lock().await = None;
+        result
+    }
+
+    async fn follow(&self, session: &mut Session) -> hub::Result<()> {
+        loop {
+            let message = session.next_message().await;
+            if message.is_ok() {
+                self.mirror.write().unwrap().last_seen = Some(Instant::now());
+            }
+            match message {
+                Ok(Message::Block(block)) => self.apply(block),
+                Ok(Message::Ack) => {
+                    let mut stats = self.stats.lock().unwrap();
+                    if let Some(sent_at) = stats.ping_sent_at.take() {
+                        stats.ping_rtt = Some(sent_at.elapsed());
+                    }
+                }
+                Ok(Message::Nak) => {}
+                Err(hub::Error::DeserializeError(err)) => {
+                    warn!(
+                        "Invalid block from the videohub '{}': {err}",
+                        self.config.name
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn ping(self: Arc<Self>) {
+        loop {
+            sleep(PING_INTERVAL).await;
+            let mut writer = self.writer.lock().await;
+            let Some(writer) = writer.as_mut() else {
+                return;
+            };
+            self.stats.lock().unwrap().ping_sent_at = Some(Instant::now());
+            if let Err(err) = writer.ping().await {
+                warn!("Failed to ping the videohub '{}': {err}", self.config.name);
+                return;
+            }
+        }
+    }
+}
+
+impl Mirror {
+    // Stale without a session, or when the videohub stopped answering the pings
+    fn cache_state(&self) -> CacheState {
+        let age = self.last_seen.map(|last_seen| last_seen.elapsed());
+        CacheState {
+            stale: !self.connected || age.is_none_or(|age| age > STALE_AFTER),
+            age: age.unwrap_or_default().as_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, net::Ipv4Addr, path::PathBuf};
+
+    fn monitor(test_name: &str) -> (Monitor, PathBuf) {
+        let config = Box::leak(Box::new(HubConfig {
+            name: "main".to_string(),
+            address: Ipv4Addr::LOCALHOST,
+            port: 9990,
+        }));
+        let path = std::env::temp_dir().join(format!(
+            "videohub-monitor-{test_name}-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let audit = Arc::new(AuditLog::open(&path).unwrap());
+        (Monitor::new(config, audit), path)
+    }
+
+    fn author() -> Author {
+        Author {
+            user: "alice".to_string(),
+            client: None,
+        }
+    }
+
+    #[test]
+    fn test_written_without_state() {
+        let (monitor, path) = monitor("no-state");
+        let block = protocol::BlockType::VideoOutputRouting(vec![protocol::Route(0, 2)]);
+        monitor.written(&author(), block);
+        // Not mirrored as the whole state, it is read from the videohub next time
+        assert!(monitor.hub_info().is_none());
+        let entries = fs::read_to_string(&path).unwrap();
+        assert_eq!(entries.lines().count(), 1);
+        assert!(entries.contains(r#""user":"alice""#));
+    }
+
+    #[test]
+    fn test_written_with_state() {
+        let (monitor, path) = monitor("state");
+        let mut hub_info = protocol::HubInfo::default();
+        hub_info.video_output_routing = vec![protocol::Route(0, 1), protocol::Route(1, 1)];
+        monitor.replace(hub_info);
+        let block = protocol::BlockType::VideoOutputRouting(vec![
+            protocol::Route(0, 1),
+            protocol::Route(1, 3),
+        ]);
+        monitor.written(&author(), block);
+        let (hub_info, _) = monitor.hub_info().unwrap();
+        assert_eq!(
+            hub_info.video_output_routing,
+            [protocol::Route(0, 1), protocol::Route(1, 3)]
+        );
+        // Only the route which changed
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_cache_state() {
+        let (monitor, _) = monitor("cache-state");
+        monitor.replace(protocol::HubInfo::default());
+        // Read without a session
+        assert!(monitor.hub_info().unwrap().1.stale);
+        monitor.set_connected(true);
+        let (_, cache_state) = monitor.hub_info().unwrap();
+        assert_eq!(
+            cache_state,
+            CacheState {
+                stale: false,
+                age: 0
+            }
+        );
+        // The videohub stopped answering
+        monitor.mirror.write().unwrap().last_seen =
+            Instant::now().checked_sub(STALE_AFTER + Duration::from_secs(5));
+        let (_, cache_state) = monitor.hub_info().unwrap();
+        assert!(cache_state.stale);
+        assert_eq!(cache_state.age, STALE_AFTER.as_secs() + 5);
+        assert!(!monitor.readiness().ready);
+    }
+}
@@ -0,0 +1,363 @@
+use super::{
+    audit::Author,
+    auth::{Auth, User},
+    config::{MqttConfig, HUBS, MQTT, PORT_NUMBERING},
+    monitor::Monitors,
+};
+use log::{info, warn};
+use rocket::{
+    fairing::AdHoc,
+    tokio::{self, sync::broadcast::error::RecvError, time::sleep},
+};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, Publish, QoS};
+use std::{sync::Arc, time::Duration};
+use videohub_proto::protocol::{self, BlockType};
+use videohub_server_api_def::defs::{self, HubEvent, LockStatus, Role};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+/// How many messages can wait to be sent to the broker
+const CAPACITY: usize = 1024;
+/// The author of the changes when the authentication is disabled and no user is set
+const DEFAULT_AUTHOR: &str = "mqtt";
+
+/// Publishes the state of the videohubs to an MQTT broker and applies the commands published to
+/// it. The ports are numbered as in the API, the topics of a videohub are:
+///
+/// - `<prefix>/<hub>/outputs/<port>/route`, `.../label` and `.../lock`, and
+///   `<prefix>/<hub>/inputs/<port>/label`: retained, the current state of the ports.
+/// - `<prefix>/<hub>/events`: the changes reported by the videohub, as JSON `HubEvent`s.
+/// - The state topics followed by `/set`: the commands changing the ports, a route takes the
+///   number of the input and a lock `owned`, `unlocked` or `force_unlock`.
+/// - `<prefix>/<hub>/errors`: why a command was not applied.
+///
+/// `<prefix>/status` is `online` while the bridge is connected, `offline` otherwise.
+///
+/// The commands are checked as the requests of the API: against the role and the outputs of the
+/// `user` of the `mqtt` table, routes are not applied to the outputs locked by another panel nor
+/// while the videohub is in take mode. With `read_only`, or when the authentication is enabled
+/// and no user is set, the commands are not subscribed to.
+pub struct Bridge {
+    client: AsyncClient,
+    prefix: String,
+    monitors: Monitors,
+    auth: Arc<Auth>,
+    user: Option<String>,
+    read_only: bool,
+}
+
+impl Bridge {
+    fn new(config: &MqttConfig, monitors: Monitors, auth: Arc<Auth>) -> (Self, EventLoop) {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            format!("{}/status", config.prefix),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+        let (client, event_loop) = AsyncClient::new(options, CAPACITY);
+        let read_only = if config.user.is_none() && auth.is_enabled() {
+            warn!("No user set for the MQTT bridge, its commands are ignored");
+            true
+        } else {
+            config.read_only
+        };
+        let bridge = Self {
+            client,
+            prefix: config.prefix.clone(),
+            monitors,
+            auth,
+            user: config.user.clone(),
+            read_only,
+        };
+        (bridge, event_loop)
+    }
+
+    /// Start the bridge once the server is running, when the `mqtt` table of Rocket.toml is set.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_liftoff("MQTT bridge", |rocket| {
+            Box::pin(async move {
+                if let Some(config) = MQTT.as_ref() {
+                    let monitors = rocket.state::<Monitors>().unwrap().clone();
+                    let auth = rocket.state::<Arc<Auth>>().unwrap().clone();
+                    let (bridge, event_loop) = Bridge::new(config, monitors, auth);
+                    let bridge = Arc::new(bridge);
+                    for config in HUBS.iter() {
+                        tokio::spawn(bridge.clone().forward(&config.name));
+                    }
+                    tokio::spawn(bridge.run(event_loop));
+                }
+            })
+        })
+    }
+
+    // The messages from the broker. The client reconnects when the event loop is polled again.
+    async fn run(self: Arc<Self>, mut event_loop: EventLoop) {
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to the MQTT broker");
+                    tokio::spawn(self.clone().connected());
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    tokio::spawn(self.clone().command(publish));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Lost the connection with the MQTT broker: {err}");
+                    sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+
+    // The broker may have lost the subscriptions and the retained state
+    async fn connected(self: Arc<Self>) {
+        self.publish(
+            format!("{}/status", self.prefix),
+            true,
+            "online".to_string(),
+        )
+        .await;
+        for config in HUBS.iter() {
+            if !self.read_only {
+                let topic = format!("{}/{}/+/+/+/set", self.prefix, config.name);
+                if let Err(err) = self.client.subscribe(topic, QoS::AtLeastOnce).await {
+                    warn!("Failed to subscribe to the MQTT commands: {err}");
+                }
+            }
+            self.publish_state(&config.name).await;
+        }
+    }
+
+    // Publish the changes reported by a videohub
+    async fn forward(self: Arc<Self>, hub: &'static str) {
+        let mut events = match self.monitors.get(hub) {
+            Some(monitor) => monitor.subscribe(),
+            None => return,
+        };
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let event = PORT_NUMBERING.to_user(event);
+                    self.publish_ports(hub, &event).await;
+                    let payload = serde_json::to_string(&event).unwrap();
+                    self.publish(format!("{}/{hub}/events", self.prefix), false, payload)
+                        .await;
+                }
+                // Some changes are missed, the whole state is published again
+                Err(RecvError::Lagged(_)) => self.publish_state(hub).await,
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    // Publish the whole state of a videohub to its retained topics
+    async fn publish_state(&self, hub: &str) {
+        let hub_info = match self
+            .monitors
+            .get(hub)
+            .and_then(|monitor| monitor.hub_info())
+        {
+            Some((hub_info, _)) => hub_info,
+            None => return,
+        };
+        let events = [
+            HubEvent::InputPorts(defs::input_ports(&hub_info)),
+            HubEvent::OutputPorts(defs::output_ports(&hub_info)),
+        ];
+        for event in events {
+            self.publish_ports(hub, &PORT_NUMBERING.to_user(event))
+                .await;
+        }
+    }
+
+    async fn publish_ports(&self, hub: &str, event: &HubEvent) {
+        match event {
+            HubEvent::InputPorts(input_ports) => {
+                for input_port in input_ports {
+                    let topic = self.port_topic(hub, "inputs", input_port.id, "label");
+                    self.publish(topic, true, input_port.label.clone()).await;
+                }
+            }
+            HubEvent::OutputPorts(output_ports) => {
+                for output_port in output_ports {
+                    let id = output_port.id;
+                    if let Some(input_port) = output_port.input_port {
+                        let topic = self.port_topic(hub, "outputs", id, "route");
+                        self.publish(topic, true, input_port.to_string()).await;
+                    }
+                    if let Some(label) = &output_port.label {
+                        let topic = self.port_topic(hub, "outputs", id, "label");
+                        self.publish(topic, true, label.clone()).await;
+                    }
+                    if let Some(lock_state) = &output_port.lock_state {
+                        let topic = self.port_topic(hub, "outputs", id, "lock");
+                        self.publish(topic, true, lock_name(lock_state)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn publish(&self, topic: String, retain: bool, payload: String) {
+        if let Err(err) = self
+            .client
+            .publish(topic, QoS::AtLeastOnce, retain, payload)
+            .await
+        {
+            warn!("Failed to publish to the MQTT broker: {err}");
+        }
+    }
+
+    fn port_topic(&self, hub: &str, side: &str, port: usize, field: &str) -> String {
+        format!("{}/{hub}/{side}/{port}/{field}", self.prefix)
+    }
+
+    async fn command(self: Arc<Self>, publish: Publish) {
+        let payload = String::from_utf8_lossy(&publish.payload).trim().to_string();
+        // <hub>/<side>/<port>/<field>/set
+        let levels: Vec<_> = publish
+            .topic
+            .strip_prefix(&format!("{}/", self.prefix))
+            .unwrap_or_default()
+            .split('/')
+            .collect();
+        let (hub, side, port, field) = match levels[..] {
+            [hub, side, port, field, "set"] => (hub, side, port, field),
+            _ => return,
+        };
+        if let Err(message) = self.apply(hub, side, port, field, payload).await {
+            warn!("MQTT command {} not applied: {message}", publish.topic);
+            let topic = format!("{}/{hub}/errors", self.prefix);
+            self.publish(topic, false, format!("{}: {message}", publish.topic))
+                .await;
+        }
+    }
+
+    async fn apply(
+        &self,
+        hub: &str,
+        side: &str,
+        port: &str,
+        field: &str,
+        payload: String,
+    ) -> Result<(), String> {
+        if self.read_only {
+            return Err("The MQTT bridge is read-only".to_string());
+        }
+        let user = self.user()?;
+        let monitor = self
+            .monitors
+            .get(hub)
+            .ok_or_else(|| format!("Unknown videohub '{hub}'"))?;
+        let (hub_info, _) = monitor.read().await.map_err(|err| err.to_string())?;
+        let nb_inputs = hub_info.device_info.nb_video_inputs;
+        let nb_outputs = hub_info.device_info.nb_video_outputs;
+        let port = parse_port(port)?;
+        let block = match (side, field) {
+            ("outputs", "route") => {
+                let input = parse_port(&payload)?;
+                check_port(port, nb_outputs, "output")?;
+                check_port(input, nb_inputs, "input")?;
+                user.check_outputs(hub, [port])?;
+                if hub_info.configuration.take_mode {
+                    return Err(format!(
+                        "The take mode of '{hub}' is enabled, the routes are staged and taken with the API"
+                    ));
+                }
+                if hub_info.is_locked_by_other(port) {
+                    return Err(format!(
+                        "The output {} of '{hub}' is locked by another panel",
+                        PORT_NUMBERING.to_user(port)
+                    ));
+                }
+                BlockType::VideoOutputRouting(vec![protocol::Route(port, input)])
+            }
+            ("outputs", "label") => {
+                check_port(port, nb_outputs, "output")?;
+                user.check_outputs(hub, [port])?;
+                BlockType::OutputLabels(vec![protocol::Label(port, payload)])
+            }
+            ("outputs", "lock") => {
+                let lock_state = parse_lock(&payload)?;
+                check_port(port, nb_outputs, "output")?;
+                user.check_outputs(hub, [port])?;
+                BlockType::VideoOutputLocks(vec![protocol::OutputLock(port, lock_state.into())])
+            }
+            ("inputs", "label") => {
+                check_port(port, nb_inputs, "input")?;
+                if user.role < Role::Admin {
+                    return Err(format!(
+                        "User '{}' is not allowed to change the input labels",
+                        user.name
+                    ));
+                }
+                BlockType::InputLabels(vec![protocol::Label(port, payload)])
+            }
+            _ => return Err(format!("Unknown command {side}/<port>/{field}")),
+        };
+
+        let author = Author {
+            user: self.user.as_deref().unwrap_or(DEFAULT_AUTHOR).to_string(),
+            client: None,
+        };
+        monitor
+            .write(&author, block)
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok(())
+    }
+
+    // The current account of the configured user, the anonymous admin when the authentication is
+    // disabled
+    fn user(&self) -> Result<User, String> {
+        let name = self.user.as_deref().unwrap_or(DEFAULT_AUTHOR);
+        let user = self
+            .auth
+            .user(name)
+            .ok_or_else(|| format!("The MQTT user '{name}' is not a user anymore"))?;
+        if user.role < Role::Operator {
+            return Err(format!(
+                "User '{name}' is not allowed to change the outputs"
+            ));
+        }
+        Ok(user)
+    }
+}
+
+// A port numbered as in the API, numbered as the protocol does
+fn parse_port(value: &str) -> Result<usize, String> {
+    let port = value
+        .parse()
+        .map_err(|_| format!("Invalid port number '{value}'"))?;
+    PORT_NUMBERING.from_user(port)
+}
+
+// The lock states are named as in the API
+fn parse_lock(value: &str) -> Result<LockStatus, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("Invalid lock state '{value}'"))
+}
+
+fn lock_name(lock_state: &LockStatus) -> String {
+    match serde_json::to_value(lock_state) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => unreachable!(),
+    }
+}
+
+fn check_port(port: usize, nb_ports: usize, side: &str) -> Result<(), String> {
+    if port < nb_ports {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown {side} port {}, the videohub has {nb_ports} {side}s",
+            PORT_NUMBERING.to_user(port)
+        ))
+    }
+}
@@ -0,0 +1,41 @@
+use std::{collections::HashMap, sync::Mutex};
+use videohub_server_api_def::defs::PendingRoute;
+
+/// The routes staged by the users of the videohubs in take mode, until they are taken or
+/// cancelled. They are only kept in memory.
+#[derive(Default)]
+pub struct PendingRoutes {
+    // By hub name and user name
+    routes: Mutex<HashMap<(String, String), Vec<PendingRoute>>>,
+}
+
+impl PendingRoutes {
+    /// The routes staged by a user, sorted by output.
+    pub fn get(&self, hub: &str, user: &str) -> Vec<PendingRoute> {
+        let routes = self.routes.lock().unwrap();
+        routes.get(&key(hub, user)).cloned().unwrap_or_default()
+    }
+
+    /// Stage routes, they replace those staged before for the same outputs. Returns all the routes
+    /// staged by the user.
+    pub fn add(&self, hub: &str, user: &str, added: Vec<PendingRoute>) -> Vec<PendingRoute> {
+        let mut routes = self.routes.lock().unwrap();
+        let staged = routes.entry(key(hub, user)).or_default();
+        for route in added {
+            staged.retain(|existing| existing.output != route.output);
+            staged.push(route);
+        }
+        staged.sort_by_key(|route| route.output);
+        staged.clone()
+    }
+
+    /// Remove the routes staged by a user, and return them.
+    pub fn clear(&self, hub: &str, user: &str) -> Vec<PendingRoute> {
+        let mut routes = self.routes.lock().unwrap();
+        routes.remove(&key(hub, user)).unwrap_or_default()
+    }
+}
+
+fn key(hub: &str, user: &str) -> (String, String) {
+    (hub.to_string(), user.to_string())
+}
@@ -0,0 +1,296 @@
+use super::config::{PORT_NUMBERING, SALVOS_DIR};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use videohub_proto::protocol;
+use videohub_server_api_def::defs::{self, Difference, OutputPort, Salvo};
+
+#[derive(Debug)]
+pub enum SalvoError {
+    AlreadyExists(String),
+    NotFound(String),
+    Io(io::Error),
+    Invalid(serde_json::Error),
+}
+
+impl std::fmt::Display for SalvoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyExists(name) => write!(f, "The salvo '{name}' already exists"),
+            Self::NotFound(name) => write!(f, "Unknown salvo '{name}'"),
+            Self::Io(err) => write!(f, "Failed to access the salvos: {err}"),
+            Self::Invalid(err) => write!(f, "Invalid salvos file: {err}"),
+        }
+    }
+}
+
+/// The salvos of the videohubs, one JSON file per videohub, rewritten on every change.
+pub struct Salvos {
+    dir: PathBuf,
+    // Serializes the changes of the files
+    lock: Mutex<()>,
+}
+
+impl Salvos {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Use the `salvos_dir` of Rocket.toml.
+    pub fn from_config() -> Self {
+        Self::new(&*SALVOS_DIR)
+    }
+
+    /// The salvos of a hub, sorted by name.
+    pub fn list(&self, hub: &str) -> Result<Vec<Salvo>, SalvoError> {
+        let _lock = self.lock.lock().unwrap();
+        self.load(hub)
+    }
+
+    pub fn get(&self, hub: &str, name: &str) -> Result<Salvo, SalvoError> {
+        self.list(hub)?
+            .into_iter()
+            .find(|salvo| salvo.name == name)
+            .ok_or_else(|| SalvoError::NotFound(name.to_string()))
+    }
+
+    pub fn create(&self, hub: &str, salvo: Salvo) -> Result<(), SalvoError> {
+        self.update(hub, |salvos| {
+            if salvos.iter().any(|existing| existing.name == salvo.name) {
+                return Err(SalvoError::AlreadyExists(salvo.name));
+            }
+            salvos.push(salvo);
+            Ok(())
+        })
+    }
+
+    /// Replace a salvo, `name` is its current name.
+    pub fn replace(&self, hub: &str, name: &str, salvo: Salvo) -> Result<(), SalvoError> {
+        self.update(hub, |salvos| {
+            if salvo.name != name && salvos.iter().any(|existing| existing.name == salvo.name) {
+                return Err(SalvoError::AlreadyExists(salvo.name));
+            }
+            let existing = salvos
+                .iter_mut()
+                .find(|existing| existing.name == name)
+                .ok_or_else(|| SalvoError::NotFound(name.to_string()))?;
+            *existing = salvo;
+            Ok(())
+        })
+    }
+
+    pub fn delete(&self, hub: &str, name: &str) -> Result<(), SalvoError> {
+        self.update(hub, |salvos| {
+            let index = salvos
+                .iter()
+                .position(|salvo| salvo.name == name)
+                .ok_or_else(|| SalvoError::NotFound(name.to_string()))?;
+            salvos.remove(index);
+            Ok(())
+        })
+    }
+
+    fn update<F>(&self, hub: &str, change: F) -> Result<(), SalvoError>
+    where
+        F: FnOnce(&mut Vec<Salvo>) -> Result<(), SalvoError>,
+    {
+        let _lock = self.lock.lock().unwrap();
+        let mut salvos = self.load(hub)?;
+        change(&mut salvos)?;
+        salvos.sort_by(|a, b| a.name.cmp(&b.name));
+        self.save(hub, &salvos)
+    }
+
+    fn path(&self, hub: &str) -> PathBuf {
+        self.dir.join(format!("{hub}.json"))
+    }
+
+    fn load(&self, hub: &str) -> Result<Vec<Salvo>, SalvoError> {
+        match fs::read_to_string(self.path(hub)) {
+            Ok(content) => serde_json::from_str(&content).map_err(SalvoError::Invalid),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(SalvoError::Io(err)),
+        }
+    }
+
+    fn save(&self, hub: &str, salvos: &[Salvo]) -> Result<(), SalvoError> {
+        fs::create_dir_all(&self.dir).map_err(SalvoError::Io)?;
+        let content = serde_json::to_string_pretty(salvos).unwrap();
+        // Written aside then renamed, not to lose the salvos on a failure
+        let path = self.path(hub);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).map_err(SalvoError::Io)?;
+        fs::rename(tmp_path, path).map_err(SalvoError::Io)
+    }
+}
+
+/// What recalling a salvo would change.
+pub fn recall_differences(hub_info: &protocol::HubInfo, salvo: &Salvo) -> Vec<Difference> {
+    salvo
+        .blocks()
+        .iter()
+        .flat_map(|block| defs::differences(hub_info, block))
+        .collect()
+}
+
+/// Fails when the routes of the output ports can not be sent at once, like for a route set from the
+/// API: in take mode they are staged and taken, and the videohub ignores those to the outputs
+/// locked by another panel. For the recalls of the salvos and the jobs of the scheduler.
+pub fn check_routes(
+    hub: &str,
+    hub_info: &protocol::HubInfo,
+    output_ports: &[OutputPort],
+) -> Result<(), String> {
+    let mut routed = output_ports
+        .iter()
+        .filter(|output_port| output_port.input_port.is_some())
+        .peekable();
+    if routed.peek().is_some() && hub_info.configuration.take_mode {
+        return Err(format!(
+            "The take mode of '{hub}' is enabled, the routes are staged with /pending_routes and \
+             taken at once"
+        ));
+    }
+    match routed.find(|output_port| hub_info.is_locked_by_other(output_port.id)) {
+        Some(output_port) => Err(format!(
+            "The output {} of '{hub}' is locked by another panel",
+            PORT_NUMBERING.to_user(output_port.id)
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use videohub_server_api_def::defs::ChangeKind;
+
+    fn salvos(test_name: &str) -> Salvos {
+        let dir = std::env::temp_dir().join(format!(
+            "videohub-salvos-{test_name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        Salvos::new(dir)
+    }
+
+    fn salvo(name: &str, routes: &[(usize, usize)]) -> Salvo {
+        Salvo {
+            name: name.to_string(),
+            outputs: routes
+                .iter()
+                .map(|(output, input)| OutputPort {
+                    id: *output,
+                    label: None,
+                    lock_state: None,
+                    input_port: Some(*input),
+                })
+                .collect(),
+            owner: Some("alice".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let salvos = salvos("save");
+        assert!(salvos.list("main").unwrap().is_empty());
+        salvos.create("main", salvo("news", &[(0, 1)])).unwrap();
+        salvos.create("main", salvo("evening", &[(1, 2)])).unwrap();
+        assert!(matches!(
+            salvos.create("main", salvo("news", &[])),
+            Err(SalvoError::AlreadyExists(_))
+        ));
+
+        // Read back from the file, sorted by name
+        let names: Vec<_> = Salvos::new(&salvos.dir)
+            .list("main")
+            .unwrap()
+            .into_iter()
+            .map(|salvo| salvo.name)
+            .collect();
+        assert_eq!(names, ["evening", "news"]);
+        let news = salvos.get("main", "news").unwrap();
+        assert_eq!(news.outputs[0].input_port, Some(1));
+        assert_eq!(news.owner.as_deref(), Some("alice"));
+        // The salvos of the other hubs are in other files
+        assert!(salvos.list("other").unwrap().is_empty());
+
+        salvos
+            .replace("main", "news", salvo("late-news", &[(0, 3)]))
+            .unwrap();
+        assert!(matches!(
+            salvos.get("main", "news"),
+            Err(SalvoError::NotFound(_))
+        ));
+        assert_eq!(
+            salvos.get("main", "late-news").unwrap().outputs[0].input_port,
+            Some(3)
+        );
+        assert!(matches!(
+            salvos.replace("main", "late-news", salvo("evening", &[])),
+            Err(SalvoError::AlreadyExists(_))
+        ));
+
+        salvos.delete("main", "late-news").unwrap();
+        assert!(matches!(
+            salvos.delete("main", "late-news"),
+            Err(SalvoError::NotFound(_))
+        ));
+        assert_eq!(salvos.list("main").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let salvos = salvos("invalid");
+        fs::create_dir_all(&salvos.dir).unwrap();
+        fs::write(salvos.path("main"), "not json").unwrap();
+        assert!(matches!(salvos.list("main"), Err(SalvoError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_recall_differences() {
+        let mut hub_info = protocol::HubInfo::default();
+        hub_info.video_output_routing = vec![protocol::Route(0, 1), protocol::Route(1, 1)];
+        let differences = recall_differences(&hub_info, &salvo("news", &[(0, 1), (1, 2)]));
+        assert_eq!(
+            differences,
+            [Difference {
+                kind: ChangeKind::Route,
+                port: 1,
+                current: Some("1".to_string()),
+                target: "2".to_string(),
+            }]
+        );
+        assert!(recall_differences(&hub_info, &salvo("same", &[(0, 1)])).is_empty());
+    }
+    #[test]
+    fn test_check_routes() {
+        let mut hub_info = protocol::HubInfo::default();
+        hub_info.video_output_locks = vec![
+            protocol::OutputLock(0, protocol::LockStatus::Owned),
+            protocol::OutputLock(1, protocol::LockStatus::Locked),
+        ];
+        let news = salvo("news", &[(0, 1)]);
+        assert!(check_routes("main", &hub_info, &news.outputs).is_ok());
+        let locked = salvo("locked", &[(0, 1), (1, 1)]);
+        assert!(check_routes("main", &hub_info, &locked.outputs).is_err());
+        // Only the routes are ignored on the outputs locked by another panel
+        let mut label = salvo("label", &[]);
+        label.outputs.push(OutputPort {
+            id: 1,
+            label: Some("PGM".to_string()),
+            lock_state: None,
+            input_port: None,
+        });
+        assert!(check_routes("main", &hub_info, &label.outputs).is_ok());
+
+        hub_info.configuration.take_mode = true;
+        assert!(check_routes("main", &hub_info, &news.outputs).is_err());
+        assert!(check_routes("main", &hub_info, &label.outputs).is_ok());
+    }
+}
@@ -0,0 +1,520 @@
+use super::{
+    audit::Author,
+    auth::Auth,
+    config::{JOBS_FILE, JOB_RUNS_FILE},
+    monitor::Monitors,
+    salvos::{check_routes, Salvos},
+};
+use chrono::{DateTime, Local, Utc};
+use log::{info, warn};
+use rocket::{
+    fairing::AdHoc,
+    tokio::{self, time::sleep},
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use videohub_server_api_def::defs::{
+    self, Job, JobAction, JobRun, OutputPort, Role, Schedule, ScheduledJob,
+};
+
+/// How often the jobs due are looked for
+const TICK: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum JobError {
+    AlreadyExists(String),
+    NotFound(String),
+    InvalidSchedule(String),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyExists(name) => write!(f, "The job '{name}' already exists"),
+            Self::NotFound(name) => write!(f, "Unknown job '{name}'"),
+            Self::InvalidSchedule(message) => write!(f, "{message}"),
+            Self::Io(err) => write!(f, "Failed to access the jobs: {err}"),
+        }
+    }
+}
+
+/// Runs the jobs of every videohub at their scheduled times. The jobs are saved in a JSON file
+/// rewritten on every change, their runs are appended to a JSON lines file.
+///
+/// The jobs due while the server is stopped are not run when it starts again.
+pub struct Scheduler {
+    path: PathBuf,
+    jobs: Mutex<HashMap<String, Vec<Job>>>,
+    runs_path: PathBuf,
+    runs: Mutex<File>,
+}
+
+impl Scheduler {
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(path: P, runs_path: Q) -> io::Result<Self> {
+        let jobs = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+        let runs = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&runs_path)?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            jobs: Mutex::new(jobs),
+            runs_path: runs_path.as_ref().to_path_buf(),
+            runs: Mutex::new(runs),
+        })
+    }
+
+    /// Use the `jobs_file` and `job_runs_file` of Rocket.toml.
+    pub fn from_config() -> Self {
+        Self::open(&*JOBS_FILE, &*JOB_RUNS_FILE).unwrap_or_else(|err| {
+            panic!(
+                "Failed to open the jobs file {}: {err}",
+                JOBS_FILE.display()
+            )
+        })
+    }
+
+    /// The jobs of a hub, sorted by name.
+    pub fn list(&self, hub: &str) -> Vec<ScheduledJob> {
+        let jobs = self.jobs.lock().unwrap();
+        let now = Utc::now();
+        jobs.get(hub)
+            .into_iter()
+            .flatten()
+            .map(|job| ScheduledJob {
+                job: job.clone(),
+                next_run: next_run(&job.schedule, now),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, hub: &str, name: &str) -> Result<ScheduledJob, JobError> {
+        self.list(hub)
+            .into_iter()
+            .find(|scheduled| scheduled.job.name == name)
+            .ok_or_else(|| JobError::NotFound(name.to_string()))
+    }
+
+    pub fn create(&self, hub: &str, job: Job) -> Result<(), JobError> {
+        check_schedule(&job.schedule)?;
+        self.update(hub, |jobs| {
+            if jobs.iter().any(|existing| existing.name == job.name) {
+                return Err(JobError::AlreadyExists(job.name));
+            }
+            jobs.push(job);
+            Ok(())
+        })
+    }
+
+    /// Replace a job, `name` is its current name.
+    pub fn replace(&self, hub: &str, name: &str, job: Job) -> Result<(), JobError> {
+        check_schedule(&job.schedule)?;
+        self.update(hub, |jobs| {
+            if job.name != name && jobs.iter().any(|existing| existing.name == job.name) {
+                return Err(JobError::AlreadyExists(job.name));
+            }
+            let existing = jobs
+                .iter_mut()
+                .find(|existing| existing.name == name)
+                .ok_or_else(|| JobError::NotFound(name.to_string()))?;
+            *existing = job;
+            Ok(())
+        })
+    }
+
+    pub fn delete(&self, hub: &str, name: &str) -> Result<(), JobError> {
+        self.update(hub, |jobs| {
+            let index = jobs
+                .iter()
+                .position(|job| job.name == name)
+                .ok_or_else(|| JobError::NotFound(name.to_string()))?;
+            jobs.remove(index);
+            Ok(())
+        })
+    }
+
+    /// The runs of the jobs of a hub, the most recent first.
+    pub fn runs(&self, hub: &str, limit: usize) -> io::Result<Vec<JobRun>> {
+        let content = fs::read_to_string(&self.runs_path)?;
+        let runs: Vec<JobRun> = content
+            .lines()
+            .filter_map(|line| match serde_json::from_str::<JobRun>(line) {
+                Ok(run) => Some(run),
+                Err(err) => {
+                    warn!("Invalid entry in the job runs file: {err}");
+                    None
+                }
+            })
+            .filter(|run| run.hub == hub)
+            .collect();
+        Ok(runs.into_iter().rev().take(limit).collect())
+    }
+
+    /// Start running the jobs once the server is running.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_liftoff("Scheduler", |rocket| {
+            Box::pin(async move {
+                let scheduler = rocket.state::<Arc<Scheduler>>().unwrap().clone();
+                let monitors = rocket.state::<Monitors>().unwrap().clone();
+                let salvos = rocket.state::<Arc<Salvos>>().unwrap().clone();
+                let auth = rocket.state::<Arc<Auth>>().unwrap().clone();
+                tokio::spawn(scheduler.run(monitors, salvos, auth));
+            })
+        })
+    }
+
+    async fn run(self: Arc<Self>, monitors: Monitors, salvos: Arc<Salvos>, auth: Arc<Auth>) {
+        let mut last_check = Utc::now();
+        loop {
+            sleep(TICK).await;
+            let now = Utc::now();
+            for (hub, job) in self.due(last_check, now) {
+                info!("Running the job '{}' of the videohub '{hub}'", job.name);
+                let run = execute(&hub, &job, &monitors, &salvos, &auth).await;
+                if let Some(err) = &run.error {
+                    warn!(
+                        "The job '{}' of the videohub '{hub}' failed: {err}",
+                        job.name
+                    );
+                }
+                self.record(&run);
+            }
+            last_check = now;
+        }
+    }
+
+    // The jobs which had to run after `since`, until `now`
+    fn due(&self, since: DateTime<Utc>, now: DateTime<Utc>) -> Vec<(String, Job)> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter()
+            .flat_map(|(hub, jobs)| jobs.iter().map(move |job| (hub, job)))
+            .filter(|(_, job)| next_run(&job.schedule, since).is_some_and(|next| next <= now))
+            .map(|(hub, job)| (hub.clone(), job.clone()))
+            .collect()
+    }
+
+    fn record(&self, run: &JobRun) {
+        let line = serde_json::to_string(run).unwrap() + "\n";
+        let mut file = self.runs.lock().unwrap();
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            warn!("Failed to write to the job runs file: {err}");
+        }
+    }
+
+    fn update<F>(&self, hub: &str, change: F) -> Result<(), JobError>
+    where
+        F: FnOnce(&mut Vec<Job>) -> Result<(), JobError>,
+    {
+        let mut all_jobs = self.jobs.lock().unwrap();
+        let mut jobs = all_jobs.get(hub).cloned().unwrap_or_default();
+        change(&mut jobs)?;
+        jobs.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut updated = all_jobs.clone();
+        updated.insert(hub.to_string(), jobs);
+        self.save(&updated)?;
+        *all_jobs = updated;
+        Ok(())
+    }
+
+    fn save(&self, jobs: &HashMap<String, Vec<Job>>) -> Result<(), JobError> {
+        let content = serde_json::to_string_pretty(jobs).unwrap();
+        // Written aside then renamed, not to lose the jobs on a failure
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).map_err(JobError::Io)?;
+        fs::rename(tmp_path, &self.path).map_err(JobError::Io)
+    }
+}
+
+fn check_schedule(schedule: &Schedule) -> Result<(), JobError> {
+    match schedule {
+        Schedule::Once(at) if *at <= Utc::now() => Err(JobError::InvalidSchedule(format!(
+            "The date {at} is in the past"
+        ))),
+        Schedule::Once(_) => Ok(()),
+        Schedule::Cron(expression) => {
+            cron::Schedule::from_str(expression)
+                .map(|_| ())
+                .map_err(|err| {
+                    JobError::InvalidSchedule(format!(
+                        "Invalid cron expression '{expression}': {err}"
+                    ))
+                })
+        }
+    }
+}
+
+// The first time the job runs after `since`
+fn next_run(schedule: &Schedule, since: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match schedule {
+        Schedule::Once(at) => (*at > since).then_some(*at),
+        Schedule::Cron(expression) => cron::Schedule::from_str(expression)
+            .ok()?
+            .after(&since.with_timezone(&Local))
+            .next()
+            .map(|next| next.with_timezone(&Utc)),
+    }
+}
+
+async fn execute(
+    hub: &str,
+    job: &Job,
+    monitors: &Monitors,
+    salvos: &Salvos,
+    auth: &Auth,
+) -> JobRun {
+    let mut run = JobRun {
+        timestamp: Utc::now(),
+        hub: hub.to_string(),
+        job: job.name.clone(),
+        error: None,
+        changes: vec![],
+    };
+    match apply(hub, job, monitors, salvos, auth).await {
+        Ok(changes) => run.changes = changes,
+        Err(err) => run.error = Some(err),
+    }
+    run
+}
+
+async fn apply(
+    hub: &str,
+    job: &Job,
+    monitors: &Monitors,
+    salvos: &Salvos,
+    auth: &Auth,
+) -> Result<Vec<defs::Difference>, String> {
+    let output_ports = match &job.action {
+        JobAction::Outputs(output_ports) => output_ports.clone(),
+        JobAction::Salvo(name) => {
+            salvos
+                .get(hub, name)
+                .map_err(|err| err.to_string())?
+                .outputs
+        }
+    };
+    authorize(auth, hub, job, &output_ports)?;
+    let monitor = monitors
+        .get(hub)
+        .ok_or_else(|| format!("Unknown videohub '{hub}'"))?;
+    let (hub_info, _) = monitor.read().await.map_err(|err| err.to_string())?;
+    check_routes(hub, &hub_info, &output_ports)?;
+    let blocks = defs::output_blocks(&output_ports);
+    let changes = blocks
+        .iter()
+        .flat_map(|block| defs::differences(&hub_info, block))
+        .collect();
+    let author = Author {
+        user: format!("scheduler/{}", job.name),
+        client: None,
+    };
+    for block in blocks {
+        monitor
+            .write(&author, block)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(changes)
+}
+
+// The owner of the job must still be allowed to change the outputs it changes: the salvo it
+// recalls or the permissions of the owner may have changed since it was saved.
+fn authorize(auth: &Auth, hub: &str, job: &Job, output_ports: &[OutputPort]) -> Result<(), String> {
+    let owner = job
+        .owner
+        .as_deref()
+        .ok_or("The job has no owner, it has to be saved again")?;
+    let user = auth
+        .user(owner)
+        .ok_or_else(|| format!("The owner '{owner}' of the job is not a user anymore"))?;
+    if user.role < Role::Operator {
+        return Err(format!(
+            "The owner '{owner}' of the job is not allowed to change the outputs"
+        ));
+    }
+    user.check_outputs(hub, output_ports.iter().map(|output_port| output_port.id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::{User, UserProvider};
+    use chrono::{Duration as ChronoDuration, TimeZone};
+
+    struct TestUsers(Vec<User>);
+
+    impl UserProvider for TestUsers {
+        fn authenticate(&self, _name: &str, _password: &str) -> Option<User> {
+            None
+        }
+
+        fn find_by_token(&self, _token: &str) -> Option<User> {
+            None
+        }
+
+        fn find_by_name(&self, name: &str) -> Option<User> {
+            self.0.iter().find(|user| user.name == name).cloned()
+        }
+    }
+
+    fn user(name: &str, role: Role, outputs: Option<Vec<usize>>) -> User {
+        User {
+            name: name.to_string(),
+            role,
+            outputs: outputs.map(|outputs| HashMap::from([("main".to_string(), outputs)])),
+        }
+    }
+
+    fn job(name: &str, schedule: Schedule, owner: Option<&str>) -> Job {
+        Job {
+            name: name.to_string(),
+            schedule,
+            action: JobAction::Salvo("news".to_string()),
+            owner: owner.map(str::to_string),
+        }
+    }
+
+    fn output_port(id: usize) -> OutputPort {
+        OutputPort {
+            id,
+            label: None,
+            lock_state: None,
+            input_port: Some(0),
+        }
+    }
+
+    fn scheduler(test_name: &str) -> Scheduler {
+        let dir = std::env::temp_dir().join(format!(
+            "videohub-scheduler-{test_name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scheduler::open(dir.join("jobs.json"), dir.join("job_runs.jsonl")).unwrap()
+    }
+
+    #[test]
+    fn test_next_run() {
+        let at = Utc.with_ymd_and_hms(2030, 6, 1, 18, 0, 0).unwrap();
+        let once = Schedule::Once(at);
+        assert_eq!(next_run(&once, at - ChronoDuration::seconds(1)), Some(at));
+        assert_eq!(next_run(&once, at), None);
+
+        let since = Local
+            .with_ymd_and_hms(2030, 6, 1, 12, 0, 0)
+            .single()
+            .unwrap();
+        let daily = Schedule::Cron("0 0 18 * * *".to_string());
+        let expected = Local
+            .with_ymd_and_hms(2030, 6, 1, 18, 0, 0)
+            .single()
+            .unwrap();
+        assert_eq!(
+            next_run(&daily, since.with_timezone(&Utc)),
+            Some(expected.with_timezone(&Utc))
+        );
+        let expected = Local
+            .with_ymd_and_hms(2030, 6, 2, 18, 0, 0)
+            .single()
+            .unwrap();
+        assert_eq!(
+            next_run(
+                &daily,
+                expected.with_timezone(&Utc) - ChronoDuration::days(1)
+            ),
+            Some(expected.with_timezone(&Utc))
+        );
+
+        let invalid = Schedule::Cron("every day".to_string());
+        assert_eq!(next_run(&invalid, since.with_timezone(&Utc)), None);
+    }
+
+    #[test]
+    fn test_due() {
+        let scheduler = scheduler("due");
+        let now = Utc::now();
+        let at = now + ChronoDuration::hours(1);
+        scheduler
+            .create("main", job("later", Schedule::Once(at), Some("alice")))
+            .unwrap();
+        scheduler
+            .create(
+                "main",
+                job(
+                    "much-later",
+                    Schedule::Once(now + ChronoDuration::days(1)),
+                    Some("alice"),
+                ),
+            )
+            .unwrap();
+
+        assert!(scheduler
+            .due(now, at - ChronoDuration::seconds(1))
+            .is_empty());
+        let due = scheduler.due(at - ChronoDuration::seconds(1), at);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, "main");
+        assert_eq!(due[0].1.name, "later");
+        // Not run again once its time is over
+        assert!(scheduler.due(at, at + ChronoDuration::hours(1)).is_empty());
+    }
+
+    #[test]
+    fn test_check_schedule() {
+        let past = Schedule::Once(Utc::now() - ChronoDuration::hours(1));
+        assert!(matches!(
+            check_schedule(&past),
+            Err(JobError::InvalidSchedule(_))
+        ));
+        let invalid = Schedule::Cron("every day".to_string());
+        assert!(matches!(
+            check_schedule(&invalid),
+            Err(JobError::InvalidSchedule(_))
+        ));
+        assert!(check_schedule(&Schedule::Cron("0 0 18 * * *".to_string())).is_ok());
+    }
+
+    #[test]
+    fn test_authorize() {
+        let auth = Auth::new(Some(Box::new(TestUsers(vec![
+            user("alice", Role::Operator, Some(vec![0, 1])),
+            user("bob", Role::Viewer, None),
+            user("carol", Role::Admin, Some(vec![])),
+        ]))));
+        let schedule = Schedule::Cron("0 0 18 * * *".to_string());
+        let allowed = [output_port(0), output_port(1)];
+        let not_allowed = [output_port(0), output_port(2)];
+
+        let alice_job = job("news", schedule.clone(), Some("alice"));
+        assert!(authorize(&auth, "main", &alice_job, &allowed).is_ok());
+        // Like a salvo changed after the job was saved
+        assert!(authorize(&auth, "main", &alice_job, &not_allowed).is_err());
+        assert!(authorize(&auth, "other", &alice_job, &allowed).is_err());
+
+        let bob_job = job("news", schedule.clone(), Some("bob"));
+        assert!(authorize(&auth, "main", &bob_job, &allowed).is_err());
+        let carol_job = job("news", schedule.clone(), Some("carol"));
+        assert!(authorize(&auth, "main", &carol_job, &not_allowed).is_ok());
+        let removed_user_job = job("news", schedule.clone(), Some("dave"));
+        assert!(authorize(&auth, "main", &removed_user_job, &allowed).is_err());
+        let no_owner_job = job("news", schedule.clone(), None);
+        assert!(authorize(&auth, "main", &no_owner_job, &allowed).is_err());
+
+        // Everybody is an admin without authentication
+        let auth = Auth::new(None);
+        let anonymous_job = job("news", schedule, Some("anonymous"));
+        assert!(authorize(&auth, "main", &anonymous_job, &not_allowed).is_ok());
+    }
+}